@@ -0,0 +1,107 @@
+//! Derive macro for the key extractors of [actix-governor](https://docs.rs/actix-governor).
+//!
+//! Use it through the `derive` feature of actix-governor, which re-exports it.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{parse_macro_input, DeriveInput, Error, LitStr};
+
+/// Where the key is read from.
+enum Source {
+    Header(LitStr),
+    Cookie(LitStr),
+}
+
+/// Implement `KeyExtractor` for a type that reads its key from a header or a cookie.
+///
+/// ```rust,ignore
+/// use actix_governor::KeyExtractor;
+///
+/// #[derive(Clone, KeyExtractor)]
+/// #[key(header = "x-api-key")]
+/// struct ApiKey;
+///
+/// #[derive(Clone, KeyExtractor)]
+/// #[key(cookie = "session")]
+/// struct Session;
+/// ```
+///
+/// The key is the value as `String`. Requests without the header or cookie, or with an empty value,
+/// are rejected with `401 Unauthorized`.
+#[proc_macro_derive(KeyExtractor, attributes(key))]
+pub fn derive_key_extractor(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(input: DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let source = parse_source(&input)?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let (name, missing, extract) = match &source {
+        Source::Header(header) => (
+            format!("header {}", header.value()),
+            format!("Missing {} header", header.value()),
+            quote!(::actix_governor::__private::header_key(req, #header)),
+        ),
+        Source::Cookie(cookie) => (
+            format!("cookie {}", cookie.value()),
+            format!("Missing {} cookie", cookie.value()),
+            quote!(::actix_governor::__private::cookie_key(req, #cookie)),
+        ),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::actix_governor::KeyExtractor for #ident #ty_generics #where_clause {
+            type Key = ::std::string::String;
+            type KeyExtractionError = &'static str;
+
+            ::actix_governor::__private_log_methods!(#name);
+
+            fn extract(
+                &self,
+                req: &::actix_governor::__private::ServiceRequest,
+            ) -> ::std::result::Result<Self::Key, Self::KeyExtractionError> {
+                #extract.ok_or(#missing)
+            }
+        }
+    })
+}
+
+fn parse_source(input: &DeriveInput) -> Result<Source, Error> {
+    let mut source = None;
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("key"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let value: LitStr = meta.value()?.parse()?;
+            if value.value().is_empty() {
+                return Err(meta.error("the name must not be empty"));
+            }
+            if source.is_some() {
+                return Err(meta.error("only one source of the key can be given"));
+            }
+            if meta.path.is_ident("header") {
+                source = Some(Source::Header(value));
+            } else if meta.path.is_ident("cookie") {
+                source = Some(Source::Cookie(value));
+            } else {
+                return Err(meta.error("expected `header` or `cookie`"));
+            }
+            Ok(())
+        })?;
+    }
+    source.ok_or_else(|| {
+        Error::new(
+            Span::call_site(),
+            "expected `#[key(header = \"...\")]` or `#[key(cookie = \"...\")]`",
+        )
+    })
+}
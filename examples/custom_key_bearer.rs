@@ -20,12 +20,12 @@ impl KeyExtractor for UserToken {
             .get("Authorization")
             .and_then(|token| token.to_str().ok())
             .and_then(|token| token.strip_prefix("Bearer "))
-            .and_then(|token| Some(token.trim().to_owned()))
+            .map(|token| token.trim().to_owned())
             .ok_or("You don't have permission to access")
     }
 
     #[cfg(feature = "log")]
-    fn key_name(&self, key: &Self::Key) -> Option<String> {
+    fn key_name(&self, _key: &Self::Key) -> Option<String> {
         Some("String".to_owned())
     }
 }
@@ -0,0 +1,75 @@
+use actix_governor::{Governor, GovernorConfigBuilder, KeyExtractor};
+use actix_web::dev::ServiceRequest;
+use actix_web::{web, App, HttpServer, Responder};
+use std::collections::HashMap;
+
+/// Maps the API keys to the tenants they belong to.
+struct Tenants(HashMap<String, String>);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct TenantKey;
+
+impl KeyExtractor for TenantKey {
+    type Key = String;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "tenant"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        // The data registered on the app is available to the key extractor
+        let tenants = req
+            .app_data::<web::Data<Tenants>>()
+            .ok_or("The tenants are not registered")?;
+        let api_key = req
+            .headers()
+            .get("x-api-key")
+            .and_then(|api_key| api_key.to_str().ok())
+            .ok_or("Missing API key")?;
+        // All API keys of a tenant share one quota
+        tenants.0.get(api_key).cloned().ok_or("Unknown API key")
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.clone())
+    }
+}
+
+async fn index() -> impl Responder {
+    "Hello world!"
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let tenants = web::Data::new(Tenants(HashMap::from([
+        ("key-1".to_owned(), "acme".to_owned()),
+        ("key-2".to_owned(), "acme".to_owned()),
+        ("key-3".to_owned(), "globex".to_owned()),
+    ])));
+
+    // Allow bursts with up to five requests per tenant
+    // and replenishes one element every two seconds
+    let governor_conf = GovernorConfigBuilder::default()
+        .per_second(2)
+        .burst_size(5)
+        .key_extractor(TenantKey)
+        .use_headers()
+        .finish()
+        .unwrap();
+
+    HttpServer::new(move || {
+        App::new()
+            // Register the tenants, so the key extractor can use them
+            .app_data(tenants.clone())
+            // Enable Governor middleware
+            .wrap(Governor::new(&governor_conf))
+            // Route hello world service
+            .route("/", web::get().to(index))
+    })
+    .bind("127.0.0.1:8080")?
+    .run()
+    .await
+}
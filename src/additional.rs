@@ -0,0 +1,123 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use governor::{clock::QuantaInstant, middleware::RateLimitingMiddleware};
+
+use crate::refund::Refunds;
+use crate::{QuotaOverride, SharedRateLimiter};
+
+/// The limiters of the [additional quotas](crate::GovernorConfigBuilder::additional_quota())
+/// and the keys they denied.
+///
+/// After an additional quota denied a key, no request of the key can pass it until the denial
+/// ends, so the requests of the key are denied right away with the time left. This reports the
+/// wait of the most restrictive quota even if another quota would deny the request first, and
+/// the requests don't use up the other quotas of the key in the meantime.
+///
+/// Like the quota of the key, every additional quota keeps the requests that were given back,
+/// e.g. after a denial by the global quota, as credits of the key.
+#[derive(Debug)]
+pub(crate) struct AdditionalQuotas<Key: Clone + Hash + Eq, M: RateLimitingMiddleware<QuantaInstant>>
+{
+    limiters: Vec<SharedRateLimiter<Key, M>>,
+    /// The requests given back to each quota.
+    credits: Vec<Refunds<Key>>,
+    /// The end of the longest denial of each key and the burst size of the quota that denied it.
+    denied: Mutex<Denied<Key>>,
+    /// The number of denied keys, so requests don't lock if there are none.
+    active: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Denied<Key> {
+    keys: HashMap<Key, (Instant, u32)>,
+    last_pruned: Instant,
+}
+
+/// How often the denials that ended are forgotten.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+impl<Key: Clone + Hash + Eq, M: RateLimitingMiddleware<QuantaInstant>> AdditionalQuotas<Key, M> {
+    pub(crate) fn new(quotas: Vec<(QuotaOverride, SharedRateLimiter<Key, M>)>) -> Self {
+        let (credits, limiters) = quotas
+            .into_iter()
+            .map(|(quota, limiter)| {
+                let credits =
+                    Refunds::new(Vec::new(), None, false, quota.period(), quota.burst_size());
+                (credits, limiter)
+            })
+            .unzip();
+        AdditionalQuotas {
+            limiters,
+            credits,
+            denied: Mutex::new(Denied {
+                keys: HashMap::new(),
+                last_pruned: Instant::now(),
+            }),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn limiters(&self) -> &[SharedRateLimiter<Key, M>] {
+        &self.limiters
+    }
+
+    /// Use `n` requests given back to the quota with the index for the key,
+    /// see [`Refunds::take_credits`].
+    pub(crate) fn take_credits(&self, index: usize, key: &Key, n: u32) -> bool {
+        self.credits[index].take_credits(key, None, n)
+    }
+
+    /// Give back a request with the cost to every quota of the key.
+    pub(crate) fn refund(&self, key: &Key, cost: u32) {
+        for credits in &self.credits {
+            credits.refund(key.clone(), None, cost);
+        }
+    }
+
+    /// The time until the key can pass all quotas that denied it and the burst size
+    /// of the quota with the longest denial, if any denial of the key didn't end yet.
+    pub(crate) fn denied(&self, key: &Key) -> Option<(Duration, u32)> {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let now = Instant::now();
+        let mut denied = self.denied.lock().unwrap();
+        let &(until, burst_size) = denied.keys.get(key)?;
+        if until <= now {
+            denied.keys.remove(key);
+            self.active.store(denied.keys.len(), Ordering::Relaxed);
+            return None;
+        }
+        Some((until - now, burst_size))
+    }
+
+    /// Remember that a quota denied a single request of the key for `wait`.
+    ///
+    /// Only denials of single requests are remembered, more expensive requests
+    /// wait longer than cheaper ones would.
+    pub(crate) fn deny(&self, key: &Key, wait: Duration, burst_size: u32) {
+        let now = Instant::now();
+        let until = now + wait;
+        let mut denied = self.denied.lock().unwrap();
+        if now.duration_since(denied.last_pruned) >= PRUNE_INTERVAL {
+            denied.keys.retain(|_, (until, _)| *until > now);
+            denied.last_pruned = now;
+        }
+        match denied.keys.get_mut(key) {
+            Some(longest) if longest.0 >= until => {}
+            Some(longest) => *longest = (until, burst_size),
+            None => {
+                denied.keys.insert(key.clone(), (until, burst_size));
+            }
+        }
+        self.active.store(denied.keys.len(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::refund::Refunds;
+use crate::{GovernorConfig, KeyExtractor, RateLimitInfo};
+
+use governor::{
+    clock::{Clock, DefaultClock},
+    NegativeMultiDecision,
+};
+
+use std::{
+    fmt,
+    hash::Hash,
+    num::NonZeroU32,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Error of drawing from the quota of a key outside of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetError {
+    /// The quota of the key is used up. Try again after the given time.
+    Exhausted(Duration),
+    /// The cost is larger than the burst size, so it never fits into the quota.
+    InsufficientCapacity(u32),
+}
+
+impl fmt::Display for BudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetError::Exhausted(wait_time) => write!(
+                f,
+                "the quota is used up, retry after {}ms",
+                wait_time.as_millis()
+            ),
+            BudgetError::InsufficientCapacity(burst_size) => {
+                write!(f, "the cost exceeds the burst size of {burst_size}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BudgetError {}
+
+impl<K: KeyExtractor, M: RateLimitInfo> GovernorConfig<K, M> {
+    /// Draw `cost` requests from the quota of the key, outside of a request.
+    ///
+    /// The base quota is shared with the [`Governor`](crate::Governor) middleware created from this
+    /// configuration, so background work like cron jobs or queue workers can count against
+    /// the same per-key budget as the HTTP requests of the key:
+    ///
+    /// ```rust
+    /// use actix_governor::{GlobalKeyExtractor, GovernorConfigBuilder};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .key_extractor(GlobalKeyExtractor)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // A batch job that is worth three requests
+    /// if config.consume(&(), 3).is_ok() {
+    ///     // Do the work
+    /// }
+    /// ```
+    ///
+    /// Either all of the cost is drawn or nothing. Refunds, cold start and slow start
+    /// only apply to requests.
+    ///
+    /// Only the quota set with [`period`](crate::GovernorConfigBuilder::period()) and
+    /// [`burst_size`](crate::GovernorConfigBuilder::burst_size()) is drawn from. Quotas that
+    /// replace it for a request, like [`key_quota`](crate::GovernorConfigBuilder::key_quota()),
+    /// rules, scopes or a reloaded quota, and the additional and global quotas don't apply,
+    /// so requests of keys with such a quota don't share it with the background work.
+    pub fn consume(&self, key: &K::Key, cost: u32) -> Result<(), BudgetError> {
+        let cost = match NonZeroU32::new(cost) {
+            Some(cost) => cost,
+            None => return Ok(()),
+        };
+        match self.state.limiter.check_key_n(key, cost) {
+            Ok(_) => Ok(()),
+            Err(NegativeMultiDecision::BatchNonConforming(_, negative)) => Err(
+                BudgetError::Exhausted(negative.wait_time_from(DefaultClock::default().now())),
+            ),
+            Err(NegativeMultiDecision::InsufficientCapacity(burst_size)) => {
+                Err(BudgetError::InsufficientCapacity(burst_size))
+            }
+        }
+    }
+
+    /// Reserve `n` requests of the quota of the key for a long-running operation.
+    ///
+    /// The operation can't know its final cost up front, so it reserves the maximum and gives back
+    /// what it didn't use with [`Reservation::commit`] or [`Reservation::release`]:
+    ///
+    /// ```rust
+    /// use actix_governor::{GlobalKeyExtractor, GovernorConfigBuilder};
+    /// use std::time::Duration;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .key_extractor(GlobalKeyExtractor)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// // An export of up to five pages
+    /// let reservation = config.reserve(&(), 5, Duration::from_secs(60)).unwrap();
+    /// // It only took two pages, the other three can be used by the next requests
+    /// reservation.commit(2);
+    /// ```
+    ///
+    /// The request that triggers the operation was already counted by the middleware,
+    /// so reserve the cost of the operation without that request.
+    ///
+    /// Unused requests are given back only until `ttl` has passed, because the quota
+    /// has replenished by then anyway. Given back requests are kept as credits of the key,
+    /// like [refunds](crate::GovernorConfigBuilder::free_status_codes()), so they can't exceed
+    /// the burst size and expire after the period of the quota.
+    ///
+    /// Like [`consume`](Self::consume), this only reserves from the configured base quota.
+    pub fn reserve(
+        &self,
+        key: &K::Key,
+        n: u32,
+        ttl: Duration,
+    ) -> Result<Reservation<K::Key>, BudgetError> {
+        self.consume(key, n)?;
+        Ok(Reservation {
+            refunds: self.state.refunds.clone(),
+            key: key.clone(),
+            remaining: n,
+            expires: Instant::now() + ttl,
+        })
+    }
+
+    /// Wait until the quota of the key allows another request and draw it.
+    ///
+    /// Like [`consume`](Self::consume), but for workers that should rather be slowed down than skip work.
+    /// It also only waits for the configured base quota.
+    pub async fn until_ready(&self, key: &K::Key) {
+        self.state.limiter.until_key_ready(key).await;
+    }
+}
+
+/// Requests reserved from the quota of a key, see [`GovernorConfig::reserve`].
+///
+/// Dropping the reservation releases the unused requests.
+#[derive(Debug)]
+#[must_use = "dropping a reservation releases it"]
+pub struct Reservation<Key: Clone + Hash + Eq> {
+    refunds: Arc<Refunds<Key>>,
+    key: Key,
+    remaining: u32,
+    expires: Instant,
+}
+
+impl<Key: Clone + Hash + Eq> Reservation<Key> {
+    /// The number of reserved requests that are not used yet.
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Use `used` of the reserved requests and give back the rest.
+    pub fn commit(mut self, used: u32) {
+        self.remaining = self.remaining.saturating_sub(used);
+    }
+
+    /// Give back all reserved requests.
+    pub fn release(self) {}
+}
+
+impl<Key: Clone + Hash + Eq> Drop for Reservation<Key> {
+    fn drop(&mut self) {
+        if Instant::now() < self.expires {
+            self.refunds.refund(self.key.clone(), None, self.remaining);
+        }
+    }
+}
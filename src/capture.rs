@@ -0,0 +1,191 @@
+use crate::decision::DecisionId;
+use crate::{GovernorConfig, KeyExtractor, RateLimitInfo};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// State of a request in a captured timeline.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum CaptureState {
+    /// The request was allowed, with the remaining burst capacity if it is known.
+    Allowed(Option<u32>),
+    /// The request was exempt from rate limiting.
+    Exempt,
+    /// The request was denied for the given reason.
+    Denied {
+        reason: &'static str,
+        wait_time: Option<u64>,
+        decision: DecisionId,
+    },
+    /// The request would have been denied for the given reason, but the governor is in shadow mode.
+    Shadowed {
+        reason: &'static str,
+        wait_time: Option<u64>,
+        decision: DecisionId,
+    },
+}
+
+#[derive(Debug)]
+struct Timeline {
+    capacity: usize,
+    entries: VecDeque<(SystemTime, CaptureState)>,
+}
+
+/// The timelines of the keys that are captured, see [`GovernorConfig::start_capture`].
+#[derive(Debug)]
+pub(crate) struct Captures<Key: Clone + Hash + Eq> {
+    timelines: Mutex<HashMap<Key, Timeline>>,
+    /// The number of captured keys, so requests don't lock if nothing is captured.
+    active: AtomicUsize,
+}
+
+impl<Key: Clone + Hash + Eq> Captures<Key> {
+    pub(crate) fn new() -> Self {
+        Captures {
+            timelines: Mutex::new(HashMap::new()),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add the state of a request to the timeline of the key, if it is captured.
+    pub(crate) fn record(&self, key: &Key, state: CaptureState) {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        if let Some(timeline) = self.timelines.lock().unwrap().get_mut(key) {
+            if timeline.entries.len() == timeline.capacity {
+                timeline.entries.pop_front();
+            }
+            timeline.entries.push_back((SystemTime::now(), state));
+        }
+    }
+
+    fn start(&self, key: Key, capacity: usize) {
+        let mut timelines = self.timelines.lock().unwrap();
+        let capacity = capacity.max(1);
+        timelines.insert(
+            key,
+            Timeline {
+                capacity,
+                entries: VecDeque::with_capacity(capacity),
+            },
+        );
+        self.active.store(timelines.len(), Ordering::Relaxed);
+    }
+
+    fn stop(&self, key: &Key) {
+        let mut timelines = self.timelines.lock().unwrap();
+        timelines.remove(key);
+        self.active.store(timelines.len(), Ordering::Relaxed);
+    }
+
+    fn to_json(&self, key: &Key) -> Option<String> {
+        let timelines = self.timelines.lock().unwrap();
+        let timeline = timelines.get(key)?;
+        let entries: Vec<_> = timeline
+            .entries
+            .iter()
+            .map(|(time, state)| entry_json(*time, state))
+            .collect();
+        Some(format!("[{}]", entries.join(",")))
+    }
+}
+
+fn entry_json(time: SystemTime, state: &CaptureState) -> String {
+    let timestamp = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_millis())
+        .unwrap_or_default();
+    let mut json = format!("{{\"timestamp_ms\":{timestamp}");
+    match state {
+        CaptureState::Allowed(remaining) => {
+            json.push_str(",\"state\":\"allowed\",\"remaining\":");
+            match remaining {
+                Some(remaining) => write!(json, "{remaining}").unwrap(),
+                None => json.push_str("null"),
+            }
+        }
+        CaptureState::Exempt => json.push_str(",\"state\":\"exempt\""),
+        CaptureState::Denied {
+            reason,
+            wait_time,
+            decision,
+        }
+        | CaptureState::Shadowed {
+            reason,
+            wait_time,
+            decision,
+        } => {
+            let state = match state {
+                CaptureState::Shadowed { .. } => "shadowed",
+                _ => "denied",
+            };
+            write!(
+                json,
+                ",\"state\":\"{state}\",\"reason\":\"{reason}\",\"retry_after_s\":"
+            )
+            .unwrap();
+            match wait_time {
+                Some(wait_time) => write!(json, "{wait_time}").unwrap(),
+                None => json.push_str("null"),
+            }
+            write!(json, ",\"decision_id\":\"{decision}\"").unwrap();
+        }
+    }
+    json.push('}');
+    json
+}
+
+impl<K: KeyExtractor, M: RateLimitInfo> GovernorConfig<K, M> {
+    /// Start recording the decisions of the governor middleware for the key,
+    /// for example to debug why a customer is throttled.
+    ///
+    /// Only the last `capacity` decisions are kept. Starting the capture of a key again
+    /// clears its timeline. The timeline can be read with [`capture_json`](Self::capture_json),
+    /// e.g. from an admin endpoint that has access to the configuration:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfig;
+    /// use std::net::IpAddr;
+    ///
+    /// let config = GovernorConfig::default();
+    /// let ip: IpAddr = "203.0.113.7".parse().unwrap();
+    ///
+    /// config.start_capture(ip, 100);
+    /// // ... the customer makes requests ...
+    /// let timeline = config.capture_json(&ip).unwrap();
+    /// config.stop_capture(&ip);
+    /// ```
+    ///
+    /// The timeline is shared with the [`Governor`](crate::Governor) middleware created from this
+    /// configuration and its clones.
+    pub fn start_capture(&self, key: K::Key, capacity: usize) {
+        self.state.captures.start(key, capacity);
+    }
+
+    /// Stop recording the decisions for the key and drop its timeline.
+    pub fn stop_capture(&self, key: &K::Key) {
+        self.state.captures.stop(key);
+    }
+
+    /// The captured decisions of the key as JSON array, oldest first.
+    /// Returns `None` if the key is not captured.
+    ///
+    /// Every entry has a `timestamp_ms` since the Unix epoch and a `state`,
+    /// which is `allowed` with the `remaining` burst capacity, `exempt` or `denied`
+    /// with the `reason`, `retry_after_s` and `decision_id` of the denial.
+    /// Denials in [shadow mode](crate::GovernorConfigBuilder::shadow_mode()) are `shadowed`
+    /// with the same fields.
+    /// The remaining burst capacity and the retry time are `null` if they are unknown.
+    pub fn capture_json(&self, key: &K::Key) -> Option<String> {
+        self.state.captures.to_json(key)
+    }
+}
@@ -0,0 +1,108 @@
+use crate::config_error::checked_quota;
+
+use governor::{
+    clock::{DefaultClock, QuantaInstant},
+    state::keyed::DefaultKeyedStateStore,
+    NotUntil, Quota, RateLimiter,
+};
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Stricter quota that is applied to keys which were never seen before.
+///
+/// A key stays on probation until it made requests without being denied
+/// for the whole probation period. Every denial by the cold start quota restarts
+/// the probation period. Keys that made no request for another probation period
+/// after their probation ended are forgotten, so they count as new again.
+#[derive(Debug)]
+pub(crate) struct ColdStart<Key: Clone + Hash + Eq> {
+    limiter: RateLimiter<Key, DefaultKeyedStateStore<Key>, DefaultClock>,
+    probation: Duration,
+    keys: Mutex<Probations<Key>>,
+}
+
+#[derive(Debug)]
+struct Probations<Key> {
+    keys: HashMap<Key, Probation>,
+    last_pruned: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Probation {
+    start: Instant,
+    last_seen: Instant,
+}
+
+impl<Key: Clone + Hash + Eq> ColdStart<Key> {
+    pub(crate) fn new(quota: Quota, probation: Duration) -> Self {
+        ColdStart {
+            limiter: RateLimiter::keyed(quota),
+            probation,
+            keys: Mutex::new(Probations {
+                keys: HashMap::new(),
+                last_pruned: Instant::now(),
+            }),
+        }
+    }
+
+    /// Check the key against the cold start quota if it is still on probation.
+    pub(crate) fn check_key(&self, key: &Key) -> Result<(), NotUntil<QuantaInstant>> {
+        let now = Instant::now();
+        let mut probations = self.keys.lock().unwrap();
+        if now.duration_since(probations.last_pruned) >= self.probation {
+            self.prune(&mut probations, now);
+        }
+        let probation = probations.keys.entry(key.clone()).or_insert(Probation {
+            start: now,
+            last_seen: now,
+        });
+        probation.last_seen = now;
+
+        if now.duration_since(probation.start) >= self.probation {
+            // The key behaved well for long enough, only the regular quota applies.
+            return Ok(());
+        }
+
+        self.limiter.check_key(key).inspect_err(|_| {
+            // Denied keys have to start their probation from scratch.
+            probation.start = now;
+        })
+    }
+
+    /// Forget the keys that made no request for a whole probation period
+    /// after their probation ended.
+    fn prune(&self, probations: &mut Probations<Key>, now: Instant) {
+        probations.keys.retain(|_, probation| {
+            let idle_since = probation.last_seen.max(probation.start + self.probation);
+            now.duration_since(idle_since) < self.probation
+        });
+        probations.keys.shrink_to_fit();
+        self.limiter.retain_recent();
+        probations.last_pruned = now;
+    }
+}
+
+/// Cold start settings as stored by the configuration builder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ColdStartQuota {
+    pub(crate) period: Duration,
+    pub(crate) burst_size: u32,
+    pub(crate) probation: Duration,
+}
+
+impl ColdStartQuota {
+    /// Returns `None` if either burst size, period interval or probation period are zero
+    /// or the quota is too large.
+    pub(crate) fn build<Key: Clone + Hash + Eq>(&self) -> Option<ColdStart<Key>> {
+        if self.probation.as_nanos() == 0 {
+            return None;
+        }
+        let quota = checked_quota(self.period, self.burst_size)?;
+        Some(ColdStart::new(quota, self.probation))
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+};
+
+/// Limits the number of requests per key that are processed at the same time.
+#[derive(Debug)]
+pub(crate) struct ConcurrencyLimit<Key: Clone + Hash + Eq> {
+    max_concurrency: u32,
+    in_flight: Mutex<HashMap<Key, u32>>,
+}
+
+impl<Key: Clone + Hash + Eq> ConcurrencyLimit<Key> {
+    pub(crate) fn new(max_concurrency: u32) -> Self {
+        ConcurrencyLimit {
+            max_concurrency,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn max_concurrency(&self) -> u32 {
+        self.max_concurrency
+    }
+
+    /// Try to register another in-flight request for the key.
+    ///
+    /// Returns `None` if the key already reached the concurrency limit.
+    /// The request counts as in-flight until the returned guard is dropped.
+    pub(crate) fn acquire(self: &Arc<Self>, key: &Key) -> Option<InFlightGuard<Key>> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        let count = in_flight.entry(key.clone()).or_insert(0);
+        if *count >= self.max_concurrency {
+            return None;
+        }
+        *count += 1;
+        Some(InFlightGuard {
+            limit: self.clone(),
+            key: key.clone(),
+            remaining: self.max_concurrency - *count,
+        })
+    }
+
+    fn release(&self, key: &Key) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(count) = in_flight.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(key);
+            }
+        }
+    }
+}
+
+/// Marks a request as in-flight until it is dropped.
+#[derive(Debug)]
+pub(crate) struct InFlightGuard<Key: Clone + Hash + Eq> {
+    limit: Arc<ConcurrencyLimit<Key>>,
+    key: Key,
+    remaining: u32,
+}
+
+impl<Key: Clone + Hash + Eq> InFlightGuard<Key> {
+    /// Number of requests of this key that could additionally be processed
+    /// at the moment this request was admitted.
+    pub(crate) fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    pub(crate) fn max_concurrency(&self) -> u32 {
+        self.limit.max_concurrency()
+    }
+}
+
+impl<Key: Clone + Hash + Eq> Drop for InFlightGuard<Key> {
+    fn drop(&mut self) {
+        self.limit.release(&self.key);
+    }
+}
@@ -0,0 +1,75 @@
+use governor::Quota;
+
+use std::{fmt, num::NonZeroU32, time::Duration};
+
+/// Error returned by [`try_finish`](crate::GovernorConfigBuilder::try_finish())
+/// if the configuration is invalid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GovernorConfigError {
+    /// The period after which one element of the quota is replenished is zero.
+    ZeroPeriod,
+    /// The burst size is zero.
+    ZeroBurstSize,
+    /// The time it takes to replenish the whole burst doesn't fit into 64 bits of nanoseconds.
+    Overflow,
+    /// The period, burst size or probation period of
+    /// [`cold_start`](crate::GovernorConfigBuilder::cold_start()) is zero or too large.
+    InvalidColdStart,
+    /// The period or burst size of [`pre_limit`](crate::GovernorConfigBuilder::pre_limit())
+    /// is zero or too large.
+    InvalidPreLimit,
+    /// The maximum of [`max_concurrency`](crate::GovernorConfigBuilder::max_concurrency()) is zero.
+    ZeroMaxConcurrency,
+    /// The maximum of [`max_concurrent_streams`](crate::GovernorConfigBuilder::max_concurrent_streams())
+    /// is zero.
+    ZeroMaxStreams,
+    /// The budget or period of [`streaming_budget`](crate::GovernorConfigBuilder::streaming_budget())
+    /// is zero.
+    InvalidStreamingBudget,
+    /// The budget or period of [`time_budget`](crate::GovernorConfigBuilder::time_budget()) is zero.
+    InvalidTimeBudget,
+}
+
+impl fmt::Display for GovernorConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GovernorConfigError::ZeroPeriod => f.write_str("the period must not be zero"),
+            GovernorConfigError::ZeroBurstSize => f.write_str("the burst size must not be zero"),
+            GovernorConfigError::Overflow => {
+                f.write_str("the period multiplied by the burst size is too large")
+            }
+            GovernorConfigError::InvalidColdStart => {
+                f.write_str("the cold start quota and probation must not be zero or too large")
+            }
+            GovernorConfigError::InvalidPreLimit => {
+                f.write_str("the pre-limit quota must not be zero or too large")
+            }
+            GovernorConfigError::ZeroMaxConcurrency => {
+                f.write_str("the maximum concurrency must not be zero")
+            }
+            GovernorConfigError::ZeroMaxStreams => {
+                f.write_str("the maximum of concurrent streams must not be zero")
+            }
+            GovernorConfigError::InvalidStreamingBudget => {
+                f.write_str("the streaming budget and its period must not be zero")
+            }
+            GovernorConfigError::InvalidTimeBudget => {
+                f.write_str("the time budget and its period must not be zero")
+            }
+        }
+    }
+}
+
+impl std::error::Error for GovernorConfigError {}
+
+/// The quota of `burst_size` elements that are replenished after `period` each.
+/// Returns `None` if either is zero or replenishing the whole burst takes more than
+/// 64 bits of nanoseconds.
+pub(crate) fn checked_quota(period: Duration, burst_size: u32) -> Option<Quota> {
+    let burst_period = period.as_nanos().checked_mul(u128::from(burst_size))?;
+    if burst_period > u128::from(u64::MAX) {
+        return None;
+    }
+    Some(Quota::with_period(period)?.allow_burst(NonZeroU32::new(burst_size)?))
+}
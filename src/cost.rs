@@ -0,0 +1,307 @@
+use crate::{GlobalLimiter, RateLimitInfo, SharedRateLimiter};
+
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::error::PayloadError;
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::web::Bytes;
+use actix_web::HttpMessage;
+use futures::Stream;
+use governor::{clock::QuantaInstant, NegativeMultiDecision, NotUntil};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    num::{NonZeroU32, NonZeroU64},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+/// The number of requests a single request counts as, so expensive endpoints like searches
+/// or exports use up the quota faster than cheap ones.
+///
+/// Insert it into the request extensions in an earlier middleware to charge the cost
+/// up front, or in the handler once the cost is known. The part of a cost set by the handler
+/// that wasn't charged up front is added to the cost of the next request of the key:
+///
+/// ```rust
+/// use actix_governor::RequestCost;
+/// use actix_web::{HttpMessage, HttpRequest, Responder};
+/// use std::num::NonZeroU32;
+///
+/// async fn export(req: HttpRequest) -> impl Responder {
+///     let pages = 4;
+///     req.extensions_mut()
+///         .insert(RequestCost(NonZeroU32::new(pages).unwrap()));
+///     "exported"
+/// }
+/// ```
+///
+/// Costs above the burst size use up the whole burst.
+/// See [`request_cost`](crate::GovernorConfigBuilder::request_cost()) for the costs of a configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RequestCost(pub NonZeroU32);
+
+type CostFn = dyn Fn(&ServiceRequest) -> NonZeroU32 + Send + Sync;
+
+/// Closure that selects the cost of a request.
+pub(crate) struct RequestCosts {
+    cost: Arc<CostFn>,
+    /// Charge the bytes of the body that are read beyond the cost, see [`MeteredPayload`].
+    bytes_per_request: Option<NonZeroU64>,
+}
+
+impl RequestCosts {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        RequestCosts {
+            cost: Arc::new(f),
+            bytes_per_request: None,
+        }
+    }
+
+    /// Charge every started `bytes_per_request` of the body as one request.
+    ///
+    /// The `Content-Length` is charged up front, the rest of the body when it is read.
+    pub(crate) fn per_body_size(bytes_per_request: NonZeroU64) -> Self {
+        let mut costs = Self::new(move |req| {
+            let content_length = req
+                .headers()
+                .get(CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(0);
+            cells(content_length, bytes_per_request)
+        });
+        costs.bytes_per_request = Some(bytes_per_request);
+        costs
+    }
+
+    /// Meter the body of the request if the costs depend on its size,
+    /// so the bytes that weren't charged up front become debt of the key.
+    pub(crate) fn meter_payload<Key>(
+        &self,
+        req: &mut ServiceRequest,
+        debts: &Arc<CostDebts<Key>>,
+        key: &Key,
+        charged: NonZeroU32,
+    ) where
+        Key: Clone + Hash + Eq + 'static,
+    {
+        let Some(bytes_per_request) = self.bytes_per_request else {
+            return;
+        };
+        let payload = MeteredPayload {
+            payload: req.take_payload(),
+            charge: debts.charge(key, charged),
+            bytes_per_request,
+            read: 0,
+        };
+        req.set_payload(Payload::from(
+            Box::pin(payload) as Pin<Box<dyn Stream<Item = _>>>
+        ));
+    }
+
+    /// The cost of the request, a [`RequestCost`] of an earlier middleware takes precedence.
+    pub(crate) fn cost(costs: Option<&Self>, req: &ServiceRequest) -> NonZeroU32 {
+        if let Some(RequestCost(cost)) = req.extensions().get::<RequestCost>() {
+            return *cost;
+        }
+        match costs {
+            Some(costs) => (costs.cost)(req),
+            None => NonZeroU32::MIN,
+        }
+    }
+}
+
+impl Clone for RequestCosts {
+    fn clone(&self) -> Self {
+        RequestCosts {
+            cost: self.cost.clone(),
+            bytes_per_request: self.bytes_per_request,
+        }
+    }
+}
+
+impl PartialEq for RequestCosts {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.cost, &other.cost) && self.bytes_per_request == other.bytes_per_request
+    }
+}
+
+impl Eq for RequestCosts {}
+
+impl fmt::Debug for RequestCosts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestCosts")
+    }
+}
+
+/// The number of requests `bytes` count as, at least one.
+fn cells(bytes: u64, bytes_per_request: NonZeroU64) -> NonZeroU32 {
+    let cells = bytes.div_ceil(bytes_per_request.get());
+    NonZeroU32::new(u32::try_from(cells).unwrap_or(u32::MAX)).unwrap_or(NonZeroU32::MIN)
+}
+
+/// Check `cost` requests of the key at once.
+/// A cost above the burst size is checked as the whole burst.
+pub(crate) fn check_cost<Key, M>(
+    limiter: &SharedRateLimiter<Key, M>,
+    key: &Key,
+    cost: NonZeroU32,
+) -> Result<M::PositiveOutcome, NotUntil<QuantaInstant>>
+where
+    Key: Clone + Hash + Eq,
+    M: RateLimitInfo,
+{
+    if cost == NonZeroU32::MIN {
+        return limiter.check_key(key);
+    }
+    check_n_capped(cost, |n| limiter.check_key_n(key, n))
+}
+
+/// Check `cost` requests against the global quota, like [`check_cost`].
+pub(crate) fn check_global_cost<M: RateLimitInfo>(
+    limiter: &GlobalLimiter<M>,
+    cost: NonZeroU32,
+) -> Result<M::PositiveOutcome, NotUntil<QuantaInstant>> {
+    if cost == NonZeroU32::MIN {
+        return limiter.check();
+    }
+    check_n_capped(cost, |n| limiter.check_n(n))
+}
+
+fn check_n_capped<P>(
+    mut n: NonZeroU32,
+    check_n: impl Fn(NonZeroU32) -> Result<P, NegativeMultiDecision<NotUntil<QuantaInstant>>>,
+) -> Result<P, NotUntil<QuantaInstant>> {
+    loop {
+        match check_n(n) {
+            Ok(outcome) => return Ok(outcome),
+            Err(NegativeMultiDecision::BatchNonConforming(_, negative)) => return Err(negative),
+            // The burst size always fits, so this is checked at most twice.
+            Err(NegativeMultiDecision::InsufficientCapacity(burst_size)) => {
+                n = NonZeroU32::new(burst_size).unwrap_or(NonZeroU32::MIN)
+            }
+        }
+    }
+}
+
+/// The costs that handlers raised after their request was checked, see [`RequestCost`].
+///
+/// The underlying rate limiter can only check cells up front, so the rest of a raised cost
+/// is kept as debt of the key that is added to the cost of its next request.
+#[derive(Debug)]
+pub(crate) struct CostDebts<Key: Clone + Hash + Eq> {
+    debts: Mutex<HashMap<Key, u32>>,
+    /// The number of keys with debts, so requests don't lock if there are none.
+    active: AtomicUsize,
+}
+
+impl<Key: Clone + Hash + Eq> CostDebts<Key> {
+    pub(crate) fn new() -> Self {
+        CostDebts {
+            debts: Mutex::new(HashMap::new()),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// The debt of the key.
+    pub(crate) fn debt(&self, key: &Key) -> u32 {
+        if self.active.load(Ordering::Relaxed) == 0 {
+            return 0;
+        }
+        self.debts.lock().unwrap().get(key).copied().unwrap_or(0)
+    }
+
+    /// Subtract the part of the debt of the key a request paid for.
+    /// The rest is added to the cost of the next request of the key.
+    pub(crate) fn settle(&self, key: &Key, paid: u32) {
+        let mut debts = self.debts.lock().unwrap();
+        if let Some(debt) = debts.get_mut(key) {
+            *debt = debt.saturating_sub(paid);
+            if *debt == 0 {
+                debts.remove(key);
+            }
+        }
+        self.active.store(debts.len(), Ordering::Relaxed);
+    }
+
+    fn add(&self, key: Key, n: u32) {
+        let mut debts = self.debts.lock().unwrap();
+        let debt = debts.entry(key).or_insert(0);
+        *debt = debt.saturating_add(n);
+        self.active.store(debts.len(), Ordering::Relaxed);
+    }
+
+    /// Create a charge for the request of the key, which was checked with the cost `charged`.
+    pub(crate) fn charge(self: &Arc<Self>, key: &Key, charged: NonZeroU32) -> CostCharge<Key> {
+        CostCharge {
+            debts: self.clone(),
+            key: key.clone(),
+            charged,
+        }
+    }
+}
+
+/// Charges the rest of the cost if the handler raised it.
+#[derive(Debug)]
+pub(crate) struct CostCharge<Key: Clone + Hash + Eq> {
+    debts: Arc<CostDebts<Key>>,
+    key: Key,
+    charged: NonZeroU32,
+}
+
+impl<Key: Clone + Hash + Eq> CostCharge<Key> {
+    pub(crate) fn settle<B>(self, response: &ServiceResponse<B>) {
+        let cost = match response.request().extensions().get::<RequestCost>() {
+            Some(RequestCost(cost)) => cost.get(),
+            None => return,
+        };
+        if cost > self.charged.get() {
+            self.debts.add(self.key, cost - self.charged.get());
+        }
+    }
+}
+
+/// Request body that charges the bytes that are read beyond the cost the request was
+/// checked with, e.g. chunked uploads without a `Content-Length`.
+///
+/// The excess is added to the debt of the key while the body is read, so it is paid by
+/// the next requests of the key.
+struct MeteredPayload<Key: Clone + Hash + Eq> {
+    payload: Payload,
+    charge: CostCharge<Key>,
+    bytes_per_request: NonZeroU64,
+    read: u64,
+}
+
+// No field is pinned, the payload is polled through `Pin::new`.
+impl<Key: Clone + Hash + Eq> Unpin for MeteredPayload<Key> {}
+
+impl<Key: Clone + Hash + Eq> Stream for MeteredPayload<Key> {
+    type Item = Result<Bytes, PayloadError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let poll = Pin::new(&mut this.payload).poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            this.read = this.read.saturating_add(chunk.len() as u64);
+            let cost = cells(this.read, this.bytes_per_request);
+            let charge = &mut this.charge;
+            if cost > charge.charged {
+                charge
+                    .debts
+                    .add(charge.key.clone(), cost.get() - charge.charged.get());
+                charge.charged = cost;
+            }
+        }
+        poll
+    }
+}
@@ -0,0 +1,77 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::{error, Error, HttpMessage};
+
+use std::{fmt, sync::Arc};
+
+type RenderFn<Key> = dyn Fn(&Key) -> String + Send + Sync;
+
+/// Closure that renders the key of a request for the `x-ratelimit-key` header.
+pub(crate) struct DebugKey<Key>(Arc<RenderFn<Key>>);
+
+impl<Key> DebugKey<Key> {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Key) -> String + Send + Sync + 'static,
+    {
+        DebugKey(Arc::new(f))
+    }
+
+    /// Remember the rendered key in the request, so the header can be added to its response.
+    pub(crate) fn insert(&self, req: &ServiceRequest, key: &Key) {
+        if let Ok(value) = HeaderValue::from_str(&(self.0)(key)) {
+            req.extensions_mut().insert(KeyHeaderValue(value));
+        }
+    }
+}
+
+impl<Key> Clone for DebugKey<Key> {
+    fn clone(&self) -> Self {
+        DebugKey(self.0.clone())
+    }
+}
+
+impl<Key> PartialEq for DebugKey<Key> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<Key> Eq for DebugKey<Key> {}
+
+impl<Key> fmt::Debug for DebugKey<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DebugKey")
+    }
+}
+
+/// The rendered key of a request, stored in its extensions.
+#[derive(Debug, Clone)]
+struct KeyHeaderValue(HeaderValue);
+
+const KEY_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-key");
+
+/// The rendered key of the request, if the header is enabled.
+pub(crate) fn key_header(req: &impl HttpMessage) -> Option<HeaderValue> {
+    req.extensions()
+        .get::<KeyHeaderValue>()
+        .map(|KeyHeaderValue(value)| value.clone())
+}
+
+/// Add the `x-ratelimit-key` header to the headers of an allowed response.
+pub(crate) fn apply(headers: &mut HeaderMap, key_header: Option<HeaderValue>) {
+    if let Some(value) = key_header {
+        headers.insert(KEY_HEADER, value);
+    }
+}
+
+/// Add the `x-ratelimit-key` header to the response of a denial.
+pub(crate) fn apply_to_error(error: Error, key_header: Option<HeaderValue>) -> Error {
+    let value = match key_header {
+        Some(value) => value,
+        None => return error,
+    };
+    let mut response = error.error_response();
+    response.headers_mut().insert(KEY_HEADER, value);
+    error::InternalError::from_response(error, response).into()
+}
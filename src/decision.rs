@@ -0,0 +1,47 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Identifies a single denial of the governor middleware.
+///
+/// Every denied request gets a new id. It is sent in the `x-ratelimit-decision-id` header,
+/// in the `decision_id` field of the JSON body of `429` responses, in the log messages
+/// of the denial and it is inserted into the extensions of the error response.
+/// Customers can report it, so the denial can be found in the logs.
+///
+/// The id consists of the start time of the process and a counter,
+/// so it is unique across restarts of a single process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DecisionId {
+    epoch: u64,
+    sequence: u64,
+}
+
+impl DecisionId {
+    pub(crate) fn next() -> Self {
+        static EPOCH: OnceLock<u64> = OnceLock::new();
+        static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+        let epoch = *EPOCH.get_or_init(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since_epoch| since_epoch.as_secs())
+                .unwrap_or_default()
+        });
+        DecisionId {
+            epoch,
+            sequence: SEQUENCE.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl fmt::Display for DecisionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:x}-{:x}", self.epoch, self.sequence)
+    }
+}
@@ -0,0 +1,50 @@
+use std::sync::atomic::AtomicU64;
+
+/// Level and sampling of the log messages of denials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LogSettings {
+    #[cfg(feature = "log")]
+    pub(crate) level: log::Level,
+    /// Log one in this many denials, `0` turns the log messages off.
+    pub(crate) one_in: u32,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        LogSettings {
+            #[cfg(feature = "log")]
+            level: log::Level::Info,
+            one_in: 1,
+        }
+    }
+}
+
+/// Samples the log messages of the denials of a configuration.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "log"), allow(dead_code))]
+pub(crate) struct DenialLog {
+    settings: LogSettings,
+    count: AtomicU64,
+}
+
+impl DenialLog {
+    pub(crate) fn new(settings: LogSettings) -> Self {
+        DenialLog {
+            settings,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// The level to log the next denial with, or `None` if it is not logged.
+    #[cfg(feature = "log")]
+    pub(crate) fn level(&self) -> Option<log::Level> {
+        let one_in = u64::from(self.settings.one_in);
+        if one_in == 0 {
+            return None;
+        }
+        let count = self
+            .count
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        count.is_multiple_of(one_in).then_some(self.settings.level)
+    }
+}
@@ -0,0 +1,107 @@
+use futures::future::LocalBoxFuture;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+type ResolveFn<Key> = dyn Fn(&Key) -> LocalBoxFuture<'static, Option<String>> + Send + Sync;
+
+/// Async resolver of the notes that are added to the denial bodies of a key,
+/// as stored by the configuration builder.
+pub(crate) struct NoteResolver<Key> {
+    resolve: Arc<ResolveFn<Key>>,
+    ttl: Duration,
+}
+
+impl<Key> NoteResolver<Key> {
+    pub(crate) fn new<F, Fut>(ttl: Duration, resolve: F) -> Self
+    where
+        F: Fn(&Key) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + 'static,
+    {
+        NoteResolver {
+            resolve: Arc::new(move |key| Box::pin(resolve(key))),
+            ttl,
+        }
+    }
+}
+
+impl<Key> Clone for NoteResolver<Key> {
+    fn clone(&self) -> Self {
+        NoteResolver {
+            resolve: self.resolve.clone(),
+            ttl: self.ttl,
+        }
+    }
+}
+
+impl<Key> PartialEq for NoteResolver<Key> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.resolve, &other.resolve) && self.ttl == other.ttl
+    }
+}
+
+impl<Key> Eq for NoteResolver<Key> {}
+
+impl<Key> fmt::Debug for NoteResolver<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoteResolver")
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+/// Resolves the notes of keys and caches them for the configured time.
+pub(crate) struct DenialNotes<Key: Clone + Hash + Eq> {
+    resolver: NoteResolver<Key>,
+    cache: Mutex<HashMap<Key, (Option<String>, Instant)>>,
+}
+
+impl<Key: Clone + Hash + Eq> DenialNotes<Key> {
+    pub(crate) fn new(resolver: NoteResolver<Key>) -> Self {
+        DenialNotes {
+            resolver,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the note of the key if it is cached, `None` if it has to be resolved.
+    pub(crate) fn cached(&self, key: &Key) -> Option<Option<String>> {
+        match self.cache.lock().unwrap().get(key) {
+            Some((note, resolved_at)) if resolved_at.elapsed() < self.resolver.ttl => {
+                Some(note.clone())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<Key: Clone + Hash + Eq + 'static> DenialNotes<Key> {
+    /// Resolve the note of the key and cache it.
+    pub(crate) fn resolve(self: &Arc<Self>, key: &Key) -> LocalBoxFuture<'static, Option<String>> {
+        let notes = self.clone();
+        let key = key.clone();
+        let resolve = (self.resolver.resolve)(&key);
+        Box::pin(async move {
+            let note = resolve.await;
+            let mut cache = notes.cache.lock().unwrap();
+            let ttl = notes.resolver.ttl;
+            cache.retain(|_, (_, resolved_at)| resolved_at.elapsed() < ttl);
+            cache.insert(key, (note.clone(), Instant::now()));
+            note
+        })
+    }
+}
+
+impl<Key: Clone + Hash + Eq> fmt::Debug for DenialNotes<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DenialNotes")
+            .field("resolver", &self.resolver)
+            .finish()
+    }
+}
@@ -0,0 +1,45 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header;
+
+/// Get the value of the header, if it is present and not empty.
+pub fn header_key(req: &ServiceRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+}
+
+/// Get the value of the cookie, if it is present and not empty.
+pub fn cookie_key(req: &ServiceRequest, name: &str) -> Option<String> {
+    req.headers()
+        .get_all(header::COOKIE)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(cookie_name, _)| *cookie_name == name)
+        .map(|(_, value)| value.trim_matches('"'))
+        .filter(|value| !value.is_empty())
+        .map(str::to_owned)
+}
+
+/// The methods of a derived key extractor that depend on the `log` feature of this crate.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "log")]
+macro_rules! __private_log_methods {
+    ($name:expr) => {
+        fn name(&self) -> &'static str {
+            $name
+        }
+    };
+}
+
+/// The methods of a derived key extractor that depend on the `log` feature of this crate.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "log"))]
+macro_rules! __private_log_methods {
+    ($name:expr) => {};
+}
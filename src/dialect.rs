@@ -0,0 +1,100 @@
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{HeaderMap, HeaderName};
+
+use std::{fmt, sync::Arc};
+
+/// Names of the rate limiting headers sent to a client.
+///
+/// Select it per request with
+/// [`header_dialect`](crate::GovernorConfigBuilder::header_dialect()),
+/// to move clients to new header names one at a time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HeaderDialect {
+    /// The `x-ratelimit-*` headers. This is the default.
+    Legacy,
+    /// The headers of the IETF draft: `ratelimit-limit`, `ratelimit-remaining` and `ratelimit-reset`,
+    /// as well as `retry-after` for denied requests.
+    /// The other `x-ratelimit-*` headers keep their names.
+    Ietf,
+    /// All `x-ratelimit-*` headers with `x-ratelimit` replaced by the given prefix,
+    /// e.g. `x-acme-ratelimit` for `x-acme-ratelimit-limit`.
+    Custom(String),
+}
+
+impl HeaderDialect {
+    /// Rename the legacy headers to the names of this dialect.
+    pub(crate) fn apply(&self, headers: &mut HeaderMap) {
+        match self {
+            HeaderDialect::Legacy => {}
+            HeaderDialect::Ietf => {
+                for (legacy, ietf) in [
+                    ("x-ratelimit-limit", "ratelimit-limit"),
+                    ("x-ratelimit-remaining", "ratelimit-remaining"),
+                ] {
+                    if let Some(value) = headers.remove(legacy).next() {
+                        headers.insert(HeaderName::from_static(ietf), value);
+                    }
+                }
+                if let Some(value) = headers.remove("x-ratelimit-after").next() {
+                    headers.insert(HeaderName::from_static("ratelimit-reset"), value.clone());
+                    headers.insert(HeaderName::from_static("retry-after"), value);
+                }
+            }
+            HeaderDialect::Custom(prefix) => {
+                let legacy: Vec<HeaderName> = headers
+                    .keys()
+                    .filter(|name| name.as_str().starts_with("x-ratelimit-"))
+                    .cloned()
+                    .collect();
+                for name in legacy {
+                    let suffix = &name.as_str()["x-ratelimit".len()..];
+                    let custom = match HeaderName::try_from(format!("{prefix}{suffix}")) {
+                        Ok(custom) => custom,
+                        Err(_) => continue,
+                    };
+                    for value in headers.remove(&name).collect::<Vec<_>>() {
+                        headers.append(custom.clone(), value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+type SelectFn = dyn Fn(&ServiceRequest) -> HeaderDialect + Send + Sync;
+
+/// Closure that selects the header dialect of a request.
+#[derive(Clone)]
+pub(crate) struct DialectSelector(Arc<SelectFn>);
+
+impl DialectSelector {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> HeaderDialect + Send + Sync + 'static,
+    {
+        DialectSelector(Arc::new(f))
+    }
+
+    /// Select the dialect of the request. Returns `None` for the legacy headers.
+    pub(crate) fn select(&self, req: &ServiceRequest) -> Option<HeaderDialect> {
+        match (self.0)(req) {
+            HeaderDialect::Legacy => None,
+            dialect => Some(dialect),
+        }
+    }
+}
+
+impl PartialEq for DialectSelector {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for DialectSelector {}
+
+impl fmt::Debug for DialectSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DialectSelector")
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{GovernorConfigBuilder, Method, PeerIpKeyExtractor};
+
+use governor::middleware::NoOpMiddleware;
+
+use std::{env, fmt, time::Duration};
+
+/// Error returned by [`from_env`](crate::GovernorConfigBuilder::from_env())
+/// if an environment variable can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvConfigError {
+    variable: String,
+    value: String,
+}
+
+impl EnvConfigError {
+    /// The name of the environment variable.
+    pub fn variable(&self) -> &str {
+        &self.variable
+    }
+
+    /// The value that couldn't be parsed.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl fmt::Display for EnvConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid value of {}: {:?}", self.variable, self.value)
+    }
+}
+
+impl std::error::Error for EnvConfigError {}
+
+impl GovernorConfigBuilder<PeerIpKeyExtractor, NoOpMiddleware> {
+    /// Create a configuration builder from environment variables,
+    /// so the limits can be tuned per environment without recompiling:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// // GOVERNOR_PERIOD=2s GOVERNOR_BURST_SIZE=20 GOVERNOR_METHODS=GET,POST
+    /// let config = GovernorConfigBuilder::from_env("GOVERNOR")
+    ///     .unwrap()
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// These variables are read, each prefixed with `prefix` and an underscore:
+    ///
+    /// + `PERIOD`: the interval after which one element of the quota is replenished,
+    ///   like `500ms`, `2s`, `1m`, `1h` or `1d`. A number without unit is in milliseconds.
+    /// + `BURST_SIZE`: the burst size.
+    /// + `METHODS`: the HTTP methods the configuration applies to, separated by commas.
+    /// + `MAX_CONCURRENCY`: the number of requests of a key that may be processed at the same time.
+    ///
+    /// Variables that are not set or empty keep their default value.
+    pub fn from_env(prefix: &str) -> Result<Self, EnvConfigError> {
+        let mut builder = GovernorConfigBuilder::default();
+        if let Some(period) = var(prefix, "PERIOD", parse_duration)? {
+            builder.period(period);
+        }
+        if let Some(burst_size) = var(prefix, "BURST_SIZE", |value| value.parse().ok())? {
+            builder.burst_size(burst_size);
+        }
+        if let Some(methods) = var(prefix, "METHODS", parse_methods)? {
+            builder.methods(methods);
+        }
+        if let Some(max_concurrency) = var(prefix, "MAX_CONCURRENCY", |value| value.parse().ok())? {
+            builder.max_concurrency(max_concurrency);
+        }
+        Ok(builder)
+    }
+}
+
+/// Read and parse the variable `{prefix}_{name}`.
+fn var<T>(
+    prefix: &str,
+    name: &str,
+    parse: impl FnOnce(&str) -> Option<T>,
+) -> Result<Option<T>, EnvConfigError> {
+    let variable = format!("{prefix}_{name}");
+    let value = match env::var(&variable) {
+        Ok(value) => value,
+        Err(env::VarError::NotPresent) => return Ok(None),
+        Err(env::VarError::NotUnicode(value)) => {
+            return Err(EnvConfigError {
+                variable,
+                value: value.to_string_lossy().into_owned(),
+            })
+        }
+    };
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(None);
+    }
+    match parse(trimmed) {
+        Some(parsed) => Ok(Some(parsed)),
+        None => Err(EnvConfigError { variable, value }),
+    }
+}
+
+pub(crate) fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: u64 = number.parse().ok()?;
+    let seconds = match unit.trim() {
+        "" | "ms" => return Some(Duration::from_millis(number)),
+        "ns" => return Some(Duration::from_nanos(number)),
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(number.checked_mul(seconds)?))
+}
+
+fn parse_methods(value: &str) -> Option<Vec<Method>> {
+    value
+        .split(',')
+        .map(|method| Method::from_bytes(method.trim().to_uppercase().as_bytes()).ok())
+        .collect()
+}
@@ -0,0 +1,164 @@
+use actix_web::http::header::HeaderName;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signed, time-limited tokens that exempt requests from rate limiting.
+///
+/// Tokens are issued with a secret that only the server knows and handed out to trusted partners,
+/// which send them in the header configured with
+/// [`exemption_tokens`](crate::GovernorConfigBuilder::exemption_tokens()).
+/// A token has the form `<expiry>.<nonce>.<signature>`, where the expiry is a unix timestamp
+/// and the signature is the hex encoded HMAC-SHA256 of `<expiry>.<nonce>`.
+///
+/// To prevent replays by other clients, a token is bound to the first key that presents it
+/// and rejected for all other keys. Use a different nonce for every token you issue.
+///
+/// ```rust
+/// use actix_governor::ExemptionTokens;
+/// use std::time::{Duration, SystemTime};
+///
+/// let tokens = ExemptionTokens::new("server secret");
+/// let token = tokens.issue("partner-42", SystemTime::now() + Duration::from_secs(3600));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExemptionTokens {
+    secret: Arc<[u8]>,
+}
+
+impl ExemptionTokens {
+    /// Create tokens signed with the given secret.
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        ExemptionTokens {
+            secret: secret.into().into(),
+        }
+    }
+
+    /// Issue a token that is valid until `expires`.
+    ///
+    /// **The nonce must not contain a `.`.**
+    pub fn issue(&self, nonce: &str, expires: SystemTime) -> String {
+        let expires = expires
+            .duration_since(UNIX_EPOCH)
+            .map(|expires| expires.as_secs())
+            .unwrap_or(0);
+        let payload = format!("{expires}.{nonce}");
+        let signature: String = self
+            .mac(&payload)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("{payload}.{signature}")
+    }
+
+    fn mac(&self, payload: &str) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.secret).expect("HMAC takes keys of any size");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    /// Check the signature and expiry of the token and return its nonce and expiry.
+    fn verify<'a>(&self, token: &'a str, now: u64) -> Result<(&'a str, u64), ExemptionError> {
+        let (payload, signature) = token.rsplit_once('.').ok_or(ExemptionError::Malformed)?;
+        let (expires, nonce) = payload.split_once('.').ok_or(ExemptionError::Malformed)?;
+        let expires: u64 = expires.parse().map_err(|_| ExemptionError::Malformed)?;
+        let signature = decode_hex(signature).ok_or(ExemptionError::Malformed)?;
+        self.mac(payload)
+            .verify_slice(&signature)
+            .map_err(|_| ExemptionError::InvalidSignature)?;
+        if expires <= now {
+            return Err(ExemptionError::Expired);
+        }
+        Ok((nonce, expires))
+    }
+}
+
+impl fmt::Debug for ExemptionTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExemptionTokens")
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Reason why an exemption token was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ExemptionError {
+    Malformed,
+    InvalidSignature,
+    Expired,
+    Replayed,
+}
+
+impl fmt::Display for ExemptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExemptionError::Malformed => f.write_str("malformed"),
+            ExemptionError::InvalidSignature => f.write_str("invalid signature"),
+            ExemptionError::Expired => f.write_str("expired"),
+            ExemptionError::Replayed => f.write_str("presented by another key"),
+        }
+    }
+}
+
+/// Verifies the exemption tokens of requests and remembers which key used them.
+#[derive(Debug)]
+pub(crate) struct Exemptions<Key: Clone + Hash + Eq> {
+    header_name: HeaderName,
+    tokens: ExemptionTokens,
+    used: Mutex<HashMap<String, (Key, u64)>>,
+}
+
+impl<Key: Clone + Hash + Eq> Exemptions<Key> {
+    pub(crate) fn new(header_name: HeaderName, tokens: ExemptionTokens) -> Self {
+        Exemptions {
+            header_name,
+            tokens,
+            used: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn header_name(&self) -> &HeaderName {
+        &self.header_name
+    }
+
+    /// Check whether the token exempts the request of the key.
+    pub(crate) fn check(&self, token: &str, key: &Key) -> Result<(), ExemptionError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or(0);
+        let (nonce, expires) = self.tokens.verify(token, now)?;
+
+        let mut used = self.used.lock().unwrap();
+        match used.get(nonce) {
+            Some((first_key, _)) if first_key != key => Err(ExemptionError::Replayed),
+            Some(_) => Ok(()),
+            None => {
+                used.retain(|_, (_, expires)| *expires > now);
+                used.insert(nonce.to_owned(), (key.clone(), expires));
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+/// Blocks single keys of a [`Governor`](crate::Governor) for scheduled time windows,
+/// without changing the configuration or restarting the server.
+///
+/// Requests of a key are denied with `403 Forbidden` and the message of the block while one of
+/// its blocks is active, even if they present an exemption token. This suspends an abusive
+/// integration or a tenant during its maintenance window without affecting the other keys.
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfig, KeyBlocks};
+/// use actix_web::{web, App, Responder};
+/// use std::net::IpAddr;
+/// use std::time::{Duration, SystemTime};
+///
+/// async fn index() -> impl Responder {
+///     "Hello world!"
+/// }
+///
+/// let config = GovernorConfig::default();
+/// let blocks = KeyBlocks::<IpAddr>::new();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&config).with_key_blocks(blocks.clone()))
+///     .route("/", web::get().to(index));
+///
+/// // Later, for example when an integration misbehaves
+/// let now = SystemTime::now();
+/// blocks.schedule(
+///     "203.0.113.7".parse().unwrap(),
+///     now,
+///     now + Duration::from_secs(3600),
+///     "Suspended for an hour, please contact support",
+/// );
+/// ```
+///
+/// All clones of a handle share the same blocks.
+pub struct KeyBlocks<Key>(Arc<RwLock<HashMap<Key, Vec<KeyBlock>>>>);
+
+/// A block of a key from `from` until `to`.
+#[derive(Debug, Clone)]
+struct KeyBlock {
+    from: SystemTime,
+    to: SystemTime,
+    message: Arc<str>,
+}
+
+impl KeyBlock {
+    fn is_active(&self, now: SystemTime) -> bool {
+        self.from <= now && now < self.to
+    }
+}
+
+impl<Key: Clone + Hash + Eq> KeyBlocks<Key> {
+    /// Create a handle without any blocks.
+    pub fn new() -> Self {
+        KeyBlocks(Arc::new(RwLock::new(HashMap::new())))
+    }
+
+    /// Block the key from `from` until `to` and deny its requests with `message`.
+    ///
+    /// Blocks of the same key can overlap, the block that started first wins.
+    /// Blocks that ended already are forgotten.
+    pub fn schedule(&self, key: Key, from: SystemTime, to: SystemTime, message: impl Into<String>) {
+        let now = SystemTime::now();
+        let mut blocks = self.0.write().unwrap();
+        blocks.retain(|_, blocks| {
+            blocks.retain(|block| block.to > now);
+            !blocks.is_empty()
+        });
+        if to <= from || to <= now {
+            return;
+        }
+        let blocks = blocks.entry(key).or_default();
+        blocks.push(KeyBlock {
+            from,
+            to,
+            message: message.into().into(),
+        });
+        blocks.sort_by_key(|block| block.from);
+    }
+
+    /// Remove all blocks of the key, including the scheduled ones.
+    pub fn lift(&self, key: &Key) {
+        self.0.write().unwrap().remove(key);
+    }
+
+    /// Whether the key is blocked at the moment.
+    pub fn is_blocked(&self, key: &Key) -> bool {
+        self.active(key).is_some()
+    }
+
+    /// The message of the active block of the key, if any.
+    pub(crate) fn active(&self, key: &Key) -> Option<Arc<str>> {
+        let blocks = self.0.read().unwrap();
+        if blocks.is_empty() {
+            return None;
+        }
+        let now = SystemTime::now();
+        blocks
+            .get(key)?
+            .iter()
+            .find(|block| block.is_active(now))
+            .map(|block| block.message.clone())
+    }
+}
+
+impl<Key: Clone + Hash + Eq> Default for KeyBlocks<Key> {
+    fn default() -> Self {
+        KeyBlocks::new()
+    }
+}
+
+impl<Key> Clone for KeyBlocks<Key> {
+    fn clone(&self) -> Self {
+        KeyBlocks(self.0.clone())
+    }
+}
+
+impl<Key> fmt::Debug for KeyBlocks<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyBlocks")
+            .field("keys", &self.0.read().unwrap().len())
+            .finish()
+    }
+}
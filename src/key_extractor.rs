@@ -1,6 +1,25 @@
-use std::{fmt::Display, hash::Hash, net::IpAddr};
+use std::{
+    any::Any,
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    fmt::{self, Display},
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use actix_web::dev::ServiceRequest;
+use actix_web::dev::{Extensions, ServiceRequest};
+use actix_web::http::header::{self, HeaderName};
+use actix_web::HttpMessage;
+#[cfg(feature = "jwt")]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::proxies::{IpNet, TrustedProxies};
+use crate::QuotaOverride;
 
 /// Generic structure of what is needed to extract a rate-limiting key from an incoming request.
 pub trait KeyExtractor: Clone {
@@ -15,6 +34,15 @@ pub trait KeyExtractor: Clone {
     fn name(&self) -> &'static str;
 
     /// Extraction method
+    ///
+    /// The request gives access to the application state: the data registered with
+    /// [`App::app_data`](actix_web::App::app_data) is available through
+    /// [`ServiceRequest::app_data`], for example a table of tenants or the configuration,
+    /// and the [`HttpRequest`](actix_web::HttpRequest) through [`ServiceRequest::request`].
+    /// Data registered on a scope or resource is available to a governor that wraps
+    /// this scope or resource, but not to one that wraps the whole app.
+    /// See the [custom_key_tenant](https://github.com/AaronErhardt/actix-governor/blob/main/examples/custom_key_tenant.rs)
+    /// example.
     fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError>;
 
     #[cfg(feature = "log")]
@@ -33,8 +61,11 @@ pub trait KeyExtractor: Clone {
 /// In this case, rate limiting will be applied to _all_ incoming requests as if they were from the same user.
 ///
 /// If this is not the behavior you want, you may:
-/// - implement your own [KeyExtractor] that tries to get IP from the `Forwarded` or `X-Forwarded-For` headers that most reverse proxies set
+/// - use [SmartIpKeyExtractor] or implement your own [KeyExtractor] that tries to get IP from the `Forwarded` or `X-Forwarded-For` headers that most reverse proxies set
 /// - make absolutely sure that you only trust these headers when the peer IP is the IP of your reverse proxy (otherwise any user could set them to fake its IP)
+///
+/// Requests without a peer address, like requests over a unix socket, are rejected.
+/// Use [`when_missing`](PeerIpKeyExtractor::when_missing) to limit or allow them instead.
 pub struct PeerIpKeyExtractor;
 
 impl KeyExtractor for PeerIpKeyExtractor {
@@ -58,6 +89,360 @@ impl KeyExtractor for PeerIpKeyExtractor {
     }
 }
 
+impl PeerIpKeyExtractor {
+    /// Set what happens with requests without a peer address, like requests over a unix socket
+    /// or test requests. By default they are rejected.
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, MissingPeer, PeerIpKeyExtractor};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .key_extractor(PeerIpKeyExtractor.when_missing(MissingPeer::Shared))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn when_missing(self, missing: MissingPeer) -> MissingPeerKeyExtractor {
+        MissingPeerKeyExtractor { missing }
+    }
+}
+
+/// What [PeerIpKeyExtractor] does with requests without a peer address,
+/// see [`PeerIpKeyExtractor::when_missing`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingPeer {
+    /// Reject the request. This is what [PeerIpKeyExtractor] does by default.
+    Reject,
+    /// Limit all requests without a peer address together, with the unspecified address `0.0.0.0` as key.
+    Shared,
+    /// Use the given address as key, e.g. `127.0.0.1` to limit requests over a unix socket
+    /// together with the local clients.
+    Sentinel(IpAddr),
+    /// Don't limit requests without a peer address.
+    Allow,
+}
+
+/// What a key extractor decided about a request besides its key.
+///
+/// The governor takes it out of the request extensions right after the extraction,
+/// so it only applies to the governor whose key extractor made the decision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KeyDecision {
+    /// Don't limit the request.
+    Unlimited,
+    /// Check the request against this quota, like a quota of [`key_quota`](crate::GovernorConfigBuilder::key_quota()).
+    Quota(QuotaOverride),
+    /// Deny the request with `403 Forbidden` and this message.
+    Blocked(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [PeerIpKeyExtractor] with a behavior for requests without a peer address,
+/// created by [`PeerIpKeyExtractor::when_missing`].
+pub struct MissingPeerKeyExtractor {
+    missing: MissingPeer,
+}
+
+impl KeyExtractor for MissingPeerKeyExtractor {
+    type Key = IpAddr;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "peer IP"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        if let Some(socket) = req.peer_addr() {
+            return Ok(socket.ip());
+        }
+        match self.missing {
+            MissingPeer::Reject => Err("Could not extract peer IP address from request"),
+            MissingPeer::Shared => Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            MissingPeer::Sentinel(ip) => Ok(ip),
+            MissingPeer::Allow => {
+                req.extensions_mut().insert(KeyDecision::Unlimited);
+                Ok(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+            }
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the subnet of the peer IP as key.
+///
+/// Clients that rotate their address within one allocation, like a `/64` of IPv6 addresses,
+/// would get a new bucket for every address with [PeerIpKeyExtractor].
+/// With this key extractor all addresses of a subnet share one bucket.
+/// By default these are `/24` subnets for IPv4 and `/64` subnets for IPv6:
+///
+/// ```rust
+/// use actix_governor::{GovernorConfigBuilder, IpSubnetKeyExtractor};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(IpSubnetKeyExtractor::new(24, 56))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// The key is the network address of the subnet. The same warning as for [PeerIpKeyExtractor] applies
+/// behind a reverse proxy.
+pub struct IpSubnetKeyExtractor {
+    v4_prefix: u8,
+    v6_prefix: u8,
+}
+
+impl IpSubnetKeyExtractor {
+    /// Use subnets with the given prefix lengths. They are capped at 32 and 128 bits.
+    pub fn new(v4_prefix: u8, v6_prefix: u8) -> Self {
+        IpSubnetKeyExtractor {
+            v4_prefix: v4_prefix.min(32),
+            v6_prefix: v6_prefix.min(128),
+        }
+    }
+}
+
+impl Default for IpSubnetKeyExtractor {
+    /// Use `/24` subnets for IPv4 and `/64` subnets for IPv6.
+    fn default() -> Self {
+        IpSubnetKeyExtractor::new(24, 64)
+    }
+}
+
+impl KeyExtractor for IpSubnetKeyExtractor {
+    type Key = IpAddr;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "peer IP subnet"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        req.peer_addr()
+            .map(|socket| subnet(socket.ip(), self.v4_prefix, self.v6_prefix))
+            .ok_or("Could not extract peer IP address from request")
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        let prefix = match key {
+            IpAddr::V4(_) => self.v4_prefix,
+            IpAddr::V6(_) => self.v6_prefix,
+        };
+        Some(format!("{key}/{prefix}"))
+    }
+}
+
+/// Get the network address of the subnet of the IP with the given prefix lengths.
+pub(crate) fn subnet(ip: IpAddr, v4_prefix: u8, v6_prefix: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let mask = u32::MAX.checked_shl(32 - u32::from(v4_prefix)).unwrap_or(0);
+            IpAddr::V4(Ipv4Addr::from(u32::from(ip) & mask))
+        }
+        IpAddr::V6(ip) => {
+            let mask = u128::MAX
+                .checked_shl(128 - u32::from(v6_prefix))
+                .unwrap_or(0);
+            IpAddr::V6(Ipv6Addr::from(u128::from(ip) & mask))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the client IP reported by a reverse proxy as key.
+///
+/// The IP is taken from the first of these headers that contains a valid one:
+/// - `X-Forwarded-For` (the leftmost address)
+/// - `X-Real-IP`
+/// - `Forwarded` (the `for` parameter of the first element)
+///
+/// If none of them is set, the peer IP is used like [PeerIpKeyExtractor] does.
+///
+/// **Warning:** clients can set these headers themselves. Only use this key extractor
+/// if your app can't be reached without going through a reverse proxy that overwrites them,
+/// otherwise any client can pick its own key and circumvent the rate limit.
+///
+/// Configure the networks of your proxies with [`trusted`](SmartIpKeyExtractor::trusted)
+/// to make it safe for apps that can be reached directly as well.
+pub struct SmartIpKeyExtractor;
+
+impl SmartIpKeyExtractor {
+    /// Only use the forwarded headers if the peer is one of the reverse proxies in `proxies`,
+    /// so clients that reach the app directly can't pick their own key. Behind a chain of proxies
+    /// the client IP is the rightmost forwarded address that is not a trusted proxy:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, SmartIpKeyExtractor};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .key_extractor(SmartIpKeyExtractor::trusted([
+    ///         "10.0.0.0/8".parse().unwrap(),
+    ///         "fd00::/8".parse().unwrap(),
+    ///     ]))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn trusted<I>(proxies: I) -> TrustedIpKeyExtractor
+    where
+        I: IntoIterator<Item = IpNet>,
+    {
+        TrustedIpKeyExtractor {
+            proxies: TrustedProxies::new(proxies.into_iter().collect()),
+        }
+    }
+}
+
+impl KeyExtractor for SmartIpKeyExtractor {
+    type Key = IpAddr;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "smart IP"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let headers = req.headers();
+        headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .and_then(parse_ip)
+            .or_else(|| {
+                headers
+                    .get("x-real-ip")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(parse_ip)
+            })
+            .or_else(|| {
+                headers
+                    .get("forwarded")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(forwarded_for)
+            })
+            .or_else(|| req.peer_addr().map(|socket| socket.ip()))
+            .ok_or("Could not extract client IP address from request")
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A [SmartIpKeyExtractor] that only trusts the forwarded headers of some reverse proxies,
+/// created by [`SmartIpKeyExtractor::trusted`].
+pub struct TrustedIpKeyExtractor {
+    proxies: TrustedProxies,
+}
+
+impl KeyExtractor for TrustedIpKeyExtractor {
+    type Key = IpAddr;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "trusted IP"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        trusted_client_ip(req, &self.proxies)
+            .ok_or("Could not extract client IP address from request")
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.to_string())
+    }
+}
+
+/// Get the `for` parameter of the first element of a `Forwarded` header.
+fn forwarded_for(value: &str) -> Option<IpAddr> {
+    forwarded_element_for(value.split(',').next()?)
+}
+
+/// Get the `for` parameter of an element of a `Forwarded` header.
+fn forwarded_element_for(element: &str) -> Option<IpAddr> {
+    element
+        .split(';')
+        .find_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("for").then_some(value)
+        })
+        .and_then(parse_ip)
+}
+
+/// Get the client IP of a request that may have passed trusted proxies.
+///
+/// The forwarded headers are only used if the peer is a trusted proxy. Their addresses are
+/// then checked from right to left, and the first one that is not a trusted proxy is the client.
+/// Invalid addresses end the search, because the proxy that added them can't be trusted.
+fn trusted_client_ip(req: &ServiceRequest, proxies: &TrustedProxies) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !proxies.is_trusted(peer) {
+        return Some(peer);
+    }
+
+    let headers = req.headers();
+    let values = |name: &str| -> Vec<String> {
+        headers
+            .get_all(name)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::to_owned)
+            .collect()
+    };
+    let mut chain: Vec<Option<IpAddr>> = values("x-forwarded-for")
+        .iter()
+        .map(|value| parse_ip(value))
+        .collect();
+    if chain.is_empty() {
+        chain = values("forwarded")
+            .iter()
+            .map(|element| forwarded_element_for(element))
+            .collect();
+    }
+    if chain.is_empty() {
+        chain = values("x-real-ip")
+            .iter()
+            .map(|value| parse_ip(value))
+            .collect();
+    }
+
+    let mut client = peer;
+    for hop in chain.into_iter().rev() {
+        match hop {
+            Some(ip) => {
+                client = ip;
+                if !proxies.is_trusted(ip) {
+                    break;
+                }
+            }
+            None => break,
+        }
+    }
+    Some(client)
+}
+
+/// Parse an IP address that may be quoted, in brackets or followed by a port.
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    let value = value.trim().trim_matches('"');
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split(']').next()?.parse().ok();
+    }
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|socket| socket.ip()))
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// A [KeyExtractor] that allow to do rate limiting for all incoming requests. This is useful if you want to hard-limit the HTTP load your app can handle.
 pub struct GlobalKeyExtractor;
@@ -80,3 +465,1790 @@ impl KeyExtractor for GlobalKeyExtractor {
         None
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the API key sent in a request header as key.
+///
+/// Requests without the header or with an empty or non-ASCII value are rejected with `401 Unauthorized`.
+/// The API key is not verified, so clients can spread their requests over made-up keys.
+/// Combine it with a second, more generous [Governor](crate::Governor) based on the peer IP to catch this.
+/// The middleware registered last runs first, so register the IP-based one after the API key one:
+///
+/// ```rust
+/// use actix_governor::{ApiKeyExtractor, Governor, GovernorConfigBuilder};
+/// use actix_web::App;
+///
+/// let per_api_key = GovernorConfigBuilder::default()
+///     .per_second(1)
+///     .burst_size(10)
+///     .key_extractor(ApiKeyExtractor::default())
+///     .finish()
+///     .unwrap();
+/// let per_ip = GovernorConfigBuilder::default()
+///     .per_millisecond(100)
+///     .burst_size(50)
+///     .finish()
+///     .unwrap();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&per_api_key))
+///     .wrap(Governor::new(&per_ip));
+/// ```
+pub struct ApiKeyExtractor {
+    header: HeaderName,
+}
+
+impl ApiKeyExtractor {
+    /// Read the API key from the given header.
+    pub fn new(header: HeaderName) -> Self {
+        ApiKeyExtractor { header }
+    }
+}
+
+impl Default for ApiKeyExtractor {
+    /// Read the API key from the `x-api-key` header.
+    fn default() -> Self {
+        ApiKeyExtractor::new(HeaderName::from_static("x-api-key"))
+    }
+}
+
+impl KeyExtractor for ApiKeyExtractor {
+    type Key = String;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "API key"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let value = req
+            .headers()
+            .get(&self.header)
+            .ok_or("Missing API key header")?;
+        match value.to_str() {
+            Ok(api_key) if !api_key.trim().is_empty() => Ok(api_key.trim().to_owned()),
+            _ => Err("Invalid API key header"),
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.clone())
+    }
+}
+
+/// What [QueryParamKeyExtractor] does with requests without the query parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingQueryParam {
+    /// Reject the request with `401 Unauthorized`. This is the default.
+    Reject,
+    /// Limit the request by its peer IP.
+    PeerIp,
+    /// Limit all requests without the parameter together.
+    Shared,
+}
+
+/// Key of [QueryParamKeyExtractor].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QueryParamKey {
+    /// The value of the query parameter.
+    Value(String),
+    /// The peer IP of a request without the parameter, see [`MissingQueryParam::PeerIp`].
+    PeerIp(IpAddr),
+    /// The shared key of requests without the parameter, see [`MissingQueryParam::Shared`].
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the value of a query parameter as key, like `?apikey=...`.
+///
+/// This is meant for APIs that can't send their key in a header, prefer [ApiKeyExtractor] otherwise.
+/// Query strings end up in access logs and browser histories.
+///
+/// ```rust
+/// use actix_governor::{GovernorConfigBuilder, MissingQueryParam, QueryParamKeyExtractor};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(QueryParamKeyExtractor::new("apikey").when_missing(MissingQueryParam::PeerIp))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// Like [ApiKeyExtractor], the value is not verified.
+pub struct QueryParamKeyExtractor {
+    param: String,
+    missing: MissingQueryParam,
+}
+
+impl QueryParamKeyExtractor {
+    /// Use the value of the query parameter `param` as key.
+    pub fn new(param: impl Into<String>) -> Self {
+        QueryParamKeyExtractor {
+            param: param.into(),
+            missing: MissingQueryParam::Reject,
+        }
+    }
+
+    /// Set what happens with requests without the parameter or with an empty value.
+    pub fn when_missing(mut self, missing: MissingQueryParam) -> Self {
+        self.missing = missing;
+        self
+    }
+}
+
+impl KeyExtractor for QueryParamKeyExtractor {
+    type Key = QueryParamKey;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "query parameter"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let value =
+            actix_web::web::Query::<HashMap<String, String>>::from_query(req.query_string())
+                .ok()
+                .and_then(|query| query.into_inner().remove(&self.param))
+                .filter(|value| !value.is_empty());
+        match (value, self.missing) {
+            (Some(value), _) => Ok(QueryParamKey::Value(value)),
+            (None, MissingQueryParam::Reject) => Err("Missing key query parameter"),
+            (None, MissingQueryParam::PeerIp) => req
+                .peer_addr()
+                .map(|socket| QueryParamKey::PeerIp(socket.ip()))
+                .ok_or("Could not extract peer IP address from request"),
+            (None, MissingQueryParam::Shared) => Ok(QueryParamKey::Missing),
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        match key {
+            QueryParamKey::Value(value) => Some(value.clone()),
+            QueryParamKey::PeerIp(ip) => Some(ip.to_string()),
+            QueryParamKey::Missing => None,
+        }
+    }
+}
+
+/// What [HostKeyExtractor] does with requests without a valid host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MissingHost {
+    /// Reject the request with `401 Unauthorized`. This is the default.
+    Reject,
+    /// Limit the request by its peer IP.
+    PeerIp,
+    /// Limit all requests without a valid host together.
+    Shared,
+}
+
+/// Key of [HostKeyExtractor].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HostKey {
+    /// The normalized host name, without the port.
+    Host(String),
+    /// The peer IP of a request without a valid host, see [`MissingHost::PeerIp`].
+    PeerIp(IpAddr),
+    /// The shared key of requests without a valid host, see [`MissingHost::Shared`].
+    Missing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the host of the request as key, for per-tenant quotas
+/// when one app serves the domains of many tenants.
+///
+/// ```rust
+/// use actix_governor::{GovernorConfigBuilder, HostKeyExtractor, MissingHost};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(HostKeyExtractor::new().when_missing(MissingHost::PeerIp))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// The host is taken from the `Host` header or, for HTTP/2, from the authority of the request URI.
+/// It is lowercased and the port and a trailing dot are removed, so `Example.com:8080` and `example.com.`
+/// share the bucket of `example.com`. The `Forwarded` and `X-Forwarded-Host` headers are ignored,
+/// because clients could set them to use the quota of another tenant.
+pub struct HostKeyExtractor {
+    missing: MissingHost,
+}
+
+impl HostKeyExtractor {
+    /// Use the host of the request as key.
+    pub fn new() -> Self {
+        HostKeyExtractor {
+            missing: MissingHost::Reject,
+        }
+    }
+
+    /// Set what happens with requests without a host or with an invalid one.
+    pub fn when_missing(mut self, missing: MissingHost) -> Self {
+        self.missing = missing;
+        self
+    }
+}
+
+impl Default for HostKeyExtractor {
+    fn default() -> Self {
+        HostKeyExtractor::new()
+    }
+}
+
+impl KeyExtractor for HostKeyExtractor {
+    type Key = HostKey;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "host"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let host = match req.headers().get(header::HOST) {
+            Some(value) => value.to_str().ok().and_then(normalize_host),
+            None => req
+                .uri()
+                .authority()
+                .and_then(|authority| normalize_host(authority.as_str())),
+        };
+        match (host, self.missing) {
+            (Some(host), _) => Ok(HostKey::Host(host)),
+            (None, MissingHost::Reject) => Err("Missing or invalid host"),
+            (None, MissingHost::PeerIp) => req
+                .peer_addr()
+                .map(|socket| HostKey::PeerIp(socket.ip()))
+                .ok_or("Could not extract peer IP address from request"),
+            (None, MissingHost::Shared) => Ok(HostKey::Missing),
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        match key {
+            HostKey::Host(host) => Some(host.clone()),
+            HostKey::PeerIp(ip) => Some(ip.to_string()),
+            HostKey::Missing => None,
+        }
+    }
+}
+
+/// Lowercase the host and remove the port and a trailing dot. Returns `None` if it is invalid.
+fn normalize_host(host: &str) -> Option<String> {
+    let authority: actix_web::http::uri::Authority = host.parse().ok()?;
+    if authority.as_str().contains('@') {
+        return None;
+    }
+    let host = authority.host().trim_end_matches('.');
+    if host.is_empty() {
+        return None;
+    }
+    Some(host.to_ascii_lowercase())
+}
+
+type CountryLookup = dyn Fn(IpAddr) -> Option<String> + Send + Sync;
+
+/// What [CountryKeyExtractor] does with the requests of a country.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CountryRule {
+    Quota(QuotaOverride),
+    Block,
+}
+
+#[derive(Clone)]
+/// A [KeyExtractor] that uses the peer IP as key and applies different quotas per country.
+///
+/// The country of the IP is resolved by a closure, usually with a GeoIP database like the ones of MaxMind.
+/// Countries with their own quota are limited by it instead of the configured quota,
+/// see [QuotaOverride], requests from blocked countries are rejected with `403 Forbidden`
+/// and all other countries use the configured quota:
+///
+/// ```rust
+/// use actix_governor::{CountryKeyExtractor, GovernorConfigBuilder, QuotaOverride};
+/// use std::time::Duration;
+///
+/// let extractor = CountryKeyExtractor::new(|ip| {
+///     // Look up the ISO country code of the IP
+///     None
+/// })
+/// .quota("XX", QuotaOverride::new(Duration::from_secs(5), 2).unwrap())
+/// .block("YY");
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(extractor)
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// With the `geoip` feature, `CountryKeyExtractor::maxmind` looks the countries up
+/// in a MaxMind database like GeoLite2 Country.
+///
+/// Country codes are compared case-insensitively. A [QuotaOverride] inserted into the request
+/// extensions by an earlier middleware takes precedence over the quota of the country.
+/// Behind a reverse proxy, take the IP from [SmartIpKeyExtractor] with
+/// [`ip_extractor`](CountryKeyExtractor::ip_extractor), otherwise every request has the country
+/// of the proxy.
+pub struct CountryKeyExtractor<K: KeyExtractor<Key = IpAddr> = PeerIpKeyExtractor> {
+    extractor: K,
+    lookup: Arc<CountryLookup>,
+    rules: HashMap<String, CountryRule>,
+}
+
+impl CountryKeyExtractor {
+    /// Resolve the country code of the peer IP with `lookup`.
+    pub fn new<F>(lookup: F) -> Self
+    where
+        F: Fn(IpAddr) -> Option<String> + Send + Sync + 'static,
+    {
+        CountryKeyExtractor {
+            extractor: PeerIpKeyExtractor,
+            lookup: Arc::new(lookup),
+            rules: HashMap::new(),
+        }
+    }
+
+    /// Resolve the country code of the peer IP in a MaxMind database:
+    ///
+    /// ```rust,no_run
+    /// use actix_governor::CountryKeyExtractor;
+    ///
+    /// let reader = maxminddb::Reader::open_readfile("GeoLite2-Country.mmdb").unwrap();
+    /// let extractor = CountryKeyExtractor::maxmind(reader).block("YY");
+    /// ```
+    ///
+    /// IPs that are not in the database use the configured quota.
+    #[cfg(feature = "geoip")]
+    pub fn maxmind<S>(reader: maxminddb::Reader<S>) -> Self
+    where
+        S: AsRef<[u8]> + Send + Sync + 'static,
+    {
+        CountryKeyExtractor::new(move |ip| {
+            let country = reader
+                .lookup(ip)
+                .ok()?
+                .decode::<maxminddb::geoip2::Country>()
+                .ok()??;
+            country.country.iso_code.map(str::to_owned)
+        })
+    }
+}
+
+impl<K: KeyExtractor<Key = IpAddr>> CountryKeyExtractor<K> {
+    /// Take the IP from `extractor` instead of the peer address, e.g. from [SmartIpKeyExtractor]
+    /// behind a reverse proxy.
+    pub fn ip_extractor<E: KeyExtractor<Key = IpAddr>>(
+        self,
+        extractor: E,
+    ) -> CountryKeyExtractor<E> {
+        CountryKeyExtractor {
+            extractor,
+            lookup: self.lookup,
+            rules: self.rules,
+        }
+    }
+
+    /// Limit the requests of the country by `quota`.
+    pub fn quota(mut self, country: &str, quota: QuotaOverride) -> Self {
+        self.rules
+            .insert(country.to_ascii_uppercase(), CountryRule::Quota(quota));
+        self
+    }
+
+    /// Reject all requests of the country.
+    pub fn block(mut self, country: &str) -> Self {
+        self.rules
+            .insert(country.to_ascii_uppercase(), CountryRule::Block);
+        self
+    }
+}
+
+impl<K: KeyExtractor<Key = IpAddr> + PartialEq> PartialEq for CountryKeyExtractor<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.extractor == other.extractor
+            && Arc::ptr_eq(&self.lookup, &other.lookup)
+            && self.rules == other.rules
+    }
+}
+
+impl<K: KeyExtractor<Key = IpAddr> + fmt::Debug> fmt::Debug for CountryKeyExtractor<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CountryKeyExtractor")
+            .field("extractor", &self.extractor)
+            .field("rules", &self.rules)
+            .finish()
+    }
+}
+
+impl<K: KeyExtractor<Key = IpAddr>> KeyExtractor for CountryKeyExtractor<K> {
+    type Key = IpAddr;
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "IP by country"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let ip = self.extractor.extract(req)?;
+        let rule = (self.lookup)(ip)
+            .and_then(|country| self.rules.get(&country.to_ascii_uppercase()).copied());
+        let decision = match rule {
+            Some(CountryRule::Quota(quota)) => KeyDecision::Quota(quota),
+            Some(CountryRule::Block) => {
+                KeyDecision::Blocked("Requests from this country are blocked")
+            }
+            None => return Ok(ip),
+        };
+        req.extensions_mut().insert(decision);
+        Ok(ip)
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.to_string())
+    }
+}
+
+type TokenTransform = dyn Fn(&str) -> String + Send + Sync;
+
+#[derive(Clone, Default)]
+/// A [KeyExtractor] that uses the token of the `Authorization: Bearer` header as key.
+///
+/// Unlike `BearerAuthKeyExtractor` it doesn't need the `httpauth` feature,
+/// and the token can be transformed before it is used as key, for example to avoid keeping
+/// access tokens in memory or to only use the part of the token that identifies the client:
+///
+/// ```rust
+/// use actix_governor::{BearerTokenKeyExtractor, GovernorConfigBuilder};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(BearerTokenKeyExtractor::new().transform(|token| {
+///         // Tokens look like `<client id>.<secret>`
+///         token.split('.').next().unwrap_or(token).to_owned()
+///     }))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// Requests without a bearer token are rejected with `401 Unauthorized`.
+/// The token is not verified, see [ApiKeyExtractor] for how to guard against made-up tokens.
+pub struct BearerTokenKeyExtractor {
+    transform: Option<Arc<TokenTransform>>,
+}
+
+impl BearerTokenKeyExtractor {
+    /// Use the token as it is.
+    pub fn new() -> Self {
+        BearerTokenKeyExtractor { transform: None }
+    }
+
+    /// Apply `f` to the token and use its result as key.
+    pub fn transform<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.transform = Some(Arc::new(f));
+        self
+    }
+}
+
+impl PartialEq for BearerTokenKeyExtractor {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.transform, &other.transform) {
+            (Some(transform), Some(other)) => Arc::ptr_eq(transform, other),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Debug for BearerTokenKeyExtractor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BearerTokenKeyExtractor")
+            .field("transform", &self.transform.is_some())
+            .finish()
+    }
+}
+
+impl KeyExtractor for BearerTokenKeyExtractor {
+    type Key = String;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "bearer token"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let token = bearer_token(req)?;
+        Ok(match &self.transform {
+            Some(transform) => transform(token),
+            None => token.to_owned(),
+        })
+    }
+}
+
+/// Get the token of the `Authorization: Bearer` header.
+fn bearer_token(req: &ServiceRequest) -> Result<&str, &'static str> {
+    let value = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or("Missing authorization header")?
+        .to_str()
+        .map_err(|_| "Invalid authorization header")?;
+    let token = match value.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => token.trim(),
+        _ => return Err("Missing bearer token"),
+    };
+    if token.is_empty() {
+        return Err("Missing bearer token");
+    }
+    Ok(token)
+}
+
+#[cfg(feature = "jwt")]
+type JwtVerifier = dyn Fn(&str) -> bool + Send + Sync;
+
+#[cfg(feature = "jwt")]
+#[derive(Clone)]
+/// A [KeyExtractor] that uses a claim of the JWT in the `Authorization: Bearer` header as key.
+///
+/// String claims are used as they are, other claims as their JSON text.
+/// Requests without a JWT, with a malformed one or without the claim are rejected with `401 Unauthorized`.
+///
+/// By default the signature is **not** verified, so clients can pick any claim.
+/// Verify it with [`hs256`](JwtClaimKeyExtractor::hs256) or plug in your own verification
+/// with [`verify`](JwtClaimKeyExtractor::verify):
+///
+/// ```rust
+/// use actix_governor::{GovernorConfigBuilder, JwtClaimKeyExtractor};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(JwtClaimKeyExtractor::new("tenant_id").hs256(b"secret"))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// The expiry of the token is not checked, expired tokens keep counting against their claim.
+pub struct JwtClaimKeyExtractor {
+    claim: String,
+    verifier: Option<Arc<JwtVerifier>>,
+}
+
+#[cfg(feature = "jwt")]
+impl JwtClaimKeyExtractor {
+    /// Use the value of `claim`, for example `sub`, as key.
+    pub fn new(claim: impl Into<String>) -> Self {
+        JwtClaimKeyExtractor {
+            claim: claim.into(),
+            verifier: None,
+        }
+    }
+
+    /// Only accept tokens for which `f` returns `true`. It is called with the whole token.
+    pub fn verify<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.verifier = Some(Arc::new(f));
+        self
+    }
+
+    /// Only accept tokens signed with HMAC-SHA256 and the given secret.
+    pub fn hs256(self, secret: &[u8]) -> Self {
+        use hmac::{Hmac, Mac};
+        use sha2::Sha256;
+
+        let secret = secret.to_vec();
+        self.verify(move |token| {
+            let (signing_input, signature) = match token.rsplit_once('.') {
+                Some(parts) => parts,
+                None => return false,
+            };
+            let is_hs256 = signing_input
+                .split('.')
+                .next()
+                .and_then(decode_jwt_part)
+                .is_some_and(|header| header["alg"] == "HS256");
+            let signature = match URL_SAFE_NO_PAD.decode(signature) {
+                Ok(signature) => signature,
+                Err(_) => return false,
+            };
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(&secret).expect("HMAC takes keys of any size");
+            mac.update(signing_input.as_bytes());
+            is_hs256 && mac.verify_slice(&signature).is_ok()
+        })
+    }
+}
+
+/// Decode a base64url encoded JSON part of a JWT.
+#[cfg(feature = "jwt")]
+fn decode_jwt_part(part: &str) -> Option<serde_json::Value> {
+    let json = URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&json).ok()
+}
+
+#[cfg(feature = "jwt")]
+impl PartialEq for JwtClaimKeyExtractor {
+    fn eq(&self, other: &Self) -> bool {
+        self.claim == other.claim
+            && match (&self.verifier, &other.verifier) {
+                (Some(verifier), Some(other)) => Arc::ptr_eq(verifier, other),
+                (None, None) => true,
+                _ => false,
+            }
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl fmt::Debug for JwtClaimKeyExtractor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtClaimKeyExtractor")
+            .field("claim", &self.claim)
+            .field("verifier", &self.verifier.is_some())
+            .finish()
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl KeyExtractor for JwtClaimKeyExtractor {
+    type Key = String;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "JWT claim"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let token = bearer_token(req)?;
+        let payload = token.split('.').nth(1).ok_or("Malformed JWT")?;
+        if let Some(verifier) = &self.verifier {
+            if !verifier(token) {
+                return Err("Invalid JWT signature");
+            }
+        }
+        let claims = decode_jwt_part(payload).ok_or("Malformed JWT")?;
+        match claims.get(&self.claim) {
+            Some(serde_json::Value::String(claim)) => Ok(claim.clone()),
+            Some(serde_json::Value::Null) | None => Err("Missing JWT claim"),
+            Some(claim) => Ok(claim.to_string()),
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.clone())
+    }
+}
+
+/// Identifies the connection a request was sent over.
+///
+/// Register [`ConnectionId::on_connect`] with [`HttpServer::on_connect`](actix_web::HttpServer::on_connect)
+/// to assign an id to every new connection, which is then used by [ConnectionKeyExtractor].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    /// Assign a new id to the connection.
+    pub fn on_connect(_connection: &dyn Any, data: &mut Extensions) {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        data.insert(ConnectionId(NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the connection as key.
+///
+/// All requests sent over the same connection share a bucket, like the multiplexed streams
+/// of a long-lived HTTP/2 connection. The connections need a [ConnectionId]:
+///
+/// ```rust,no_run
+/// use actix_governor::{ConnectionId, ConnectionKeyExtractor, Governor, GovernorConfigBuilder};
+/// use actix_web::{App, HttpServer};
+///
+/// #[actix_web::main]
+/// async fn main() -> std::io::Result<()> {
+///     let governor_conf = GovernorConfigBuilder::default()
+///         .key_extractor(ConnectionKeyExtractor)
+///         .finish()
+///         .unwrap();
+///
+///     HttpServer::new(move || App::new().wrap(Governor::new(&governor_conf)))
+///         .on_connect(ConnectionId::on_connect)
+///         .bind("127.0.0.1:8080")?
+///         .run()
+///         .await
+/// }
+/// ```
+///
+/// Clients can still get a fresh bucket by opening another connection,
+/// so combine it with a limit on the peer IP if that matters.
+pub struct ConnectionKeyExtractor;
+
+impl KeyExtractor for ConnectionKeyExtractor {
+    type Key = ConnectionId;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "connection"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        req.conn_data::<ConnectionId>()
+            .copied()
+            .ok_or("Could not find the connection id of the request")
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.0.to_string())
+    }
+}
+
+#[cfg(feature = "mtls")]
+/// SHA-256 fingerprint of the client certificate of a mutual TLS connection.
+///
+/// It has to be inserted into the connection data from
+/// [`HttpServer::on_connect`](actix_web::HttpServer::on_connect), where the TLS stream of the
+/// connection is available, to use [ClientCertKeyExtractor]. With the `rustls` feature, register
+/// [`ClientCertFingerprint::rustls_on_connect`] for servers bound with `bind_rustls_0_23`:
+///
+/// ```rust,ignore
+/// use actix_governor::ClientCertFingerprint;
+///
+/// HttpServer::new(app)
+///     .on_connect(ClientCertFingerprint::rustls_on_connect)
+///     .bind_rustls_0_23(("0.0.0.0", 443), tls_config)?
+/// ```
+///
+/// With the `openssl` feature, register [`ClientCertFingerprint::openssl_on_connect`]
+/// for servers bound with `bind_openssl` instead. Other TLS stacks can insert
+/// [`ClientCertFingerprint::from_der`] of the client certificate themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientCertFingerprint([u8; 32]);
+
+#[cfg(feature = "mtls")]
+impl ClientCertFingerprint {
+    /// Compute the fingerprint of a DER encoded certificate.
+    pub fn from_der(certificate: &[u8]) -> Self {
+        use sha2::{Digest, Sha256};
+
+        ClientCertFingerprint(Sha256::digest(certificate).into())
+    }
+
+    /// The bytes of the fingerprint.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Insert the fingerprint of the client certificate of a rustls connection.
+    ///
+    /// Connections without a client certificate, or that aren't rustls 0.23 connections,
+    /// get no fingerprint.
+    #[cfg(feature = "rustls")]
+    pub fn rustls_on_connect(connection: &dyn Any, data: &mut Extensions) {
+        use actix_tls::accept::rustls_0_23::TlsStream;
+        use actix_web::rt::net::TcpStream;
+
+        if let Some(tls) = connection.downcast_ref::<TlsStream<TcpStream>>() {
+            if let Some([certificate, ..]) = tls.get_ref().1.peer_certificates() {
+                data.insert(ClientCertFingerprint::from_der(certificate));
+            }
+        }
+    }
+
+    /// Insert the fingerprint of the client certificate of an openssl connection.
+    ///
+    /// Connections without a client certificate, or that aren't openssl connections,
+    /// get no fingerprint.
+    #[cfg(feature = "openssl")]
+    pub fn openssl_on_connect(connection: &dyn Any, data: &mut Extensions) {
+        use actix_tls::accept::openssl::TlsStream;
+        use actix_web::rt::net::TcpStream;
+
+        if let Some(tls) = connection.downcast_ref::<TlsStream<TcpStream>>() {
+            if let Some(certificate) = tls.ssl().peer_certificate() {
+                if let Ok(der) = certificate.to_der() {
+                    data.insert(ClientCertFingerprint::from_der(&der));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "mtls")]
+impl Display for ClientCertFingerprint {
+    /// Format the fingerprint as lowercase hex, like `sha256sum` does.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mtls")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the fingerprint of the client certificate as key.
+///
+/// For services behind mutual TLS this is a strong identity of the client,
+/// which can't be spoofed like a header. The connections need a [ClientCertFingerprint],
+/// requests of connections without a client certificate are rejected.
+///
+/// The fingerprint is only available if actix-web terminates TLS itself.
+/// Behind a TLS terminating proxy, use a header set by the proxy with [ApiKeyExtractor] instead.
+pub struct ClientCertKeyExtractor;
+
+#[cfg(feature = "mtls")]
+impl KeyExtractor for ClientCertKeyExtractor {
+    type Key = ClientCertFingerprint;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "client certificate"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        req.conn_data::<ClientCertFingerprint>()
+            .copied()
+            .ok_or("Missing client certificate")
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.to_string())
+    }
+}
+
+/// A [KeyExtractor] that uses a value inserted into the request extensions as key.
+///
+/// Authentication middleware often stores the authenticated user in the request extensions.
+/// Wrap the governor inside of that middleware and rate limit by the user,
+/// without authenticating the request again in a custom key extractor:
+///
+/// ```rust
+/// use actix_governor::{ExtensionKeyExtractor, Governor, GovernorConfigBuilder};
+/// use actix_web::{dev::Service, web, App, HttpMessage};
+///
+/// #[derive(Clone, PartialEq, Eq, Hash)]
+/// struct UserId(u64);
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(ExtensionKeyExtractor::<UserId>::new())
+///     .finish()
+///     .unwrap();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&config))
+///     // Registered last, so it runs first
+///     .wrap_fn(|req, srv| {
+///         // Authenticate the request here
+///         req.extensions_mut().insert(UserId(42));
+///         srv.call(req)
+///     })
+///     .route("/", web::get().to(|| async { "Hello world!" }));
+/// ```
+///
+/// Requests without the value are rejected.
+pub struct ExtensionKeyExtractor<T> {
+    key: PhantomData<fn() -> T>,
+}
+
+impl<T> ExtensionKeyExtractor<T> {
+    /// Use the value of type `T` in the request extensions as key.
+    pub fn new() -> Self {
+        ExtensionKeyExtractor { key: PhantomData }
+    }
+}
+
+impl<T> Default for ExtensionKeyExtractor<T> {
+    fn default() -> Self {
+        ExtensionKeyExtractor::new()
+    }
+}
+
+impl<T> Clone for ExtensionKeyExtractor<T> {
+    fn clone(&self) -> Self {
+        ExtensionKeyExtractor::new()
+    }
+}
+
+impl<T> fmt::Debug for ExtensionKeyExtractor<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ExtensionKeyExtractor<{}>", std::any::type_name::<T>())
+    }
+}
+
+impl<T: Clone + Hash + Eq + 'static> KeyExtractor for ExtensionKeyExtractor<T> {
+    type Key = T;
+    type KeyExtractionError = &'static str;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "request extension"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        req.extensions()
+            .get::<T>()
+            .cloned()
+            .ok_or("Missing key in the request extensions")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that combines the matched route pattern with the key of another extractor.
+///
+/// Every route gets its own bucket per client, so a single governor middleware can wrap the
+/// whole app while `/login` and `/search` are limited independently:
+///
+/// ```rust
+/// use actix_governor::{GovernorConfigBuilder, PeerIpKeyExtractor, RouteScopedKeyExtractor};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(RouteScopedKeyExtractor::new(PeerIpKeyExtractor))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// The route is the pattern the request matches, like `/users/{id}`, so all users share one bucket.
+/// Requests that don't match any route share the `None` route.
+pub struct RouteScopedKeyExtractor<K: KeyExtractor> {
+    extractor: K,
+}
+
+impl<K: KeyExtractor> RouteScopedKeyExtractor<K> {
+    /// Scope the keys of `extractor` by route.
+    pub fn new(extractor: K) -> Self {
+        RouteScopedKeyExtractor { extractor }
+    }
+}
+
+impl<K: KeyExtractor> KeyExtractor for RouteScopedKeyExtractor<K> {
+    type Key = (Option<String>, K::Key);
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        self.extractor.name()
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let key = self.extractor.extract(req)?;
+        Ok((req.match_pattern(), key))
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, (route, key): &Self::Key) -> Option<String> {
+        let route = route.as_deref().unwrap_or("unmatched route");
+        Some(match self.extractor.key_name(key) {
+            Some(key_name) => format!("{key_name} on {route}"),
+            None => route.to_owned(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that tries another extractor if the first one fails.
+///
+/// This is the usual keying of APIs: clients with an API key are limited per key,
+/// all others per IP address:
+///
+/// ```rust
+/// use actix_governor::{
+///     ApiKeyExtractor, FallbackKeyExtractor, GovernorConfigBuilder, PeerIpKeyExtractor,
+/// };
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(FallbackKeyExtractor::new(
+///         ApiKeyExtractor::default(),
+///         PeerIpKeyExtractor,
+///     ))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// Nest it to chain more than two extractors. If all of them fail, the error of the last one is returned.
+pub struct FallbackKeyExtractor<P: KeyExtractor, F: KeyExtractor> {
+    primary: P,
+    fallback: F,
+}
+
+impl<P: KeyExtractor, F: KeyExtractor> FallbackKeyExtractor<P, F> {
+    /// Use `primary` and fall back to `fallback` if it fails.
+    pub fn new(primary: P, fallback: F) -> Self {
+        FallbackKeyExtractor { primary, fallback }
+    }
+}
+
+/// Key of a [FallbackKeyExtractor]. Keys of the two extractors never share a bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FallbackKey<P, F> {
+    /// The key of the primary extractor.
+    Primary(P),
+    /// The key of the fallback extractor.
+    Fallback(F),
+}
+
+impl<P: KeyExtractor, F: KeyExtractor> KeyExtractor for FallbackKeyExtractor<P, F> {
+    type Key = FallbackKey<P::Key, F::Key>;
+    type KeyExtractionError = F::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "fallback chain"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        match self.primary.extract(req) {
+            Ok(key) => Ok(FallbackKey::Primary(key)),
+            Err(_) => self.fallback.extract(req).map(FallbackKey::Fallback),
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        let (name, key_name) = match key {
+            FallbackKey::Primary(key) => (self.primary.name(), self.primary.key_name(key)),
+            FallbackKey::Fallback(key) => (self.fallback.name(), self.fallback.key_name(key)),
+        };
+        Some(match key_name {
+            Some(key_name) => format!("{name} {key_name}"),
+            None => name.to_owned(),
+        })
+    }
+}
+
+type Normalize<Key> = dyn Fn(Key) -> Key + Send + Sync;
+
+#[derive(Clone)]
+/// A [KeyExtractor] that normalizes the keys of another extractor before they reach the limiter.
+///
+/// Keys that differ only in their spelling get separate buckets, e.g. an API key with
+/// surrounding spaces or the IPv4 address `1.2.3.4` and its IPv6-mapped form `::ffff:1.2.3.4`
+/// of a dual-stack socket. Normalize them to share one bucket:
+///
+/// ```rust
+/// use actix_governor::{ApiKeyExtractor, GovernorConfigBuilder, NormalizedKeyExtractor};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(NormalizedKeyExtractor::new(
+///         ApiKeyExtractor::default(),
+///         |key: String| key.trim().to_lowercase(),
+///     ))
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// The common normalizations are available as steps that are applied in order,
+/// starting from [`steps`](NormalizedKeyExtractor::steps):
+///
+/// ```rust
+/// use actix_governor::{ApiKeyExtractor, NormalizedKeyExtractor, PeerIpKeyExtractor};
+///
+/// let api_keys = NormalizedKeyExtractor::steps(ApiKeyExtractor::default())
+///     .trim()
+///     .lowercase()
+///     .truncate(64);
+/// let networks = NormalizedKeyExtractor::steps(PeerIpKeyExtractor)
+///     .to_canonical()
+///     .subnet(24, 64);
+/// ```
+///
+/// Use [`normalize_keys`](crate::GovernorConfigBuilder::normalize_keys()) or
+/// [`normalize_keys_with`](crate::GovernorConfigBuilder::normalize_keys_with()) to wrap the
+/// configured extractor, and [`hash_keys`](crate::GovernorConfigBuilder::hash_keys()) afterwards
+/// to hash the normalized keys.
+pub struct NormalizedKeyExtractor<K: KeyExtractor> {
+    extractor: K,
+    normalize: Arc<Normalize<K::Key>>,
+}
+
+impl<K: KeyExtractor> NormalizedKeyExtractor<K> {
+    /// Pass the keys of `extractor` through `normalize`.
+    pub fn new<F>(extractor: K, normalize: F) -> Self
+    where
+        F: Fn(K::Key) -> K::Key + Send + Sync + 'static,
+    {
+        NormalizedKeyExtractor {
+            extractor,
+            normalize: Arc::new(normalize),
+        }
+    }
+}
+
+impl<K: KeyExtractor> NormalizedKeyExtractor<K> {
+    /// Pass the keys of `extractor` through unchanged, add normalization steps with the other methods.
+    pub fn steps(extractor: K) -> Self {
+        NormalizedKeyExtractor::new(extractor, |key| key)
+    }
+
+    /// Pass the normalized keys through `normalize` as well.
+    pub fn then<F>(mut self, normalize: F) -> Self
+    where
+        F: Fn(K::Key) -> K::Key + Send + Sync + 'static,
+        K::Key: 'static,
+    {
+        let previous = self.normalize;
+        self.normalize = Arc::new(move |key| normalize(previous(key)));
+        self
+    }
+}
+
+impl<K: KeyExtractor<Key = String>> NormalizedKeyExtractor<K> {
+    /// Remove leading and trailing whitespace of the keys.
+    pub fn trim(self) -> Self {
+        self.then(|key: String| match key.trim() {
+            trimmed if trimmed.len() == key.len() => key,
+            trimmed => trimmed.to_owned(),
+        })
+    }
+
+    /// Convert the keys to lowercase.
+    pub fn lowercase(self) -> Self {
+        self.then(|key: String| key.to_lowercase())
+    }
+
+    /// Cut the keys after `max_chars` characters.
+    pub fn truncate(self, max_chars: usize) -> Self {
+        self.then(move |mut key: String| {
+            if let Some((end, _)) = key.char_indices().nth(max_chars) {
+                key.truncate(end);
+            }
+            key
+        })
+    }
+}
+
+impl<K: KeyExtractor<Key = IpAddr>> NormalizedKeyExtractor<K> {
+    /// Convert IPv6-mapped IPv4 addresses of `extractor` to IPv4 addresses,
+    /// so `::ffff:1.2.3.4` shares the bucket of `1.2.3.4`.
+    pub fn canonical_ip(extractor: K) -> Self {
+        NormalizedKeyExtractor::steps(extractor).to_canonical()
+    }
+
+    /// Convert IPv6-mapped IPv4 addresses to IPv4 addresses, like [`canonical_ip`](Self::canonical_ip).
+    pub fn to_canonical(self) -> Self {
+        self.then(|ip: IpAddr| ip.to_canonical())
+    }
+
+    /// Replace the addresses by their network with the given prefix lengths,
+    /// like [IpSubnetKeyExtractor].
+    pub fn subnet(self, v4_prefix: u8, v6_prefix: u8) -> Self {
+        let v4_prefix = v4_prefix.min(32);
+        let v6_prefix = v6_prefix.min(128);
+        self.then(move |ip: IpAddr| subnet(ip, v4_prefix, v6_prefix))
+    }
+}
+
+impl<K: KeyExtractor> fmt::Debug for NormalizedKeyExtractor<K>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NormalizedKeyExtractor")
+            .field("extractor", &self.extractor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: KeyExtractor> KeyExtractor for NormalizedKeyExtractor<K> {
+    type Key = K::Key;
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        self.extractor.name()
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        self.extractor.extract(req).map(|key| (self.normalize)(key))
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        self.extractor.key_name(key)
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A [KeyExtractor] that replaces the key of another extractor by its hash.
+///
+/// The rate limiter keeps the keys of recent clients in memory. With hashed keys, raw IP addresses
+/// or user ids are only kept while the request is processed, and every key takes the same space.
+/// Use [`hash_keys`](crate::GovernorConfigBuilder::hash_keys()) to wrap the configured extractor.
+///
+/// The keys are hashed with SipHash and a random secret that is created with the extractor,
+/// so the hashes can't be reversed by trying all IP addresses without the secret.
+/// Clones share the secret.
+pub struct HashedKeyExtractor<K: KeyExtractor> {
+    extractor: K,
+    hasher: RandomState,
+}
+
+impl<K: KeyExtractor> HashedKeyExtractor<K> {
+    /// Hash the keys of `extractor`.
+    pub fn new(extractor: K) -> Self {
+        HashedKeyExtractor {
+            extractor,
+            hasher: RandomState::new(),
+        }
+    }
+}
+
+/// Key of a [HashedKeyExtractor].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HashedKey(u64);
+
+impl<K: KeyExtractor> KeyExtractor for HashedKeyExtractor<K> {
+    type Key = HashedKey;
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        self.extractor.name()
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let key = self.extractor.extract(req)?;
+        Ok(HashedKey(self.hasher.hash_one(&key)))
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(format!("{:016x}", key.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that keys on the pair of the client IP and the family of its user agent.
+///
+/// Clients behind one NAT or proxy share an IP address. When a scraper shares it with
+/// regular users, limiting the IP limits all of them. With this key extractor the scraper
+/// gets its own bucket, as long as its user agent differs from the browsers of the users:
+///
+/// ```rust
+/// use actix_governor::{GovernorConfigBuilder, UserAgentKeyExtractor};
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(UserAgentKeyExtractor::default())
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// The user agent is normalized to its family, so version updates don't create new buckets:
+/// common bots and HTTP libraries like `Googlebot`, `curl` or `python-requests` and the
+/// major browsers are recognized, other user agents are reduced to their first product name.
+/// Requests without a user agent share the bucket of an empty family.
+///
+/// **Warning:** clients can send any user agent. This only separates well-behaved bots from
+/// users; a client that rotates its user agent gets a bucket per family.
+pub struct UserAgentKeyExtractor<K: KeyExtractor = PeerIpKeyExtractor> {
+    extractor: K,
+}
+
+impl<K: KeyExtractor> UserAgentKeyExtractor<K> {
+    /// Pair the user agent family with the key of `extractor`, e.g. [SmartIpKeyExtractor]
+    /// behind a reverse proxy.
+    pub fn new(extractor: K) -> Self {
+        UserAgentKeyExtractor { extractor }
+    }
+}
+
+impl Default for UserAgentKeyExtractor {
+    fn default() -> Self {
+        UserAgentKeyExtractor::new(PeerIpKeyExtractor)
+    }
+}
+
+impl<K: KeyExtractor> KeyExtractor for UserAgentKeyExtractor<K> {
+    type Key = (K::Key, String);
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "user agent"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let key = self.extractor.extract(req)?;
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        Ok((key, user_agent_family(user_agent)))
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, (key, family): &Self::Key) -> Option<String> {
+        let key_name = self.extractor.key_name(key).unwrap_or_default();
+        Some(format!("{key_name} {family}").trim().to_owned())
+    }
+}
+
+/// Markers of user agent families, checked in order against the lowercased user agent.
+/// Bots come first, because many of them also claim to be a browser.
+const USER_AGENT_FAMILIES: &[(&str, &str)] = &[
+    ("googlebot", "googlebot"),
+    ("bingbot", "bingbot"),
+    ("yandexbot", "yandexbot"),
+    ("baiduspider", "baiduspider"),
+    ("duckduckbot", "duckduckbot"),
+    ("applebot", "applebot"),
+    ("ahrefsbot", "ahrefsbot"),
+    ("semrushbot", "semrushbot"),
+    ("gptbot", "gptbot"),
+    ("facebookexternalhit", "facebookexternalhit"),
+    ("headlesschrome", "headlesschrome"),
+    ("python-requests", "python-requests"),
+    ("python-urllib", "python-urllib"),
+    ("aiohttp", "aiohttp"),
+    ("scrapy", "scrapy"),
+    ("curl/", "curl"),
+    ("wget/", "wget"),
+    ("go-http-client", "go-http-client"),
+    ("okhttp", "okhttp"),
+    ("node-fetch", "node-fetch"),
+    ("axios", "axios"),
+    ("libwww-perl", "libwww-perl"),
+    ("java/", "java"),
+    ("edg/", "edge"),
+    ("opr/", "opera"),
+    ("firefox/", "firefox"),
+    ("chrome/", "chrome"),
+    ("safari/", "safari"),
+];
+
+/// Normalize the user agent to its family.
+fn user_agent_family(user_agent: &str) -> String {
+    let lowercase = user_agent.to_ascii_lowercase();
+    if let Some((_, family)) = USER_AGENT_FAMILIES
+        .iter()
+        .find(|(marker, _)| lowercase.contains(marker))
+    {
+        return (*family).to_owned();
+    }
+    if ["bot", "crawler", "spider"]
+        .iter()
+        .any(|marker| lowercase.contains(marker))
+    {
+        return "other bot".to_owned();
+    }
+    lowercase
+        .split(|c: char| c == '/' || c.is_ascii_whitespace())
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .take(32)
+        .collect()
+}
+
+/// Last extracted key and number of requests since then, per connection.
+type SampledKeys<Key> = HashMap<ConnectionId, (Key, u32)>;
+
+#[derive(Debug, Clone)]
+/// A [KeyExtractor] that only runs an expensive key extractor for some requests of a connection.
+///
+/// The first request of a connection and then every `every`-th request run the wrapped extractor,
+/// the other requests reuse the key that was extracted last on the same connection.
+/// The connections need a [ConnectionId], requests of connections without one always run
+/// the wrapped extractor.
+///
+/// This trades accuracy for latency on very hot paths, for example if the key is parsed from a JWT:
+/// requests can be counted against a stale key until the next sample, and requests that would fail
+/// extraction pass as long as an earlier request of the connection succeeded.
+/// Requests for which the wrapped extractor exempts, blocks or assigns a quota to the key
+/// aren't cached, the next request of the connection runs the extractor again.
+///
+/// **Don't use it behind a reverse proxy.** A proxy sends the requests of many clients over
+/// the same pooled connections, so they would be counted against the key of another client.
+///
+/// ```rust,no_run
+/// use actix_governor::{
+///     ConnectionId, Governor, GovernorConfigBuilder, PeerIpKeyExtractor, SampledKeyExtractor,
+/// };
+/// use actix_web::{App, HttpServer};
+///
+/// #[actix_web::main]
+/// async fn main() -> std::io::Result<()> {
+///     let governor_conf = GovernorConfigBuilder::default()
+///         .key_extractor(SampledKeyExtractor::new(PeerIpKeyExtractor, 10))
+///         .finish()
+///         .unwrap();
+///
+///     HttpServer::new(move || App::new().wrap(Governor::new(&governor_conf)))
+///         .on_connect(ConnectionId::on_connect)
+///         .bind("127.0.0.1:8080")?
+///         .run()
+///         .await
+/// }
+/// ```
+pub struct SampledKeyExtractor<K: KeyExtractor> {
+    extractor: K,
+    every: u32,
+    capacity: usize,
+    connections: Arc<Mutex<SampledKeys<K::Key>>>,
+}
+
+impl<K: KeyExtractor> SampledKeyExtractor<K> {
+    /// Run `extractor` for one out of `every` requests of a connection.
+    /// With `every` set to `0` or `1` the extractor runs for every request.
+    pub fn new(extractor: K, every: u32) -> Self {
+        SampledKeyExtractor {
+            extractor,
+            every,
+            capacity: 4096,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Set how many connections the keys are cached for. By default this is 4096.
+    ///
+    /// Closed connections can't be detected, so the cache is emptied once it is full.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+}
+
+impl<K: KeyExtractor> KeyExtractor for SampledKeyExtractor<K> {
+    type Key = K::Key;
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        self.extractor.name()
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let connection = match req.conn_data::<ConnectionId>() {
+            Some(connection) => *connection,
+            None => return self.extractor.extract(req),
+        };
+
+        {
+            let mut connections = self.connections.lock().unwrap();
+            if let Some((key, count)) = connections.get_mut(&connection) {
+                *count += 1;
+                if *count < self.every {
+                    return Ok(key.clone());
+                }
+            }
+        }
+
+        let key = self.extractor.extract(req)?;
+        let mut connections = self.connections.lock().unwrap();
+        if req.extensions().contains::<KeyDecision>() {
+            // The decision belongs to this request, a cached key would lose it.
+            connections.remove(&connection);
+            return Ok(key);
+        }
+        if connections.len() >= self.capacity && !connections.contains_key(&connection) {
+            connections.clear();
+        }
+        connections.insert(connection, (key.clone(), 0));
+        Ok(key)
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        self.extractor.key_name(key)
+    }
+}
+
+type Coarsen<Key> = dyn Fn(&Key) -> Key + Send + Sync;
+type TripHook = dyn Fn(usize) + Send + Sync;
+
+/// Distinct keys seen in the current window of a [CardinalityGuardKeyExtractor].
+#[derive(Debug)]
+struct Cardinality<Key> {
+    window_start: Instant,
+    keys: HashSet<Key>,
+    tripped: bool,
+}
+
+#[derive(Clone)]
+/// A [KeyExtractor] that switches to coarser keys when too many distinct keys show up.
+///
+/// The rate limiter keeps state for every key, so clients that spray requests over
+/// many keys, like a botnet or an attacker rotating IPv6 addresses, make it grow without bound.
+/// The guard counts the distinct keys of each `window`. Once more than `max_keys` were seen,
+/// every key is passed through `coarsen` until a window with fewer keys passed.
+///
+/// [`CardinalityGuardKeyExtractor::subnets`] limits subnets of the peer IP instead of single addresses:
+///
+/// ```rust
+/// use actix_governor::{CardinalityGuardKeyExtractor, GovernorConfigBuilder};
+/// use std::time::Duration;
+///
+/// let config = GovernorConfigBuilder::default()
+///     .key_extractor(
+///         CardinalityGuardKeyExtractor::subnets(10_000, Duration::from_secs(60))
+///             .on_trip(|keys| eprintln!("{keys} new keys per minute, limiting subnets")),
+///     )
+///     .finish()
+///     .unwrap();
+/// ```
+pub struct CardinalityGuardKeyExtractor<K: KeyExtractor> {
+    extractor: K,
+    max_keys: usize,
+    window: Duration,
+    coarsen: Arc<Coarsen<K::Key>>,
+    on_trip: Option<Arc<TripHook>>,
+    cardinality: Arc<Mutex<Cardinality<K::Key>>>,
+}
+
+impl<K: KeyExtractor> CardinalityGuardKeyExtractor<K> {
+    /// Use `coarsen` on the keys of `extractor` once more than `max_keys` distinct keys
+    /// were seen within `window`.
+    pub fn new<F>(extractor: K, max_keys: usize, window: Duration, coarsen: F) -> Self
+    where
+        F: Fn(&K::Key) -> K::Key + Send + Sync + 'static,
+    {
+        CardinalityGuardKeyExtractor {
+            extractor,
+            max_keys,
+            window,
+            coarsen: Arc::new(coarsen),
+            on_trip: None,
+            cardinality: Arc::new(Mutex::new(Cardinality {
+                window_start: Instant::now(),
+                keys: HashSet::new(),
+                tripped: false,
+            })),
+        }
+    }
+
+    /// Call `f` with the number of distinct keys whenever the guard switches to coarser keys,
+    /// for example to alert or to update a metric.
+    pub fn on_trip<F>(mut self, f: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_trip = Some(Arc::new(f));
+        self
+    }
+
+    /// Whether the guard currently uses coarser keys.
+    pub fn is_tripped(&self) -> bool {
+        self.cardinality.lock().unwrap().tripped
+    }
+}
+
+impl CardinalityGuardKeyExtractor<PeerIpKeyExtractor> {
+    /// Use the peer IP as key and fall back to its `/16` subnet for IPv4 and `/48` subnet for IPv6
+    /// once more than `max_keys` distinct addresses were seen within `window`.
+    pub fn subnets(max_keys: usize, window: Duration) -> Self {
+        CardinalityGuardKeyExtractor::new(PeerIpKeyExtractor, max_keys, window, |ip| {
+            subnet(*ip, 16, 48)
+        })
+    }
+}
+
+impl<K: KeyExtractor> fmt::Debug for CardinalityGuardKeyExtractor<K>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CardinalityGuardKeyExtractor")
+            .field("extractor", &self.extractor)
+            .field("max_keys", &self.max_keys)
+            .field("window", &self.window)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K: KeyExtractor> KeyExtractor for CardinalityGuardKeyExtractor<K> {
+    type Key = K::Key;
+    type KeyExtractionError = K::KeyExtractionError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        self.extractor.name()
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        let key = self.extractor.extract(req)?;
+
+        let (tripped, keys) = {
+            let mut cardinality = self.cardinality.lock().unwrap();
+            let now = Instant::now();
+            if now.duration_since(cardinality.window_start) >= self.window {
+                // Stay coarse as long as the last window had too many keys.
+                cardinality.tripped = cardinality.keys.len() > self.max_keys;
+                cardinality.keys.clear();
+                cardinality.window_start = now;
+            }
+            // Stop remembering keys once the limit is exceeded, so the guard doesn't grow itself.
+            if cardinality.keys.len() <= self.max_keys {
+                cardinality.keys.insert(key.clone());
+            }
+            let just_tripped = !cardinality.tripped && cardinality.keys.len() > self.max_keys;
+            if just_tripped {
+                cardinality.tripped = true;
+            }
+            (
+                cardinality.tripped,
+                just_tripped.then_some(cardinality.keys.len()),
+            )
+        };
+
+        if let Some(keys) = keys {
+            #[cfg(feature = "log")]
+            log::warn!(
+                "More than {} distinct keys for {}, switching to coarser keys",
+                self.max_keys,
+                self.extractor.name()
+            );
+            if let Some(on_trip) = &self.on_trip {
+                on_trip(keys);
+            }
+        }
+
+        Ok(if tripped { (self.coarsen)(&key) } else { key })
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        self.extractor.key_name(key)
+    }
+}
+
+#[cfg(feature = "identity")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the id of the identity attached by [actix-identity](actix_identity) as key.
+///
+/// The identity is only available if the identity and session middlewares run before the governor middleware,
+/// so they have to be registered **after** it:
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfigBuilder, IdentityKeyExtractor};
+/// use actix_identity::IdentityMiddleware;
+/// use actix_session::{storage::CookieSessionStore, SessionMiddleware};
+/// use actix_web::{cookie::Key, App};
+///
+/// let governor_conf = GovernorConfigBuilder::default()
+///     .key_extractor(IdentityKeyExtractor)
+///     .finish()
+///     .unwrap();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&governor_conf))
+///     .wrap(IdentityMiddleware::default())
+///     .wrap(SessionMiddleware::new(CookieSessionStore::default(), Key::generate()));
+/// ```
+///
+/// Requests without an identity are rejected, so combine it with a [guard](actix_web::guard) or scope
+/// if anonymous requests should pass.
+pub struct IdentityKeyExtractor;
+
+#[cfg(feature = "identity")]
+impl KeyExtractor for IdentityKeyExtractor {
+    type Key = String;
+    type KeyExtractionError = actix_identity::error::GetIdentityError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "identity"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        use actix_identity::IdentityExt;
+
+        req.get_identity()?.id()
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.clone())
+    }
+}
+
+#[cfg(feature = "httpauth")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the user id of the `Authorization: Basic` header as key.
+///
+/// The header is not verified, so register the authentication middleware of
+/// [actix-web-httpauth](actix_web_httpauth) **before** the governor middleware, which makes it run after it.
+/// Requests with invalid credentials still count against the quota of the user id they claim:
+///
+/// ```rust
+/// use actix_governor::{BasicAuthKeyExtractor, Governor, GovernorConfigBuilder};
+/// use actix_web::{dev::ServiceRequest, App, Error};
+/// use actix_web_httpauth::{extractors::basic::BasicAuth, middleware::HttpAuthentication};
+///
+/// async fn validator(
+///     req: ServiceRequest,
+///     credentials: BasicAuth,
+/// ) -> Result<ServiceRequest, (Error, ServiceRequest)> {
+///     // Check the credentials here
+///     Ok(req)
+/// }
+///
+/// let governor_conf = GovernorConfigBuilder::default()
+///     .key_extractor(BasicAuthKeyExtractor)
+///     .finish()
+///     .unwrap();
+///
+/// let app = App::new()
+///     .wrap(HttpAuthentication::basic(validator))
+///     .wrap(Governor::new(&governor_conf));
+/// ```
+///
+/// To only limit authenticated requests, register the authentication middleware after the governor middleware instead.
+pub struct BasicAuthKeyExtractor;
+
+#[cfg(feature = "httpauth")]
+impl KeyExtractor for BasicAuthKeyExtractor {
+    type Key = String;
+    type KeyExtractionError = actix_web::error::ParseError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "basic auth"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        use actix_web::http::header::Header;
+        use actix_web_httpauth::headers::authorization::{Authorization, Basic};
+
+        Authorization::<Basic>::parse(req).map(|auth| auth.as_ref().user_id().to_owned())
+    }
+
+    #[cfg(feature = "log")]
+    fn key_name(&self, key: &Self::Key) -> Option<String> {
+        Some(key.clone())
+    }
+}
+
+#[cfg(feature = "httpauth")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A [KeyExtractor] that uses the token of the `Authorization: Bearer` header as key.
+///
+/// See [BasicAuthKeyExtractor] for the order of the middlewares.
+pub struct BearerAuthKeyExtractor;
+
+#[cfg(feature = "httpauth")]
+impl KeyExtractor for BearerAuthKeyExtractor {
+    type Key = String;
+    type KeyExtractionError = actix_web::error::ParseError;
+
+    #[cfg(feature = "log")]
+    fn name(&self) -> &'static str {
+        "bearer auth"
+    }
+
+    fn extract(&self, req: &ServiceRequest) -> Result<Self::Key, Self::KeyExtractionError> {
+        use actix_web::http::header::Header;
+        use actix_web_httpauth::headers::authorization::{Authorization, Bearer};
+
+        Authorization::<Bearer>::parse(req).map(|auth| auth.as_ref().token().to_owned())
+    }
+}
@@ -0,0 +1,67 @@
+use actix_web::http::header::HeaderMap;
+
+use std::{fmt, sync::Arc};
+
+/// State of a key after the governor middleware allowed one of its requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeySnapshot {
+    burst_size: u32,
+    remaining_burst_capacity: u32,
+}
+
+impl KeySnapshot {
+    pub(crate) fn new(burst_size: u32, remaining_burst_capacity: u32) -> Self {
+        KeySnapshot {
+            burst_size,
+            remaining_burst_capacity,
+        }
+    }
+
+    /// The number of requests the key can make in a burst.
+    pub fn burst_size(&self) -> u32 {
+        self.burst_size
+    }
+
+    /// The number of requests the key can still make before it is limited.
+    pub fn remaining_burst_capacity(&self) -> u32 {
+        self.remaining_burst_capacity
+    }
+}
+
+type KeyHeadersFn<Key> = dyn Fn(&Key, &KeySnapshot, &mut HeaderMap) + Send + Sync;
+
+/// Closure that adds custom headers to the allowed responses of a key.
+pub(crate) struct KeyHeaders<Key>(Arc<KeyHeadersFn<Key>>);
+
+impl<Key> KeyHeaders<Key> {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Key, &KeySnapshot, &mut HeaderMap) + Send + Sync + 'static,
+    {
+        KeyHeaders(Arc::new(f))
+    }
+
+    pub(crate) fn apply(&self, key: &Key, snapshot: &KeySnapshot, headers: &mut HeaderMap) {
+        (self.0)(key, snapshot, headers)
+    }
+}
+
+impl<Key> Clone for KeyHeaders<Key> {
+    fn clone(&self) -> Self {
+        KeyHeaders(self.0.clone())
+    }
+}
+
+impl<Key> PartialEq for KeyHeaders<Key> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<Key> Eq for KeyHeaders<Key> {}
+
+impl<Key> fmt::Debug for KeyHeaders<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("KeyHeaders")
+    }
+}
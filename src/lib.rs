@@ -66,10 +66,10 @@
 //! Instead of using the configuration builder you can use predefined presets.
 //!
 //! + [`GovernorConfig::default()`]: The default configuration which is suitable for most services.
-//! Allows bursts with up to eight requests and replenishes one element after 500ms, based on peer IP.
+//!   Allows bursts with up to eight requests and replenishes one element after 500ms, based on peer IP.
 //!
 //! + [`GovernorConfig::secure()`]: A default configuration for security related services.
-//! Allows bursts with up to two requests and replenishes one element after four seconds, based on peer IP.
+//!   Allows bursts with up to two requests and replenishes one element after four seconds, based on peer IP.
 //!
 //! For example the secure configuration can be used as a short version of this code:
 //!
@@ -83,6 +83,31 @@
 //!     .unwrap();
 //! ```
 //!
+//! With the `serde` feature the builder can be read from the configuration file of the service.
+//! The period is given in milliseconds and `headers` selects the builder of [`use_headers`]:
+//!
+//! ```rust,ignore
+//! use actix_governor::{GovernorConfigBuilder, PeerIpKeyExtractor};
+//! use governor::middleware::NoOpMiddleware;
+//!
+//! #[derive(serde::Deserialize)]
+//! struct Settings {
+//!     rate_limit: GovernorConfigBuilder<PeerIpKeyExtractor, NoOpMiddleware>,
+//! }
+//!
+//! let mut settings: Settings = toml::from_str(
+//!     r#"
+//!     [rate_limit]
+//!     period_ms = 500
+//!     burst_size = 8
+//!     methods = ["GET", "POST"]
+//!     "#,
+//! )?;
+//! let config = settings.rate_limit.finish().unwrap();
+//! ```
+//!
+//! Fields that are missing keep their default value.
+//!
 //! # Customize rate limiting key
 //!
 //! By default, rate limiting is done using the peer IP address (i.e. the IP address of the HTTP client that requested your app: either your user or a reverse proxy, depending on your deployment setup).
@@ -91,11 +116,35 @@
 //! 2. allows you to setup multiple instances of this middleware based on different keys (for example, if you want to apply rate limiting with different rates on IP and API keys at the same time)
 //!
 //! This is achieved by defining a [KeyExtractor] and giving it to a [Governor] instance.
-//! Two ready-to-use key extractors are provided:
-//! - [PeerIpKeyExtractor]: this is the default
+//! These ready-to-use key extractors are provided:
+//! - [PeerIpKeyExtractor]: this is the default, see [`PeerIpKeyExtractor::when_missing`] for requests without a peer address
 //! - [GlobalKeyExtractor]: uses the same key for all incoming requests
+//! - [SmartIpKeyExtractor]: uses the client IP reported by a reverse proxy, see [`SmartIpKeyExtractor::trusted`]
+//! - [IpSubnetKeyExtractor]: uses the subnet of the peer IP, like `/24` or `/64`
+//! - [ApiKeyExtractor]: uses the API key sent in a header like `x-api-key`
+//! - [HostKeyExtractor]: uses the host of the request, for per-tenant quotas
+//! - [UserAgentKeyExtractor]: uses the client IP and the family of its user agent, to separate bots from users behind a NAT
+//! - [CountryKeyExtractor]: uses the client IP with different quotas or blocks per country
+//! - `ClientCertKeyExtractor`: uses the fingerprint of the client certificate of mutual TLS, with the `mtls`, `rustls` or `openssl` feature
+//! - [ExtensionKeyExtractor]: uses a value inserted into the request extensions, like the authenticated user
+//! - [FallbackKeyExtractor]: tries extractors in order, e.g. the API key and then the peer IP
+//! - [NormalizedKeyExtractor]: normalizes the keys of another extractor, e.g. lowercases them or maps `::ffff:1.2.3.4` to `1.2.3.4`
 //!
 //! Check out the [custom_key](https://github.com/AaronErhardt/actix-governor/blob/main/examples/custom_key.rs) example to see how a custom key extractor can be implemented.
+//! Key extractors can use the application state, like a table of tenants registered with `App::app_data`,
+//! see the [custom_key_tenant](https://github.com/AaronErhardt/actix-governor/blob/main/examples/custom_key_tenant.rs) example.
+//!
+//! Key extractors that only read a header or a cookie can be derived with the `derive` feature:
+//!
+//! ```rust,ignore
+//! use actix_governor::KeyExtractor;
+//!
+//! #[derive(Clone, KeyExtractor)]
+//! #[key(header = "x-api-key")]
+//! struct ApiKey;
+//! ```
+//!
+//! Requests without the header or cookie are denied with `401 Unauthorized`.
 //!
 //! # Add x-ratelimit headers
 //!
@@ -103,6 +152,25 @@
 //!
 //! [`use_headers`]: crate::GovernorConfigBuilder::use_headers()
 //!
+//! # Conditional rate limiting
+//!
+//! [`Governor`] is cheap to construct and to clone, so it can be wrapped in
+//! actix-web's [`Condition`](actix_web::middleware::Condition) middleware
+//! to decide whether rate limiting is used when the app is built:
+//!
+//! ```rust
+//! use actix_governor::{Governor, GovernorConfig};
+//! use actix_web::{middleware::Condition, App};
+//!
+//! let config = GovernorConfig::default();
+//! let enabled = std::env::var("RATE_LIMIT").is_ok();
+//!
+//! let app = App::new().wrap(Condition::new(enabled, Governor::new(&config)));
+//! ```
+//!
+//! To turn rate limiting on and off while the server is running, use a [`GovernorSwitch`].
+//! To suspend single keys for a scheduled time window, like during their maintenance, use [`KeyBlocks`].
+//!
 //! # Common pitfalls
 //!
 //! Do not construct the same configuration multiple times, unless explicitly wanted!
@@ -114,31 +182,187 @@
 #[cfg(test)]
 mod tests;
 
+// Lets the tests use the paths of the code generated by `#[derive(KeyExtractor)]`.
+#[cfg(all(test, feature = "derive"))]
+extern crate self as actix_governor;
+
+/// Items used by the code generated by `#[derive(KeyExtractor)]`. Not part of the public API.
+#[cfg(feature = "derive")]
+#[doc(hidden)]
+pub mod __private {
+    pub use crate::derive::{cookie_key, header_key};
+    pub use actix_web::dev::ServiceRequest;
+}
+
 use governor::{
     clock::{DefaultClock, QuantaInstant},
     middleware::{NoOpMiddleware, RateLimitingMiddleware, StateInformationMiddleware},
-    state::keyed::DefaultKeyedStateStore,
-    Quota, RateLimiter,
+    state::{keyed::DefaultKeyedStateStore, InMemoryState, NotKeyed},
+    RateLimiter,
 };
 
-use std::{cell::RefCell, marker::PhantomData, num::NonZeroU32, rc::Rc, sync::Arc, time::Duration};
+use std::{
+    any::Any,
+    cell::RefCell,
+    collections::{hash_map::RandomState, HashMap},
+    fmt,
+    future::Future,
+    hash::BuildHasher,
+    marker::PhantomData,
+    net::IpAddr,
+    num::{NonZeroU32, NonZeroU64},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
-use actix_web::http::Method;
+use actix_web::http::{
+    header::{HeaderMap, HeaderName},
+    Method, StatusCode,
+};
 use actix_web::{body::MessageBody, Error};
 use futures::future;
 
+mod additional;
+mod budget;
+mod capture;
+mod cold_start;
+mod concurrency;
+mod config_error;
+mod cost;
+mod debug_key;
+mod decision;
+mod denial_log;
+mod denial_notes;
+#[cfg(feature = "derive")]
+mod derive;
+mod dialect;
+mod env;
+#[cfg(feature = "exemption")]
+mod exemption;
+mod key_blocks;
 mod key_extractor;
+mod key_headers;
+mod payload;
+mod policy;
+mod proxies;
+mod quota_math;
+mod quota_override;
+mod ramp;
+mod refund;
+mod reload;
+#[cfg(feature = "replay")]
+mod replay;
+mod rules;
+mod scope;
 mod service;
+#[cfg(feature = "serde")]
+mod settings;
+mod singleflight;
+mod streaming;
+mod switch;
+mod time_budget;
 
 type SharedRateLimiter<Key, M> =
     Arc<RateLimiter<Key, DefaultKeyedStateStore<Key>, DefaultClock, M>>;
+/// The limiter of the [global quota](GovernorConfigBuilder::global_quota()).
+type GlobalLimiter<M> = Arc<RateLimiter<NotKeyed, InMemoryState, DefaultClock, M>>;
 
-pub use key_extractor::{GlobalKeyExtractor, KeyExtractor, PeerIpKeyExtractor};
+#[cfg(feature = "derive")]
+pub use actix_governor_derive::KeyExtractor;
+use additional::AdditionalQuotas;
+pub use budget::{BudgetError, Reservation};
+use capture::Captures;
+use cold_start::{ColdStart, ColdStartQuota};
+use concurrency::ConcurrencyLimit;
+use config_error::checked_quota;
+pub use config_error::GovernorConfigError;
+pub use cost::RequestCost;
+use cost::{CostDebts, RequestCosts};
+use debug_key::DebugKey;
+pub use decision::DecisionId;
+use denial_log::{DenialLog, LogSettings};
+use denial_notes::{DenialNotes, NoteResolver};
+use dialect::DialectSelector;
+pub use dialect::HeaderDialect;
+pub use env::EnvConfigError;
+#[cfg(feature = "exemption")]
+pub use exemption::ExemptionTokens;
+#[cfg(feature = "exemption")]
+use exemption::Exemptions;
+pub use key_blocks::KeyBlocks;
+#[cfg(feature = "identity")]
+pub use key_extractor::IdentityKeyExtractor;
+#[cfg(feature = "jwt")]
+pub use key_extractor::JwtClaimKeyExtractor;
+pub use key_extractor::{
+    ApiKeyExtractor, BearerTokenKeyExtractor, CardinalityGuardKeyExtractor, ConnectionId,
+    ConnectionKeyExtractor, CountryKeyExtractor, ExtensionKeyExtractor, FallbackKey,
+    FallbackKeyExtractor, GlobalKeyExtractor, HashedKey, HashedKeyExtractor, HostKey,
+    HostKeyExtractor, IpSubnetKeyExtractor, KeyExtractor, MissingHost, MissingPeer,
+    MissingPeerKeyExtractor, MissingQueryParam, NormalizedKeyExtractor, PeerIpKeyExtractor,
+    QueryParamKey, QueryParamKeyExtractor, RouteScopedKeyExtractor, SampledKeyExtractor,
+    SmartIpKeyExtractor, TrustedIpKeyExtractor, UserAgentKeyExtractor,
+};
+#[cfg(feature = "httpauth")]
+pub use key_extractor::{BasicAuthKeyExtractor, BearerAuthKeyExtractor};
+#[cfg(feature = "mtls")]
+pub use key_extractor::{ClientCertFingerprint, ClientCertKeyExtractor};
+use key_headers::KeyHeaders;
+pub use key_headers::KeySnapshot;
+pub use payload::DeniedPayload;
+pub use policy::{GovernorPolicy, PolicyLabels};
+pub use proxies::{IpNet, ParseIpNetError};
+pub use quota_math::{
+    period_for_window, period_for_window_rounded, requests_per_window, requests_per_window_rounded,
+    QuotaError,
+};
+pub use quota_override::QuotaOverride;
+use quota_override::{OverrideLimiters, QuotaResolver};
+use ramp::Ramp;
+pub use refund::CacheHit;
+use refund::Refunds;
+pub use reload::ReloadableQuota;
+#[cfg(feature = "replay")]
+pub use replay::{ParseRecordError, RecordedRequest, ReplayReport, SimulatedOutcome};
+pub use rules::PathRule;
+pub use scope::ScopePolicy;
+use scope::ScopeTable;
+pub use service::{DenialReason, RateLimitInfo};
+pub use singleflight::{Singleflight, SingleflightMiddleware};
+pub use streaming::{MeteredBody, StreamMeter, StreamMeterMiddleware};
+use streaming::{StreamClassifier, StreamingQuota};
+pub use switch::GovernorSwitch;
+use time_budget::TimeBudget;
 
 const DEFAULT_PERIOD: Duration = Duration::from_millis(500);
 const DEFAULT_BURST_SIZE: u32 = 8;
 
+// The configuration, the middleware factory and the handles returned by them are
+// shared between workers, stored in `web::Data` and moved into spawned tasks.
+// Make sure they stay `Send + Sync` for the provided key extractors.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<GovernorConfig<PeerIpKeyExtractor, NoOpMiddleware>>();
+    assert_send_sync::<GovernorConfig<PeerIpKeyExtractor, StateInformationMiddleware>>();
+    assert_send_sync::<GovernorConfig<GlobalKeyExtractor, NoOpMiddleware>>();
+    assert_send_sync::<GovernorConfig<ConnectionKeyExtractor, NoOpMiddleware>>();
+    assert_send_sync::<GovernorConfigBuilder<PeerIpKeyExtractor, NoOpMiddleware>>();
+    assert_send_sync::<Governor<PeerIpKeyExtractor, NoOpMiddleware>>();
+    assert_send_sync::<Governor<PeerIpKeyExtractor, StateInformationMiddleware>>();
+    assert_send_sync::<Singleflight<PeerIpKeyExtractor>>();
+    assert_send_sync::<GovernorPolicy>();
+    assert_send_sync::<PolicyLabels>();
+    assert_send_sync::<GovernorSwitch>();
+    #[cfg(feature = "exemption")]
+    assert_send_sync::<ExemptionTokens>();
+    assert_send_sync::<QuotaOverride>();
+    assert_send_sync::<KeySnapshot>();
+    assert_send_sync::<DenialReason>();
+};
+
 /// Helper struct for building a configuration for the governor middleware.
 ///
 /// # Example
@@ -170,11 +394,100 @@ const DEFAULT_BURST_SIZE: u32 = 8;
 /// ```
 #[derive(Debug, Eq)]
 pub struct GovernorConfigBuilder<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> {
+    options: BuilderOptions,
+    key_extractor: K,
+    key_headers: Option<KeyHeaders<K::Key>>,
+    note_resolver: Option<NoteResolver<K::Key>>,
+    debug_key: Option<DebugKey<K::Key>>,
+    quota_resolver: Option<QuotaResolver<K::Key>>,
+    middleware: PhantomData<M>,
+}
+
+/// The settings of the builder that don't depend on the key extractor or the middleware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct BuilderOptions {
     period: Duration,
     burst_size: u32,
+    /// Why the last call of `requests_per_period` couldn't compute the period.
+    quota_error: Option<QuotaError>,
     methods: Option<Vec<Method>>,
-    key_extractor: K,
-    middleware: PhantomData<M>,
+    cold_start: Option<ColdStartQuota>,
+    max_concurrency: Option<u32>,
+    max_streams: Option<u32>,
+    free_status_codes: Option<Vec<StatusCode>>,
+    cache_hit_header: Option<HeaderName>,
+    free_head: bool,
+    #[cfg(feature = "exemption")]
+    exemption_tokens: Option<(HeaderName, ExemptionTokens)>,
+    policy_name: Option<String>,
+    policy_labels: Vec<(String, String)>,
+    ramp: Option<Duration>,
+    enforced_millionths: Option<u32>,
+    pre_limit: Option<(Duration, u32)>,
+    exempt_extensions: Option<Vec<String>>,
+    header_dialect: Option<DialectSelector>,
+    denied_payload: Option<DeniedPayload>,
+    log_settings: LogSettings,
+    soft_limit: Option<u32>,
+    streaming: Option<StreamingQuota>,
+    time_budget: Option<(Duration, Duration)>,
+    scopes: Vec<(String, ScopePolicy)>,
+    shadow: bool,
+    method_quotas: Vec<(Method, QuotaOverride)>,
+    rules: Vec<PathRule>,
+    request_costs: Option<RequestCosts>,
+    windows: Vec<QuotaOverride>,
+    global_quota: Option<QuotaOverride>,
+    warmup: Option<Duration>,
+}
+
+impl BuilderOptions {
+    fn set_requests_per_period(&mut self, count: u32, period: Duration) {
+        (self.period, self.quota_error) = match period_for_window_rounded(count, period) {
+            Ok(period) => (period, None),
+            Err(error) => (Duration::ZERO, Some(error)),
+        };
+        self.burst_size = count;
+    }
+}
+
+impl Default for BuilderOptions {
+    fn default() -> Self {
+        BuilderOptions {
+            period: DEFAULT_PERIOD,
+            burst_size: DEFAULT_BURST_SIZE,
+            quota_error: None,
+            methods: None,
+            cold_start: None,
+            max_concurrency: None,
+            max_streams: None,
+            free_status_codes: None,
+            cache_hit_header: None,
+            free_head: false,
+            #[cfg(feature = "exemption")]
+            exemption_tokens: None,
+            policy_name: None,
+            policy_labels: Vec::new(),
+            ramp: None,
+            enforced_millionths: None,
+            pre_limit: None,
+            exempt_extensions: None,
+            header_dialect: None,
+            denied_payload: None,
+            log_settings: LogSettings::default(),
+            soft_limit: None,
+            streaming: None,
+            time_budget: None,
+            scopes: Vec::new(),
+            shadow: false,
+            method_quotas: Vec::new(),
+            rules: Vec::new(),
+            request_costs: None,
+            windows: Vec::new(),
+            global_quota: None,
+            warmup: None,
+        }
+    }
 }
 
 impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> Clone
@@ -182,10 +495,12 @@ impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> Clone
 {
     fn clone(&self) -> Self {
         Self {
-            period: self.period,
-            burst_size: self.burst_size,
-            methods: self.methods.clone(),
+            options: self.options.clone(),
             key_extractor: self.key_extractor.clone(),
+            key_headers: self.key_headers.clone(),
+            note_resolver: self.note_resolver.clone(),
+            debug_key: self.debug_key.clone(),
+            quota_resolver: self.quota_resolver.clone(),
             middleware: self.middleware,
         }
     }
@@ -195,10 +510,12 @@ impl<K: KeyExtractor + PartialEq, M: RateLimitingMiddleware<QuantaInstant>> Part
     for GovernorConfigBuilder<K, M>
 {
     fn eq(&self, other: &Self) -> bool {
-        self.period == other.period
-            && self.burst_size == other.burst_size
-            && self.methods == other.methods
+        self.options == other.options
             && self.key_extractor == other.key_extractor
+            && self.key_headers == other.key_headers
+            && self.note_resolver == other.note_resolver
+            && self.debug_key == other.debug_key
+            && self.quota_resolver == other.quota_resolver
     }
 }
 
@@ -214,10 +531,12 @@ impl Default for GovernorConfigBuilder<PeerIpKeyExtractor, NoOpMiddleware> {
 impl<M: RateLimitingMiddleware<QuantaInstant>> GovernorConfigBuilder<PeerIpKeyExtractor, M> {
     pub fn const_default() -> Self {
         GovernorConfigBuilder {
-            period: DEFAULT_PERIOD,
-            burst_size: DEFAULT_BURST_SIZE,
-            methods: None,
+            options: BuilderOptions::default(),
             key_extractor: PeerIpKeyExtractor,
+            key_headers: None,
+            note_resolver: None,
+            debug_key: None,
+            quota_resolver: None,
             middleware: PhantomData,
         }
     }
@@ -225,28 +544,59 @@ impl<M: RateLimitingMiddleware<QuantaInstant>> GovernorConfigBuilder<PeerIpKeyEx
     ///
     /// **The interval must not be zero.**
     pub fn const_period(mut self, duration: Duration) -> Self {
-        self.period = duration;
+        self.options.period = duration;
         self
     }
     /// Set the interval after which one element of the quota is replenished in seconds.
     ///
     /// **The interval must not be zero.**
     pub fn const_per_second(mut self, seconds: u64) -> Self {
-        self.period = Duration::from_secs(seconds);
+        self.options.period = Duration::from_secs(seconds);
         self
     }
     /// Set the interval after which one element of the quota is replenished in milliseconds.
     ///
     /// **The interval must not be zero.**
     pub fn const_per_millisecond(mut self, milliseconds: u64) -> Self {
-        self.period = Duration::from_millis(milliseconds);
+        self.options.period = Duration::from_millis(milliseconds);
         self
     }
     /// Set the interval after which one element of the quota is replenished in nanoseconds.
     ///
     /// **The interval must not be zero.**
     pub fn const_per_nanosecond(mut self, nanoseconds: u64) -> Self {
-        self.period = Duration::from_nanos(nanoseconds);
+        self.options.period = Duration::from_nanos(nanoseconds);
+        self
+    }
+    /// Set the interval after which one element of the quota is replenished in minutes.
+    ///
+    /// **The interval must not be zero.**
+    pub fn const_per_minute(mut self, minutes: u64) -> Self {
+        self.options.period = Duration::from_secs(minutes.saturating_mul(60));
+        self
+    }
+    /// Set the interval after which one element of the quota is replenished in hours.
+    ///
+    /// **The interval must not be zero.**
+    pub fn const_per_hour(mut self, hours: u64) -> Self {
+        self.options.period = Duration::from_secs(hours.saturating_mul(3600));
+        self
+    }
+    /// Set the interval after which one element of the quota is replenished in days.
+    ///
+    /// **The interval must not be zero.**
+    pub fn const_per_day(mut self, days: u64) -> Self {
+        self.options.period = Duration::from_secs(days.saturating_mul(86400));
+        self
+    }
+    /// Allow `count` requests per `period`, like 100 requests per hour.
+    ///
+    /// This sets the burst size to `count` and replenishes one element of the quota
+    /// every `period / count`, rounded up to the next nanosecond.
+    ///
+    /// **The count and the period must not be zero.**
+    pub fn const_requests_per_period(mut self, count: u32, period: Duration) -> Self {
+        self.options.set_requests_per_period(count, period);
         self
     }
     /// Set quota size that defines how many requests can occur
@@ -255,7 +605,7 @@ impl<M: RateLimitingMiddleware<QuantaInstant>> GovernorConfigBuilder<PeerIpKeyEx
     ///
     /// **The burst_size must not be zero.**
     pub fn const_burst_size(mut self, burst_size: u32) -> Self {
-        self.burst_size = burst_size;
+        self.options.burst_size = burst_size;
         self
     }
 }
@@ -265,28 +615,59 @@ impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> GovernorConfigBu
     ///
     /// **The interval must not be zero.**
     pub fn period(&mut self, duration: Duration) -> &mut Self {
-        self.period = duration;
+        self.options.period = duration;
         self
     }
     /// Set the interval after which one element of the quota is replenished in seconds.
     ///
     /// **The interval must not be zero.**
     pub fn per_second(&mut self, seconds: u64) -> &mut Self {
-        self.period = Duration::from_secs(seconds);
+        self.options.period = Duration::from_secs(seconds);
         self
     }
     /// Set the interval after which one element of the quota is replenished in milliseconds.
     ///
     /// **The interval must not be zero.**
     pub fn per_millisecond(&mut self, milliseconds: u64) -> &mut Self {
-        self.period = Duration::from_millis(milliseconds);
+        self.options.period = Duration::from_millis(milliseconds);
         self
     }
     /// Set the interval after which one element of the quota is replenished in nanoseconds.
     ///
     /// **The interval must not be zero.**
     pub fn per_nanosecond(&mut self, nanoseconds: u64) -> &mut Self {
-        self.period = Duration::from_nanos(nanoseconds);
+        self.options.period = Duration::from_nanos(nanoseconds);
+        self
+    }
+    /// Set the interval after which one element of the quota is replenished in minutes.
+    ///
+    /// **The interval must not be zero.**
+    pub fn per_minute(&mut self, minutes: u64) -> &mut Self {
+        self.options.period = Duration::from_secs(minutes.saturating_mul(60));
+        self
+    }
+    /// Set the interval after which one element of the quota is replenished in hours.
+    ///
+    /// **The interval must not be zero.**
+    pub fn per_hour(&mut self, hours: u64) -> &mut Self {
+        self.options.period = Duration::from_secs(hours.saturating_mul(3600));
+        self
+    }
+    /// Set the interval after which one element of the quota is replenished in days.
+    ///
+    /// **The interval must not be zero.**
+    pub fn per_day(&mut self, days: u64) -> &mut Self {
+        self.options.period = Duration::from_secs(days.saturating_mul(86400));
+        self
+    }
+    /// Allow `count` requests per `period`, like 100 requests per hour.
+    ///
+    /// This sets the burst size to `count` and replenishes one element of the quota
+    /// every `period / count`, rounded up to the next nanosecond.
+    ///
+    /// **The count and the period must not be zero.**
+    pub fn requests_per_period(&mut self, count: u32, period: Duration) -> &mut Self {
+        self.options.set_requests_per_period(count, period);
         self
     }
     /// Set quota size that defines how many requests can occur
@@ -295,89 +676,1322 @@ impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> GovernorConfigBu
     ///
     /// **The burst_size must not be zero.**
     pub fn burst_size(&mut self, burst_size: u32) -> &mut Self {
-        self.burst_size = burst_size;
+        self.options.burst_size = burst_size;
         self
     }
 
     /// Set the HTTP methods this configuration should apply to.
     /// By default this is all methods.
     pub fn methods(&mut self, methods: Vec<Method>) -> &mut Self {
-        self.methods = Some(methods);
+        self.options.methods = Some(methods);
+        self
+    }
+
+    /// Use `quota` for the requests with one of the given methods,
+    /// e.g. to throttle mutating requests more aggressively than reads:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, QuotaOverride};
+    /// use actix_web::http::Method;
+    /// use std::time::Duration;
+    ///
+    /// let writes = QuotaOverride::new(Duration::from_secs(6), 10).unwrap();
+    /// let config = GovernorConfigBuilder::default()
+    ///     // GET and all other methods: 100 requests per minute
+    ///     .requests_per_period(100, Duration::from_secs(60))
+    ///     // POST, PUT and DELETE: 10 requests per minute
+    ///     .method_quota(&[Method::POST, Method::PUT, Method::DELETE], writes)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The keys get an independent bucket for every distinct quota, like with [`QuotaOverride`],
+    /// so the methods that share a quota share a bucket. Later calls override the quota of
+    /// a method. Only methods that are limited, see [`methods`], use their quota.
+    ///
+    /// The quota of a method replaces the quotas of [`key_quota`] and [`ReloadableQuota`],
+    /// but not the quota of a [`scope`] or a [`QuotaOverride`] of the request.
+    ///
+    /// [`methods`]: crate::GovernorConfigBuilder::methods()
+    /// [`key_quota`]: crate::GovernorConfigBuilder::key_quota()
+    /// [`scope`]: crate::GovernorConfigBuilder::scope()
+    pub fn method_quota(&mut self, methods: &[Method], quota: QuotaOverride) -> &mut Self {
+        self.options
+            .method_quotas
+            .extend(methods.iter().map(|method| (method.clone(), quota)));
+        self
+    }
+
+    /// Also limit the keys to `quota`, e.g. to combine a burst limit with a sustained limit:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, QuotaOverride};
+    /// use std::time::Duration;
+    ///
+    /// // 1000 requests per hour
+    /// let hourly = QuotaOverride::new(Duration::from_millis(3600), 1000).unwrap();
+    /// let config = GovernorConfigBuilder::default()
+    ///     // 20 requests per second
+    ///     .per_millisecond(50)
+    ///     .burst_size(20)
+    ///     .additional_quota(hourly)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// A request has to pass the quota of the configuration, or the quota that replaces it,
+    /// and all additional quotas. The quotas are checked in order, so a request that is denied
+    /// by an additional quota still counts against the quotas checked before.
+    /// The rate limiting headers show the quota with the fewest remaining requests,
+    /// or the quota that denied the request. Once an additional quota denied a key, the requests
+    /// of the key are denied until that quota allows them again, without counting against
+    /// any quota, and `Retry-After` waits for the most restrictive quota.
+    ///
+    /// The additional quotas share one key store per quota, instead of the separate
+    /// stores and headers of stacked [`Governor`] middleware.
+    pub fn additional_quota(&mut self, quota: QuotaOverride) -> &mut Self {
+        self.options.windows.push(quota);
+        self
+    }
+
+    /// Limit the requests of all keys together to `quota`, as a ceiling for the whole service
+    /// on top of the quota of each key:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, QuotaOverride};
+    /// use std::time::Duration;
+    ///
+    /// // 2000 requests per second for the whole service
+    /// let ceiling = QuotaOverride::new(Duration::from_micros(500), 2000).unwrap();
+    /// let config = GovernorConfigBuilder::default()
+    ///     // 10 requests per second for each IP
+    ///     .per_millisecond(100)
+    ///     .burst_size(10)
+    ///     .global_quota(ceiling)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The global quota is checked after the quotas of the key, so requests that are denied
+    /// for their key don't count against it, and is shown in the rate limiting headers
+    /// like an [additional quota](crate::GovernorConfigBuilder::additional_quota()).
+    /// Requests that are denied by the global quota don't count against the quotas of their key
+    /// either: their cost is given back to the key, like the unused requests of a
+    /// [`Reservation`], so clients don't use up their own quota during an overload of the
+    /// whole service.
+    /// Unlike [`GlobalKeyExtractor`], which replaces the keys, the keys keep their own quota.
+    pub fn global_quota(&mut self, quota: QuotaOverride) -> &mut Self {
+        self.options.global_quota = Some(quota);
+        self
+    }
+
+    /// Count every request as `cost` requests, for a configuration that only covers
+    /// expensive endpoints like searches or exports.
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::num::NonZeroU32;
+    ///
+    /// // A burst of 20 allows 4 searches
+    /// let config = GovernorConfigBuilder::default()
+    ///     .burst_size(20)
+    ///     .request_cost(NonZeroU32::new(5).unwrap())
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// A [`RequestCost`] in the request extensions takes precedence.
+    pub fn request_cost(&mut self, cost: NonZeroU32) -> &mut Self {
+        self.request_cost_with(move |_| cost)
+    }
+
+    /// Select the cost of each request, so expensive requests use up the quota faster:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::num::NonZeroU32;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .burst_size(20)
+    ///     .request_cost_with(|req| {
+    ///         let cost = if req.path().starts_with("/search") { 5 } else { 1 };
+    ///         NonZeroU32::new(cost).unwrap()
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The cost is checked at once, so a request is denied if the key has fewer requests left
+    /// than it costs. Costs above the burst size use up the whole burst.
+    /// A [`RequestCost`] in the request extensions takes precedence.
+    pub fn request_cost_with<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest) -> NonZeroU32 + Send + Sync + 'static,
+    {
+        self.options.request_costs = Some(RequestCosts::new(f));
+        self
+    }
+
+    /// Count every started `bytes_per_request` of the `Content-Length` of a request as one request,
+    /// so uploads are limited by their size instead of their number:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::num::NonZeroU64;
+    ///
+    /// // 10 MiB per second, in bursts of up to 50 MiB
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_millisecond(10)
+    ///     .burst_size(512)
+    ///     .request_cost_per_body_size(NonZeroU64::new(100 * 1024).unwrap())
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The `Content-Length` is charged up front. Requests without a valid `Content-Length`
+    /// header, like chunked uploads, are checked as one request, and the bytes that the handler
+    /// reads beyond what was charged are added to the cost of the next requests of the key,
+    /// like a raised [`RequestCost`]. Replaces the costs set by [`request_cost`]
+    /// or [`request_cost_with`].
+    ///
+    /// [`request_cost`]: crate::GovernorConfigBuilder::request_cost()
+    /// [`request_cost_with`]: crate::GovernorConfigBuilder::request_cost_with()
+    pub fn request_cost_per_body_size(&mut self, bytes_per_request: NonZeroU64) -> &mut Self {
+        self.options.request_costs = Some(RequestCosts::per_body_size(bytes_per_request));
+        self
+    }
+
+    /// Start keys that were never seen before under a stricter quota.
+    ///
+    /// New keys are checked against both the regular quota and the cold start quota
+    /// until they made requests for the whole `probation` period without being denied.
+    /// Every denial by the cold start quota restarts the probation period of the key.
+    /// Keys that made no request for another probation period after their probation ended
+    /// count as new again.
+    /// This blunts attackers that frequently rotate their IP addresses.
+    ///
+    /// **The interval, burst size and probation period must not be zero.**
+    pub fn cold_start(
+        &mut self,
+        period: Duration,
+        burst_size: u32,
+        probation: Duration,
+    ) -> &mut Self {
+        self.options.cold_start = Some(ColdStartQuota {
+            period,
+            burst_size,
+            probation,
+        });
+        self
+    }
+
+    /// Set how many requests of the same key may be processed at the same time.
+    /// Further requests of that key are denied until one of the in-flight requests finished,
+    /// independent of the remaining quota.
+    /// By default the concurrency is not limited.
+    ///
+    /// A request counts as in-flight until the handler returned its response. With the
+    /// [`StreamMeter`] middleware it counts until its response body ended, so streams are
+    /// limited as well.
+    ///
+    /// With [`use_headers`] the `x-ratelimit-concurrency-limit` and `x-ratelimit-concurrency-remaining`
+    /// headers are added as well.
+    ///
+    /// **The maximum concurrency must not be zero.**
+    ///
+    /// [`use_headers`]: crate::GovernorConfigBuilder::use_headers()
+    pub fn max_concurrency(&mut self, max_concurrency: u32) -> &mut Self {
+        self.options.max_concurrency = Some(max_concurrency);
+        self
+    }
+
+    /// Set how many HTTP/2 streams of the same key may be open at the same time.
+    ///
+    /// This protects against clients that open hundreds of multiplexed streams
+    /// while staying within the allowed request rate.
+    /// It works like [`max_concurrency`], but only counts HTTP/2 requests. Use the [`StreamMeter`]
+    /// middleware to keep a stream counted until its response body ended.
+    /// By default the number of streams is not limited.
+    ///
+    /// **The maximum number of streams must not be zero.**
+    ///
+    /// [`max_concurrency`]: crate::GovernorConfigBuilder::max_concurrency()
+    pub fn max_concurrent_streams(&mut self, max_streams: u32) -> &mut Self {
+        self.options.max_streams = Some(max_streams);
+        self
+    }
+
+    /// Check a cheap limit based on the peer IP before the key is extracted.
+    ///
+    /// Requests that exceed it are denied without running the key extractor,
+    /// so floods of unauthenticated requests are rejected before expensive work like JWT parsing
+    /// happens. Requests that pass are then checked against the precise quota of their key.
+    ///
+    /// **The interval and burst size must not be zero.**
+    pub fn pre_limit(&mut self, period: Duration, burst_size: u32) -> &mut Self {
+        self.options.pre_limit = Some((period, burst_size));
+        self
+    }
+
+    /// Override the policy for the requests below a path, like `/api` or `/api/login`.
+    ///
+    /// One governor at the top of the app then applies the policy of the innermost scope of
+    /// each request, instead of separate governors on nested scopes that don't know each other.
+    /// Scopes inherit the settings they don't override from their enclosing scope:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, QuotaOverride, ScopePolicy};
+    /// use std::time::Duration;
+    ///
+    /// let strict = QuotaOverride::new(Duration::from_secs(20), 3).unwrap();
+    /// let config = GovernorConfigBuilder::default()
+    ///     .scope("/static", ScopePolicy::new().exempt())
+    ///     .scope("/api", ScopePolicy::new().quota(strict))
+    ///     // Inherits the strict quota of /api
+    ///     .scope("/api/health", ScopePolicy::new())
+    ///     .scope("/static/uploads", ScopePolicy::new().limited())
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Paths match whole segments, so `/api` covers `/api/users` but not `/apis`.
+    /// The quota of a scope replaces the quotas of [`key_quota`] and [`ReloadableQuota`],
+    /// but not a [`QuotaOverride`] of the request.
+    ///
+    /// [`key_quota`]: crate::GovernorConfigBuilder::key_quota()
+    pub fn scope(&mut self, path: &str, policy: ScopePolicy) -> &mut Self {
+        self.options.scopes.push((path.to_owned(), policy));
+        self
+    }
+
+    /// Use the quota of a [`PathRule`] for the requests that match its pattern,
+    /// so a large API gets its quotas from one table instead of many nested scopes:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, PathRule, QuotaOverride};
+    /// use actix_web::http::Method;
+    /// use std::time::Duration;
+    ///
+    /// let search = QuotaOverride::new(Duration::from_secs(2), 5).unwrap();
+    /// let uploads = QuotaOverride::new(Duration::from_secs(30), 2).unwrap();
+    /// let config = GovernorConfigBuilder::default()
+    ///     .rule(PathRule::glob("/api/*/search", search))
+    ///     .rule(PathRule::glob("/files/**", uploads).methods(vec![Method::POST, Method::PUT]))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The rules are evaluated in the order they were added and the first matching rule wins.
+    /// With the `regex` feature, rules can also match a regular expression, see `PathRule::regex`.
+    /// The quota of a rule replaces the quotas of a [`scope`], the [`method_quota`], [`key_quota`]
+    /// and [`ReloadableQuota`], but not a [`QuotaOverride`] of the request.
+    ///
+    /// [`scope`]: crate::GovernorConfigBuilder::scope()
+    /// [`method_quota`]: crate::GovernorConfigBuilder::method_quota()
+    /// [`key_quota`]: crate::GovernorConfigBuilder::key_quota()
+    pub fn rule(&mut self, rule: PathRule) -> &mut Self {
+        self.options.rules.push(rule);
+        self
+    }
+
+    /// Only log the requests that would be denied instead of denying them,
+    /// to try a new configuration against production traffic before enforcing it.
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(2)
+    ///     .burst_size(10)
+    ///     .shadow_mode()
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The requests still consume quota and get the rate limiting headers,
+    /// so the `x-ratelimit-remaining` header shows `0` where the request would be denied.
+    /// The would-be denials are logged like denials with the `log` feature,
+    /// and appear as `shadowed` in [captured](crate::GovernorConfig::start_capture()) timelines.
+    /// This covers the quota, the pre-limit and the concurrency, streaming and time budgets.
+    pub fn shadow_mode(&mut self) -> &mut Self {
+        self.options.shadow = true;
+        self
+    }
+
+    /// Run in [shadow mode](crate::GovernorConfigBuilder::shadow_mode()) for the `warmup` duration
+    /// after the configuration was created, so clients that retry their queued requests right
+    /// after a deploy don't get denied by the new, empty key store.
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(2)
+    ///     .burst_size(10)
+    ///     .warmup(Duration::from_secs(30))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The requests during the warmup still consume quota, the buckets replenish as usual.
+    pub fn warmup(&mut self, warmup: Duration) -> &mut Self {
+        self.options.warmup = Some(warmup);
+        self
+    }
+
+    /// Don't limit requests for files with one of the given extensions, like static assets.
+    /// The extensions are matched case-insensitively, with or without the leading dot.
+    ///
+    /// This way the governor middleware can wrap the whole app
+    /// without counting every stylesheet or image against the quota of the page loads:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .exempt_extensions(["css", "js", "png", "svg", "woff2"])
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Exempted requests get the `x-ratelimit-whitelisted` header like requests with methods that aren't limited.
+    pub fn exempt_extensions<I, S>(&mut self, extensions: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.options.exempt_extensions = Some(
+            extensions
+                .into_iter()
+                .map(|extension| {
+                    extension
+                        .as_ref()
+                        .trim_start_matches('.')
+                        .to_ascii_lowercase()
+                })
+                .collect(),
+        );
+        self
+    }
+
+    /// Enforce the quota gradually after the configuration was created,
+    /// so a newly deployed, tighter quota doesn't deny a large fraction of the users at once.
+    ///
+    /// The share of keys whose requests are denied grows linearly from none to all keys
+    /// within the `ramp` duration. A key that is enforced stays enforced.
+    /// Requests of keys that are not enforced yet still consume quota, but are let through.
+    pub fn slow_start(&mut self, ramp: Duration) -> &mut Self {
+        self.options.ramp = Some(ramp);
+        self
+    }
+
+    /// Enforce the quota only for `percent` percent of the keys, to try a new quota
+    /// on a small share of the traffic before it is rolled out to all keys.
+    ///
+    /// A key is selected by its hash, so it is always enforced or never. Requests of keys
+    /// that are not enforced still consume quota and get the rate limiting headers,
+    /// but are let through and their denials are recorded like in
+    /// [shadow mode](crate::GovernorConfigBuilder::shadow_mode()).
+    /// Combined with [`slow_start`] the share grows up to `percent`.
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// // Canary the quota on 5% of the keys
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(10)
+    ///     .enforce_percentage(5)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Values above 100 are treated as 100.
+    ///
+    /// [`slow_start`]: crate::GovernorConfigBuilder::slow_start()
+    pub fn enforce_percentage(&mut self, percent: u8) -> &mut Self {
+        self.options.enforced_millionths = Some(u32::from(percent.min(100)) * 10_000);
+        self
+    }
+
+    /// Enforce the quota only for a `fraction` between 0 and 1 of the keys,
+    /// to ramp a new quota up from a small share of the users to all of them.
+    ///
+    /// Like [`enforce_percentage`], with a finer resolution for small shares:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// // Enforce the quota for 0.5% of the keys
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(10)
+    ///     .enforce_fraction(0.005)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The denials of the keys that are not enforced are recorded like in
+    /// [shadow mode](crate::GovernorConfigBuilder::shadow_mode()). Values are clamped
+    /// to the range from 0 to 1, `NaN` enforces no keys.
+    ///
+    /// [`enforce_percentage`]: crate::GovernorConfigBuilder::enforce_percentage()
+    pub fn enforce_fraction(&mut self, fraction: f32) -> &mut Self {
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        self.options.enforced_millionths = Some((f64::from(fraction) * 1_000_000.0).round() as u32);
+        self
+    }
+
+    /// Set status codes of responses that don't consume quota, e.g. `304 Not Modified`.
+    ///
+    /// Requests are charged before they are processed, so the quota of a request
+    /// with a free response is given back afterwards. The key can then make an
+    /// additional request, even if its quota is used up.
+    ///
+    /// The refunds only apply to the quota that charged the request, like a [`QuotaOverride`]
+    /// or the quota of a [`scope`](crate::GovernorConfigBuilder::scope()).
+    /// A key can't collect more than the burst size of that quota, and a refund expires after
+    /// its period, so refunds can't be saved up while the quota replenishes.
+    pub fn free_status_codes(&mut self, status_codes: Vec<StatusCode>) -> &mut Self {
+        self.options.free_status_codes = Some(status_codes);
+        self
+    }
+
+    /// Don't consume quota for responses that were served from a cache.
+    ///
+    /// A response counts as cache hit if the cache middleware inserted [`CacheHit`]
+    /// into its extensions or if the value of the given header starts with `HIT`,
+    /// like `x-cache: HIT` or `cf-cache-status: HIT`.
+    /// The quota is given back the same way as for [`free_status_codes`].
+    ///
+    /// [`free_status_codes`]: crate::GovernorConfigBuilder::free_status_codes()
+    pub fn free_cache_hits(&mut self, header_name: HeaderName) -> &mut Self {
+        self.options.cache_hit_header = Some(header_name);
+        self
+    }
+
+    /// Don't consume quota for `HEAD` requests.
+    ///
+    /// Monitoring tools often send frequent `HEAD` requests, which shouldn't use up the quota
+    /// of the users sharing their key, e.g. behind a NAT. `HEAD` requests are still checked
+    /// and get the rate limit headers, but their quota is given back the same way as for
+    /// [`free_status_codes`]. So they are denied while the quota of the key is used up.
+    ///
+    /// [`free_status_codes`]: crate::GovernorConfigBuilder::free_status_codes()
+    pub fn free_head_requests(&mut self) -> &mut Self {
+        self.options.free_head = true;
+        self
+    }
+
+    /// Add custom headers to the allowed responses of a key, based on its state.
+    ///
+    /// The closure receives the key, its [`KeySnapshot`] and the headers of the response.
+    /// It is only called if the middleware reports the state of the key, which requires
+    /// [`use_headers`] or a custom middleware. Requests that are not rate limited
+    /// because of their method don't have a key, so the closure isn't called for them either.
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use actix_web::http::header::{HeaderName, HeaderValue};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .use_headers()
+    ///     .key_headers(|_key, snapshot, headers| {
+    ///         if snapshot.remaining_burst_capacity() < snapshot.burst_size() / 4 {
+    ///             headers.insert(
+    ///                 HeaderName::from_static("x-throttle-hint"),
+    ///                 HeaderValue::from_static("reduce"),
+    ///             );
+    ///         }
+    ///     })
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`use_headers`]: crate::GovernorConfigBuilder::use_headers()
+    pub fn key_headers<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&K::Key, &KeySnapshot, &mut HeaderMap) + Send + Sync + 'static,
+    {
+        self.key_headers = Some(KeyHeaders::new(f));
+        self
+    }
+
+    /// Select the quota of each key, e.g. by the plan of a tenant.
+    ///
+    /// Keys for which the closure returns a [`QuotaOverride`] are checked against that quota,
+    /// all others against the configured one. This way a single governor serves all tiers:
+    ///
+    /// ```rust
+    /// use actix_governor::{ApiKeyExtractor, GovernorConfigBuilder, QuotaOverride};
+    /// use std::time::Duration;
+    ///
+    /// let premium = QuotaOverride::new(Duration::from_millis(100), 50).unwrap();
+    /// let config = GovernorConfigBuilder::default()
+    ///     .key_extractor(ApiKeyExtractor::default())
+    ///     .key_quota(move |key: &String| key.starts_with("premium-").then_some(premium))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// A [`QuotaOverride`] inserted into the request extensions by an earlier middleware
+    /// takes precedence over the quota of the key.
+    ///
+    /// **Every distinct quota creates a rate limiter that lives as long as the configuration,
+    /// so return them from a small set of plans.**
+    ///
+    pub fn key_quota<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&K::Key) -> Option<QuotaOverride> + Send + Sync + 'static,
+    {
+        self.quota_resolver = Some(QuotaResolver::new(f));
+        self
+    }
+
+    /// Echo the key of each request in the `x-ratelimit-key` header of its response,
+    /// including denials, formatted with [`Debug`](std::fmt::Debug).
+    ///
+    /// This shows which bucket a request was counted against, e.g. to find out whether a proxy
+    /// in front of the app forwards the client IP. The key can contain personal data or secrets
+    /// like API keys, so only enable it while debugging, or use [`debug_key_header_hashed`]:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let mut builder = GovernorConfigBuilder::default();
+    /// if cfg!(debug_assertions) {
+    ///     builder.debug_key_header();
+    /// }
+    /// let config = builder.finish().unwrap();
+    /// ```
+    ///
+    /// Requests that were denied before the key was extracted don't get the header.
+    ///
+    /// [`debug_key_header_hashed`]: crate::GovernorConfigBuilder::debug_key_header_hashed()
+    pub fn debug_key_header(&mut self) -> &mut Self
+    where
+        K::Key: std::fmt::Debug,
+    {
+        self.debug_key = Some(DebugKey::new(|key: &K::Key| format!("{key:?}")));
+        self
+    }
+
+    /// Like [`debug_key_header`], but echo a hash of the key instead of the key itself.
+    ///
+    /// Requests with the same key get the same hash, so it still shows whether two requests
+    /// share a bucket without revealing the key. The hash is keyed with a random secret,
+    /// so it changes when the app restarts.
+    ///
+    /// [`debug_key_header`]: crate::GovernorConfigBuilder::debug_key_header()
+    pub fn debug_key_header_hashed(&mut self) -> &mut Self {
+        let hasher = RandomState::new();
+        self.debug_key = Some(DebugKey::new(move |key: &K::Key| {
+            format!("{:016x}", hasher.hash_one(key))
+        }));
+        self
+    }
+
+    /// Select the names of the rate limiting headers per request, see [`HeaderDialect`].
+    ///
+    /// This allows to move a large base of clients to new header names gradually,
+    /// for example based on a header announcing the capabilities of the client or on the API version:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, HeaderDialect};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .header_dialect(|req| {
+    ///         if req.path().starts_with("/v2/") || req.headers().contains_key("x-ietf-ratelimit") {
+    ///             HeaderDialect::Ietf
+    ///         } else {
+    ///             HeaderDialect::Legacy
+    ///         }
+    ///     })
+    ///     .use_headers()
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn header_dialect<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&ServiceRequest) -> HeaderDialect + Send + Sync + 'static,
+    {
+        self.options.header_dialect = Some(DialectSelector::new(f));
+        self
+    }
+
+    /// Set what happens to the body of denied requests, see [`DeniedPayload`].
+    ///
+    /// By default the body is left to actix-web. With a policy, large uploads that are denied
+    /// anyway can't keep the server busy:
+    ///
+    /// ```rust
+    /// use actix_governor::{DeniedPayload, GovernorConfigBuilder};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .denied_payload(DeniedPayload::Drain(64 * 1024))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Denied requests are rejected before the body is read in any case, this only decides
+    /// whether the connection is kept alive.
+    pub fn denied_payload(&mut self, policy: DeniedPayload) -> &mut Self {
+        self.options.denied_payload = Some(policy);
+        self
+    }
+
+    /// Set the level of the log messages of denials and log only one in `one_in` denials.
+    ///
+    /// By default every denial is logged at the info level. Every configuration
+    /// has its own settings, so a noisy policy can be sampled while denials of a policy
+    /// that guards logins are logged as warnings:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let search = GovernorConfigBuilder::default()
+    ///     .policy_name("search")
+    ///     .log_denials(log::Level::Debug, 100)
+    ///     .finish()
+    ///     .unwrap();
+    /// let login = GovernorConfigBuilder::default()
+    ///     .policy_name("login")
+    ///     .log_denials(log::Level::Warn, 1)
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// `0` turns the log messages of denials off.
+    #[cfg(feature = "log")]
+    pub fn log_denials(&mut self, level: log::Level, one_in: u32) -> &mut Self {
+        self.options.log_settings = LogSettings { level, one_in };
+        self
+    }
+
+    /// Warn clients that are about to exceed the quota with a `Warning` header,
+    /// once no more than `remaining` requests of the burst are left.
+    ///
+    /// Well-behaved clients can slow down before their requests are denied.
+    /// The header is only added together with the headers of [`use_headers`]:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .burst_size(10)
+    ///     .soft_limit(2)
+    ///     .use_headers()
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The responses with at most two remaining requests then contain
+    /// `warning: 199 - "rate limit nearly exceeded, 2 requests remaining"`.
+    /// actix-web can't send `103 Early Hints`, so the warning arrives with the response.
+    ///
+    /// [`use_headers`]: crate::GovernorConfigBuilder::use_headers()
+    pub fn soft_limit(&mut self, remaining: u32) -> &mut Self {
+        self.options.soft_limit = Some(remaining);
+        self
+    }
+
+    /// Limit streaming requests, like server-sent events or long polling, by the time they are
+    /// open instead of their number.
+    ///
+    /// Requests for which `is_streaming` returns `true` don't count against the request quota.
+    /// Instead each key may keep streams open for `budget` in total per `period`,
+    /// e.g. ten minutes per hour:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .streaming_budget(
+    ///         |req| req.path().starts_with("/events"),
+    ///         Duration::from_secs(10 * 60),
+    ///         Duration::from_secs(60 * 60),
+    ///     )
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// A stream is charged when its response body ends or the client disconnects, so a key
+    /// can exceed its budget with the streams it already opened. This needs the [`StreamMeter`]
+    /// middleware, which measures the response bodies. Its following streams are
+    /// denied until the excess time is paid off. Combine it with [`max_concurrency`] to limit
+    /// how many streams a key can have open at the same time, [`StreamMeter`] keeps them counted
+    /// until their body ended.
+    ///
+    /// **The budget and the period must not be zero.**
+    ///
+    /// [`max_concurrency`]: crate::GovernorConfigBuilder::max_concurrency()
+    pub fn streaming_budget<F>(
+        &mut self,
+        is_streaming: F,
+        budget: Duration,
+        period: Duration,
+    ) -> &mut Self
+    where
+        F: Fn(&ServiceRequest) -> bool + Send + Sync + 'static,
+    {
+        self.options.streaming = Some(StreamingQuota {
+            classifier: StreamClassifier::new(is_streaming),
+            budget,
+            period,
+        });
+        self
+    }
+
+    /// Limit the time the handlers spend on the requests of a key, e.g. 30 seconds per minute.
+    ///
+    /// Cheap requests like a search with a broad filter can keep the server busy for a long time.
+    /// The time from admitting a request until its response is ready is charged to its key
+    /// after the response, and requests of keys that used up their budget are denied
+    /// until the time is paid off, at the rate of `budget` per `period`:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::time::Duration;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .time_budget(Duration::from_secs(30), Duration::from_secs(60))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// The budget applies in addition to the request quota. Requests that are running already
+    /// are not charged yet, so combine it with [`max_concurrency`] to keep a key from starting
+    /// many expensive requests at once. Streaming requests are limited by
+    /// [`streaming_budget`] instead.
+    ///
+    /// **The budget and the period must not be zero.**
+    ///
+    /// [`max_concurrency`]: crate::GovernorConfigBuilder::max_concurrency()
+    /// [`streaming_budget`]: crate::GovernorConfigBuilder::streaming_budget()
+    pub fn time_budget(&mut self, budget: Duration, period: Duration) -> &mut Self {
+        self.options.time_budget = Some((budget, period));
+        self
+    }
+
+    /// Add a note of the key to the body of the responses that deny a request because of the quota,
+    /// e.g. `your plan allows 100 requests per minute, upgrade at ...`.
+    ///
+    /// The note is resolved asynchronously, for example from a database, and cached for `ttl`.
+    /// Keys without a note get the regular body. Notes are added as `note` field:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::{net::IpAddr, time::Duration};
+    ///
+    /// async fn lookup_plan(ip: IpAddr) -> Option<String> {
+    ///     Some("your plan allows 100 requests per minute, upgrade at https://example.com".to_owned())
+    /// }
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .denial_notes(Duration::from_secs(300), |ip| lookup_plan(*ip))
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    pub fn denial_notes<F, Fut>(&mut self, ttl: Duration, resolver: F) -> &mut Self
+    where
+        F: Fn(&K::Key) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Option<String>> + 'static,
+    {
+        self.note_resolver = Some(NoteResolver::new(ttl, resolver));
+        self
+    }
+
+    /// Exempt requests that present a valid [`ExemptionTokens`] token in the given header
+    /// from rate limiting, e.g. for trusted partners.
+    ///
+    /// Requests with an invalid, expired or replayed token are limited as usual.
+    /// With the `logger` feature every accepted and rejected token is logged.
+    #[cfg(feature = "exemption")]
+    pub fn exemption_tokens(
+        &mut self,
+        header_name: HeaderName,
+        tokens: ExemptionTokens,
+    ) -> &mut Self {
+        self.options.exemption_tokens = Some((header_name, tokens));
+        self
+    }
+
+    /// Name the policy of this configuration, so stacked policies can be told apart.
+    ///
+    /// See [`PolicyLabels`] for where the name shows up.
+    pub fn policy_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.options.policy_name = Some(name.into());
+        self
+    }
+
+    /// Add a label to the policy of this configuration, see [`PolicyLabels`].
+    pub fn policy_label(&mut self, key: impl Into<String>, value: impl Into<String>) -> &mut Self {
+        self.options.policy_labels.push((key.into(), value.into()));
         self
     }
 
     /// Set the key extractor this configuration should use.
     /// By default this is using the [PeerIpKeyExtractor].
+    ///
+    /// The closures that use the key, set with [`key_headers`], [`key_quota`], [`denial_notes`]
+    /// and [`debug_key_header`], are kept if the new extractor has the same key type.
+    /// Otherwise they can't be called with the new keys and are removed.
+    ///
+    /// [`key_headers`]: crate::GovernorConfigBuilder::key_headers()
+    /// [`key_quota`]: crate::GovernorConfigBuilder::key_quota()
+    /// [`denial_notes`]: crate::GovernorConfigBuilder::denial_notes()
+    /// [`debug_key_header`]: crate::GovernorConfigBuilder::debug_key_header()
     pub fn key_extractor<K2: KeyExtractor>(
         &mut self,
         key_extractor: K2,
-    ) -> GovernorConfigBuilder<K2, M> {
+    ) -> GovernorConfigBuilder<K2, M>
+    where
+        K::Key: 'static,
+        K2::Key: 'static,
+    {
         GovernorConfigBuilder {
-            period: self.period,
-            burst_size: self.burst_size,
-            methods: self.methods.to_owned(),
+            options: self.options.clone(),
             key_extractor,
+            key_headers: self.key_headers.clone().and_then(with_key_type),
+            note_resolver: self.note_resolver.clone().and_then(with_key_type),
+            debug_key: self.debug_key.clone().and_then(with_key_type),
+            quota_resolver: self.quota_resolver.clone().and_then(with_key_type),
             middleware: PhantomData,
         }
     }
 
+    /// Normalize the keys of the configured key extractor, see [NormalizedKeyExtractor].
+    ///
+    /// For example, count IPv6-mapped IPv4 addresses like `::ffff:1.2.3.4` of a dual-stack
+    /// socket against the bucket of `1.2.3.4`:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    /// use std::net::IpAddr;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .normalize_keys(|ip: IpAddr| ip.to_canonical())
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn normalize_keys<F>(
+        &mut self,
+        normalize: F,
+    ) -> GovernorConfigBuilder<NormalizedKeyExtractor<K>, M>
+    where
+        K::Key: 'static,
+        F: Fn(K::Key) -> K::Key + Send + Sync + 'static,
+    {
+        let key_extractor = NormalizedKeyExtractor::new(self.key_extractor.clone(), normalize);
+        self.key_extractor(key_extractor)
+    }
+
+    /// Normalize the keys of the configured key extractor with the steps of [NormalizedKeyExtractor]:
+    ///
+    /// ```rust
+    /// use actix_governor::{ApiKeyExtractor, GovernorConfigBuilder};
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .key_extractor(ApiKeyExtractor::default())
+    ///     .normalize_keys_with(|keys| keys.trim().lowercase().truncate(64))
+    ///     .hash_keys()
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    pub fn normalize_keys_with<F>(
+        &mut self,
+        steps: F,
+    ) -> GovernorConfigBuilder<NormalizedKeyExtractor<K>, M>
+    where
+        K::Key: 'static,
+        F: FnOnce(NormalizedKeyExtractor<K>) -> NormalizedKeyExtractor<K>,
+    {
+        let key_extractor = steps(NormalizedKeyExtractor::steps(self.key_extractor.clone()));
+        self.key_extractor(key_extractor)
+    }
+
+    /// Hash the keys of the configured key extractor, see [HashedKeyExtractor].
+    ///
+    /// Raw keys like IP addresses or user ids are then not kept in memory after the request,
+    /// which helps with privacy regulations like the GDPR:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(2)
+    ///     .hash_keys()
+    ///     .finish()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// Like [`key_extractor`], this removes the closures that use the raw key, so call it first.
+    ///
+    /// [`key_extractor`]: crate::GovernorConfigBuilder::key_extractor()
+    pub fn hash_keys(&mut self) -> GovernorConfigBuilder<HashedKeyExtractor<K>, M>
+    where
+        K::Key: 'static,
+    {
+        let key_extractor = HashedKeyExtractor::new(self.key_extractor.clone());
+        self.key_extractor(key_extractor)
+    }
+
     /// Set x-ratelimit headers to response, the headers is
     /// - `x-ratelimit-limit`       - Request limit
     /// - `x-ratelimit-remaining`   - The number of requests left for the time window
     /// - `x-ratelimit-after`       - Number of seconds in which the API will become available after its rate limit has been exceeded
-    /// - `x-ratelimit-whitelisted` - If the request method not in methods, this header will be add it, use [`methods`] to add methods
+    /// - `x-ratelimit-policy`      - The name of the policy, if set with [`policy_name`]
+    /// - `x-ratelimit-whitelisted` - If the request method not in methods or the request presents an exemption token, this header will be add it, use [`methods`] to add methods
     ///
     /// By default `x-ratelimit-after` is enabled, with [`use_headers`] will enable `x-ratelimit-limit`, `x-ratelimit-whitelisted` and `x-ratelimit-remaining`
     ///
+    /// If several governors with headers wrap the same route, the limit and remaining pair of the
+    /// governor with the fewest remaining requests is sent, `x-ratelimit-policy` is repeated for
+    /// each governor and `x-ratelimit-whitelisted` is only sent if none of them limited the request.
+    ///
     /// [`methods`]: crate::GovernorConfigBuilder::methods()
+    /// [`policy_name`]: crate::GovernorConfigBuilder::policy_name()
     pub fn use_headers(&mut self) -> GovernorConfigBuilder<K, StateInformationMiddleware> {
         GovernorConfigBuilder {
-            period: self.period,
-            burst_size: self.burst_size,
-            methods: self.methods.to_owned(),
+            options: self.options.clone(),
             key_extractor: self.key_extractor.clone(),
+            key_headers: self.key_headers.clone(),
+            note_resolver: self.note_resolver.clone(),
+            debug_key: self.debug_key.clone(),
+            quota_resolver: self.quota_resolver.clone(),
+            middleware: PhantomData,
+        }
+    }
+
+    /// Use a custom [`RateLimitingMiddleware`] of the governor crate.
+    ///
+    /// The middleware has to implement [`RateLimitInfo`], which defines
+    /// which rate limit headers are added to the responses.
+    pub fn with_middleware<M2: RateLimitInfo>(&mut self) -> GovernorConfigBuilder<K, M2> {
+        GovernorConfigBuilder {
+            options: self.options.clone(),
+            key_extractor: self.key_extractor.clone(),
+            key_headers: self.key_headers.clone(),
+            note_resolver: self.note_resolver.clone(),
+            debug_key: self.debug_key.clone(),
+            quota_resolver: self.quota_resolver.clone(),
             middleware: PhantomData,
         }
     }
 
     /// Finish building the configuration and return the configuration for the middleware.
-    /// Returns `None` if either burst size or period interval are zero.
+    /// Returns `None` if either burst size or period interval are zero
+    /// or one of the optional limits is invalid, see [`try_finish`] for the reason.
+    ///
+    /// [`try_finish`]: crate::GovernorConfigBuilder::try_finish()
     pub fn finish(&mut self) -> Option<GovernorConfig<K, M>> {
-        if self.burst_size != 0 && self.period.as_nanos() != 0 {
-            Some(GovernorConfig {
-                key_extractor: self.key_extractor.clone(),
-                limiter: Arc::new(
-                    RateLimiter::keyed(
-                        Quota::with_period(self.period)
-                            .unwrap()
-                            .allow_burst(NonZeroU32::new(self.burst_size).unwrap()),
-                    )
-                    .with_middleware::<M>(),
+        self.try_finish().ok()
+    }
+
+    /// Like [`finish`], but returns why the configuration is invalid:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, GovernorConfigError};
+    ///
+    /// let error = GovernorConfigBuilder::default()
+    ///     .burst_size(0)
+    ///     .try_finish()
+    ///     .unwrap_err();
+    /// assert_eq!(error, GovernorConfigError::ZeroBurstSize);
+    /// ```
+    ///
+    /// [`finish`]: crate::GovernorConfigBuilder::finish()
+    pub fn try_finish(&mut self) -> Result<GovernorConfig<K, M>, GovernorConfigError> {
+        if self.options.period.is_zero() {
+            // The period is only zero because of the error, unless it was set again since.
+            return Err(match self.options.quota_error {
+                Some(QuotaError::ZeroRequests) => GovernorConfigError::ZeroBurstSize,
+                Some(QuotaError::Overflow) => GovernorConfigError::Overflow,
+                _ => GovernorConfigError::ZeroPeriod,
+            });
+        }
+        if self.options.burst_size == 0 {
+            return Err(GovernorConfigError::ZeroBurstSize);
+        }
+        let quota = checked_quota(self.options.period, self.options.burst_size)
+            .ok_or(GovernorConfigError::Overflow)?;
+        let cold_start = match &self.options.cold_start {
+            Some(cold_start) => Some(Arc::new(
+                cold_start
+                    .build()
+                    .ok_or(GovernorConfigError::InvalidColdStart)?,
+            )),
+            None => None,
+        };
+        let concurrency = match self.options.max_concurrency {
+            Some(0) => return Err(GovernorConfigError::ZeroMaxConcurrency),
+            Some(max_concurrency) => Some(Arc::new(ConcurrencyLimit::new(max_concurrency))),
+            None => None,
+        };
+        let streams = match self.options.max_streams {
+            Some(0) => return Err(GovernorConfigError::ZeroMaxStreams),
+            Some(max_streams) => Some(Arc::new(ConcurrencyLimit::new(max_streams))),
+            None => None,
+        };
+        let streaming = match &self.options.streaming {
+            Some(quota) => Some((
+                quota.classifier.clone(),
+                Arc::new(
+                    TimeBudget::new(quota.budget, quota.period)
+                        .ok_or(GovernorConfigError::InvalidStreamingBudget)?,
                 ),
-                methods: self.methods.clone(),
+            )),
+            None => None,
+        };
+        let time_budget = match self.options.time_budget {
+            Some((budget, period)) => Some(Arc::new(
+                TimeBudget::new(budget, period).ok_or(GovernorConfigError::InvalidTimeBudget)?,
+            )),
+            None => None,
+        };
+        let refunds = Arc::new(Refunds::new(
+            self.options.free_status_codes.clone().unwrap_or_default(),
+            self.options.cache_hit_header.clone(),
+            self.options.free_head,
+            self.options.period,
+            self.options.burst_size,
+        ));
+        let pre_limiter = match self.options.pre_limit {
+            Some((period, burst_size)) => Some(Arc::new(RateLimiter::keyed(
+                checked_quota(period, burst_size).ok_or(GovernorConfigError::InvalidPreLimit)?,
+            ))),
+            None => None,
+        };
+        let windows = self
+            .options
+            .windows
+            .iter()
+            .map(|window| {
+                checked_quota(window.period(), window.burst_size())
+                    .map(|quota| {
+                        (
+                            *window,
+                            Arc::new(RateLimiter::keyed(quota).with_middleware::<M>()),
+                        )
+                    })
+                    .ok_or(GovernorConfigError::Overflow)
             })
+            .collect::<Result<Vec<_>, _>>()?;
+        let global_limiter = match self.options.global_quota {
+            Some(quota) => Some(Arc::new(
+                RateLimiter::direct(
+                    checked_quota(quota.period(), quota.burst_size())
+                        .ok_or(GovernorConfigError::Overflow)?,
+                )
+                .with_middleware::<M>(),
+            )),
+            None => None,
+        };
+        let labels = if self.options.policy_name.is_some() || !self.options.policy_labels.is_empty()
+        {
+            Some(PolicyLabels::new(
+                self.options.policy_name.clone(),
+                self.options.policy_labels.clone(),
+            ))
         } else {
             None
+        };
+        let scopes = (!self.options.scopes.is_empty())
+            .then(|| Arc::new(ScopeTable::new(&self.options.scopes)));
+        // Later quotas of a method replace the earlier ones.
+        let mut method_quotas: Vec<(Method, QuotaOverride)> = Vec::new();
+        for (method, quota) in &self.options.method_quotas {
+            match method_quotas
+                .iter_mut()
+                .find(|(declared, _)| declared == method)
+            {
+                Some((_, declared)) => *declared = *quota,
+                None => method_quotas.push((method.clone(), *quota)),
+            }
         }
+        Ok(GovernorConfig {
+            state: Arc::new(GovernorState {
+                key_extractor: self.key_extractor.clone(),
+                limiter: Arc::new(RateLimiter::keyed(quota).with_middleware::<M>()),
+                methods: self.options.methods.clone(),
+                cold_start,
+                concurrency,
+                streams,
+                refunds,
+                overrides: Arc::new(OverrideLimiters::new()),
+                key_headers: self.key_headers.clone(),
+                denial_notes: self
+                    .note_resolver
+                    .clone()
+                    .map(|resolver| Arc::new(DenialNotes::new(resolver))),
+                #[cfg(feature = "exemption")]
+                exemptions: self
+                    .options
+                    .exemption_tokens
+                    .clone()
+                    .map(|(header_name, tokens)| Arc::new(Exemptions::new(header_name, tokens))),
+                labels: labels.clone(),
+                ramp: (self.options.ramp.is_some() || self.options.enforced_millionths.is_some())
+                    .then(|| {
+                        Arc::new(Ramp::new(
+                            self.options.ramp,
+                            self.options.enforced_millionths.unwrap_or(1_000_000),
+                        ))
+                    }),
+                pre_limiter,
+                dialects: self.options.header_dialect.clone(),
+                denied_payload: self.options.denied_payload,
+                denial_log: Arc::new(DenialLog::new(self.options.log_settings)),
+                soft_limit: self.options.soft_limit,
+                streaming,
+                time_budget,
+                debug_key: self.debug_key.clone(),
+                quota_resolver: self.quota_resolver.clone(),
+                captures: Arc::new(Captures::new()),
+                cost_debts: Arc::new(CostDebts::new()),
+                scopes: scopes.clone(),
+                enabled: GovernorSwitch::default(),
+                shadow: self.options.shadow,
+                method_quotas: (!method_quotas.is_empty())
+                    .then(|| Arc::new(method_quotas.iter().cloned().collect())),
+                rules: (!self.options.rules.is_empty())
+                    .then(|| Arc::new(self.options.rules.clone())),
+                request_costs: self.options.request_costs.clone(),
+                windows: (!windows.is_empty()).then(|| Arc::new(AdditionalQuotas::new(windows))),
+                global_limiter,
+                warmup_until: self.options.warmup.map(|warmup| Instant::now() + warmup),
+                policy: GovernorPolicy {
+                    period: self.options.period,
+                    burst_size: self.options.burst_size,
+                    methods: self.options.methods.clone(),
+                    // Depends on the middleware, see `GovernorConfig::policy`.
+                    use_headers: false,
+                    cold_start: self.options.cold_start,
+                    max_concurrency: self.options.max_concurrency,
+                    max_concurrent_streams: self.options.max_streams,
+                    free_status_codes: self.options.free_status_codes.clone().unwrap_or_default(),
+                    cache_hit_header: self.options.cache_hit_header.clone(),
+                    labels,
+                    scopes,
+                    rules: self.options.rules.clone(),
+                    method_quotas,
+                    additional_quotas: self.options.windows.clone(),
+                    global_quota: self.options.global_quota,
+                    exempt_extensions: self.options.exempt_extensions.clone(),
+                    shadow: self.options.shadow,
+                    ramp: self.options.ramp,
+                    enforced_millionths: self.options.enforced_millionths,
+                    warmup: self.options.warmup,
+                },
+            }),
+        })
     }
 }
 
-#[derive(Debug)]
 /// Configuration for the Governor middleware.
+///
+/// The configuration is `Send + Sync` as long as the key extractor and its key are,
+/// so it can be stored in `web::Data` or moved into background tasks.
+/// Clones share the same rate limiter state.
 pub struct GovernorConfig<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> {
+    state: Arc<GovernorState<K, M>>,
+}
+
+impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> fmt::Debug
+    for GovernorConfig<K, M>
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GovernorConfig")
+            .field("policy", &self.state.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The state shared by a configuration and the governors and middleware created from it.
+struct GovernorState<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> {
     key_extractor: K,
     limiter: SharedRateLimiter<K::Key, M>,
     methods: Option<Vec<Method>>,
+    cold_start: Option<Arc<ColdStart<K::Key>>>,
+    concurrency: Option<Arc<ConcurrencyLimit<K::Key>>>,
+    streams: Option<Arc<ConcurrencyLimit<K::Key>>>,
+    refunds: Arc<Refunds<K::Key>>,
+    overrides: Arc<OverrideLimiters<K::Key, M>>,
+    key_headers: Option<KeyHeaders<K::Key>>,
+    denial_notes: Option<Arc<DenialNotes<K::Key>>>,
+    #[cfg(feature = "exemption")]
+    exemptions: Option<Arc<Exemptions<K::Key>>>,
+    labels: Option<PolicyLabels>,
+    ramp: Option<Arc<Ramp>>,
+    pre_limiter: Option<SharedRateLimiter<IpAddr, NoOpMiddleware>>,
+    dialects: Option<DialectSelector>,
+    denied_payload: Option<DeniedPayload>,
+    #[cfg_attr(not(feature = "log"), allow(dead_code))]
+    denial_log: Arc<DenialLog>,
+    soft_limit: Option<u32>,
+    streaming: Option<(StreamClassifier, Arc<TimeBudget<K::Key>>)>,
+    time_budget: Option<Arc<TimeBudget<K::Key>>>,
+    debug_key: Option<DebugKey<K::Key>>,
+    quota_resolver: Option<QuotaResolver<K::Key>>,
+    captures: Arc<Captures<K::Key>>,
+    cost_debts: Arc<CostDebts<K::Key>>,
+    scopes: Option<Arc<ScopeTable>>,
+    enabled: GovernorSwitch,
+    shadow: bool,
+    method_quotas: Option<Arc<HashMap<Method, QuotaOverride>>>,
+    rules: Option<Arc<Vec<PathRule>>>,
+    request_costs: Option<RequestCosts>,
+    windows: Option<Arc<AdditionalQuotas<K::Key, M>>>,
+    global_limiter: Option<GlobalLimiter<M>>,
+    warmup_until: Option<Instant>,
+    policy: GovernorPolicy,
 }
 
 impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> Clone for GovernorConfig<K, M> {
     fn clone(&self) -> Self {
         GovernorConfig {
-            key_extractor: self.key_extractor.clone(),
-            limiter: self.limiter.clone(),
-            methods: self.methods.clone(),
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<K: KeyExtractor, M: RateLimitInfo> GovernorConfig<K, M> {
+    /// Describe the limits this configuration enforces, see [`GovernorPolicy`].
+    pub fn policy(&self) -> GovernorPolicy {
+        GovernorPolicy {
+            use_headers: M::USE_HEADERS,
+            ..self.state.policy.clone()
         }
     }
+
+    /// Turn rate limiting of all governors created from this configuration on or off.
+    ///
+    /// While it is off, requests are passed through without consuming quota,
+    /// for example to lift the limits during an incident without redeploying:
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfig;
+    ///
+    /// let config = GovernorConfig::default();
+    /// config.set_enabled(false);
+    /// assert!(!config.is_enabled());
+    /// ```
+    ///
+    /// Clones of the configuration share the state. Use a [`GovernorSwitch`] to toggle
+    /// a single governor.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.state.enabled.set(enabled);
+    }
+
+    /// Whether rate limiting is on, see [`set_enabled`](Self::set_enabled).
+    pub fn is_enabled(&self) -> bool {
+        self.state.enabled.is_enabled()
+    }
 }
 
 impl Default for GovernorConfig<PeerIpKeyExtractor, NoOpMiddleware> {
@@ -388,6 +2002,12 @@ impl Default for GovernorConfig<PeerIpKeyExtractor, NoOpMiddleware> {
     }
 }
 
+/// The closure of a key as the closure of another key extractor, if it has the same key type.
+fn with_key_type<T: 'static, U: 'static>(closure: T) -> Option<U> {
+    let closure: Box<dyn Any> = Box::new(closure);
+    closure.downcast().ok().map(|closure| *closure)
+}
+
 impl<M: RateLimitingMiddleware<QuantaInstant>> GovernorConfig<PeerIpKeyExtractor, M> {
     /// A default configuration for security related services.
     /// Allows bursts with up to two requests and replenishes one element after four seconds, based on peer IP.
@@ -396,11 +2016,12 @@ impl<M: RateLimitingMiddleware<QuantaInstant>> GovernorConfig<PeerIpKeyExtractor
     /// yet allows to quickly retype a wrong password once before the quota is exceeded.
     pub fn secure() -> Self {
         GovernorConfigBuilder {
-            period: Duration::from_secs(4),
-            burst_size: 2,
-            methods: None,
-            key_extractor: PeerIpKeyExtractor,
-            middleware: PhantomData,
+            options: BuilderOptions {
+                period: Duration::from_secs(4),
+                burst_size: 2,
+                ..BuilderOptions::default()
+            },
+            ..GovernorConfigBuilder::const_default()
         }
         .finish()
         .unwrap()
@@ -409,70 +2030,88 @@ impl<M: RateLimitingMiddleware<QuantaInstant>> GovernorConfig<PeerIpKeyExtractor
 
 /// Governor middleware factory.
 pub struct Governor<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> {
-    key_extractor: K,
-    limiter: SharedRateLimiter<K::Key, M>,
-    methods: Option<Vec<Method>>,
+    state: Arc<GovernorState<K, M>>,
+    switch: Option<GovernorSwitch>,
+    reloadable_quota: Option<ReloadableQuota>,
+    key_blocks: Option<KeyBlocks<K::Key>>,
+}
+
+impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> Clone for Governor<K, M> {
+    fn clone(&self) -> Self {
+        Governor {
+            state: self.state.clone(),
+            switch: self.switch.clone(),
+            reloadable_quota: self.reloadable_quota.clone(),
+            key_blocks: self.key_blocks.clone(),
+        }
+    }
 }
 
 impl<K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> Governor<K, M> {
     /// Create new governor middleware factory from configuration.
     pub fn new(config: &GovernorConfig<K, M>) -> Self {
         Governor {
-            key_extractor: config.key_extractor.clone(),
-            limiter: config.limiter.clone(),
-            methods: config.methods.clone(),
+            state: config.state.clone(),
+            switch: None,
+            reloadable_quota: None,
+            key_blocks: None,
         }
     }
-}
 
-impl<S, B, K> Transform<S, ServiceRequest> for Governor<K, NoOpMiddleware>
-where
-    K: KeyExtractor,
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
-    B: MessageBody,
-{
-    type Response = ServiceResponse<B>;
-    type Error = Error;
-    type Transform = GovernorMiddleware<S, K, NoOpMiddleware>;
-    type InitError = ();
-    type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+    /// Only rate limit requests while the switch is enabled.
+    ///
+    /// See [`GovernorSwitch`] for toggling rate limiting at runtime.
+    pub fn with_switch(mut self, switch: GovernorSwitch) -> Self {
+        self.switch = Some(switch);
+        self
+    }
 
-    fn new_transform(&self, service: S) -> Self::Future {
-        future::ok(GovernorMiddleware::<S, K, NoOpMiddleware> {
-            service: Rc::new(RefCell::new(service)),
-            key_extractor: self.key_extractor.clone(),
-            limiter: self.limiter.clone(),
-            methods: self.methods.clone(),
-        })
+    /// Use the quota of the handle instead of the configured quota while it is set.
+    ///
+    /// See [`ReloadableQuota`] for changing the quota at runtime.
+    pub fn with_reloadable_quota(mut self, quota: ReloadableQuota) -> Self {
+        self.reloadable_quota = Some(quota);
+        self
+    }
+
+    /// Deny the requests of the keys that are blocked by the handle.
+    ///
+    /// See [`KeyBlocks`] for blocking keys at runtime.
+    pub fn with_key_blocks(mut self, blocks: KeyBlocks<K::Key>) -> Self {
+        self.key_blocks = Some(blocks);
+        self
     }
 }
 
-impl<S, B, K> Transform<S, ServiceRequest> for Governor<K, StateInformationMiddleware>
+impl<S, B, K, M> Transform<S, ServiceRequest> for Governor<K, M>
 where
     K: KeyExtractor,
+    K::Key: 'static,
+    M: RateLimitInfo,
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     B: MessageBody,
-    <S as Service<ServiceRequest>>::Future: Unpin,
 {
-    type Response = ServiceResponse<B>;
+    type Response = S::Response;
     type Error = Error;
-    type Transform = GovernorMiddleware<S, K, StateInformationMiddleware>;
+    type Transform = GovernorMiddleware<S, K, M>;
     type InitError = ();
     type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        future::ok(GovernorMiddleware::<S, K, StateInformationMiddleware> {
+        future::ok(GovernorMiddleware::<S, K, M> {
             service: Rc::new(RefCell::new(service)),
-            key_extractor: self.key_extractor.clone(),
-            limiter: self.limiter.clone(),
-            methods: self.methods.clone(),
+            state: self.state.clone(),
+            switch: self.switch.clone(),
+            reloadable_quota: self.reloadable_quota.clone(),
+            key_blocks: self.key_blocks.clone(),
         })
     }
 }
 
 pub struct GovernorMiddleware<S, K: KeyExtractor, M: RateLimitingMiddleware<QuantaInstant>> {
     service: std::rc::Rc<std::cell::RefCell<S>>,
-    key_extractor: K,
-    limiter: SharedRateLimiter<K::Key, M>,
-    methods: Option<Vec<Method>>,
+    state: Arc<GovernorState<K, M>>,
+    switch: Option<GovernorSwitch>,
+    reloadable_quota: Option<ReloadableQuota>,
+    key_blocks: Option<KeyBlocks<K::Key>>,
 }
@@ -0,0 +1,84 @@
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::http::{header, ConnectionType};
+use actix_web::{error, Error, HttpMessage};
+use futures::future::{FutureExt, LocalBoxFuture};
+use futures::StreamExt;
+
+/// What to do with the body of a request that is denied by the governor middleware.
+///
+/// Denied requests are never passed to the handler, so their body is not read.
+/// Without a policy it is up to actix-web what happens to an unread upload.
+/// Select a policy with [`denied_payload`](crate::GovernorConfigBuilder::denied_payload())
+/// to make sure that large uploads that are denied anyway, like multipart forms,
+/// don't keep the server busy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DeniedPayload {
+    /// Close the connection after the denial if the request has a body.
+    /// The client has to reconnect, but the body is never read.
+    Close,
+    /// Read and discard up to the given number of bytes of the body, so the connection can be
+    /// kept alive for small bodies. The connection is closed if the body is larger.
+    Drain(usize),
+}
+
+impl DeniedPayload {
+    /// Apply the policy to the denial of the request.
+    pub(crate) fn reject(
+        self,
+        req: &mut ServiceRequest,
+        denial: LocalBoxFuture<'static, Error>,
+    ) -> LocalBoxFuture<'static, Error> {
+        if !has_body(req) {
+            return denial;
+        }
+        match self {
+            DeniedPayload::Close => denial.map(close).boxed_local(),
+            DeniedPayload::Drain(limit) => {
+                let payload = req.take_payload();
+                async move {
+                    let drained = drain(payload, limit).await;
+                    let error = denial.await;
+                    if drained {
+                        error
+                    } else {
+                        close(error)
+                    }
+                }
+                .boxed_local()
+            }
+        }
+    }
+}
+
+/// Whether the request announces a body.
+fn has_body(req: &ServiceRequest) -> bool {
+    let headers = req.headers();
+    headers.contains_key(header::TRANSFER_ENCODING)
+        || headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|length| length.to_str().ok())
+            .and_then(|length| length.parse::<u64>().ok())
+            .is_some_and(|length| length > 0)
+}
+
+/// Read and discard the payload. Returns `false` if it is larger than the limit or broken.
+async fn drain(mut payload: Payload, limit: usize) -> bool {
+    let mut read = 0;
+    while let Some(chunk) = payload.next().await {
+        match chunk {
+            Ok(chunk) if read + chunk.len() <= limit => read += chunk.len(),
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Rebuild the error with a response that closes the connection.
+fn close(error: Error) -> Error {
+    let mut response = error.error_response();
+    response
+        .head_mut()
+        .set_connection_type(ConnectionType::Close);
+    error::InternalError::from_response(error, response).into()
+}
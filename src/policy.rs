@@ -0,0 +1,622 @@
+use actix_web::http::{
+    header::{HeaderName, HeaderValue},
+    Method, StatusCode,
+};
+use actix_web::{body::BoxBody, HttpRequest, HttpResponse, Responder};
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use crate::cold_start::ColdStartQuota;
+use crate::scope::ScopeTable;
+use crate::service::escape_json;
+use crate::{PathRule, QuotaOverride, ScopePolicy};
+
+/// Machine-readable description of the limits a configuration enforces.
+///
+/// Get it with [`GovernorConfig::policy`](crate::GovernorConfig::policy()).
+/// It can be returned from a handler to publish the live configuration as JSON,
+/// so clients and API gateways can derive their retry logic from it:
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfigBuilder};
+/// use actix_web::{web, App};
+///
+/// let config = GovernorConfigBuilder::default().finish().unwrap();
+/// let policy = config.policy();
+///
+/// let app = App::new()
+///     .route(
+///         "/rate-limits",
+///         web::get().to(move || {
+///             let policy = policy.clone();
+///             async move { policy }
+///         }),
+///     )
+///     .wrap(Governor::new(&config));
+/// ```
+///
+/// The policy covers the settings of the configuration. Quotas that are selected while
+/// the requests are processed, by [`key_quota`](crate::GovernorConfigBuilder::key_quota()),
+/// the key extractor, a [`QuotaOverride`] of an earlier middleware or a
+/// [`ReloadableQuota`](crate::ReloadableQuota), as well as request costs and exemption tokens,
+/// are not part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GovernorPolicy {
+    pub(crate) period: Duration,
+    pub(crate) burst_size: u32,
+    pub(crate) methods: Option<Vec<Method>>,
+    pub(crate) use_headers: bool,
+    pub(crate) cold_start: Option<ColdStartQuota>,
+    pub(crate) max_concurrency: Option<u32>,
+    pub(crate) max_concurrent_streams: Option<u32>,
+    pub(crate) free_status_codes: Vec<StatusCode>,
+    pub(crate) cache_hit_header: Option<HeaderName>,
+    pub(crate) labels: Option<PolicyLabels>,
+    pub(crate) scopes: Option<Arc<ScopeTable>>,
+    pub(crate) rules: Vec<PathRule>,
+    pub(crate) method_quotas: Vec<(Method, QuotaOverride)>,
+    pub(crate) additional_quotas: Vec<QuotaOverride>,
+    pub(crate) global_quota: Option<QuotaOverride>,
+    pub(crate) exempt_extensions: Option<Vec<String>>,
+    pub(crate) shadow: bool,
+    pub(crate) ramp: Option<Duration>,
+    pub(crate) enforced_millionths: Option<u32>,
+    pub(crate) warmup: Option<Duration>,
+}
+
+/// How the policy treats the requests of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Route {
+    /// The requests are not rate limited.
+    Exempt,
+    /// The requests are limited by their own quota, or by the configured quota if it is `None`.
+    Limited(Option<QuotaOverride>),
+}
+
+impl GovernorPolicy {
+    /// The interval after which one element of the quota is replenished.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The number of requests a key can make in a burst.
+    pub fn burst_size(&self) -> u32 {
+        self.burst_size
+    }
+
+    /// The HTTP methods that are rate limited, `None` if all methods are.
+    pub fn methods(&self) -> Option<&[Method]> {
+        self.methods.as_deref()
+    }
+
+    /// Whether the `x-ratelimit-*` headers are added to the responses.
+    pub fn use_headers(&self) -> bool {
+        self.use_headers
+    }
+
+    /// The maximum number of requests of a key that are processed at the same time.
+    pub fn max_concurrency(&self) -> Option<u32> {
+        self.max_concurrency
+    }
+
+    /// The maximum number of open HTTP/2 streams of a key.
+    pub fn max_concurrent_streams(&self) -> Option<u32> {
+        self.max_concurrent_streams
+    }
+
+    /// The status codes of responses that don't consume quota.
+    pub fn free_status_codes(&self) -> &[StatusCode] {
+        &self.free_status_codes
+    }
+
+    /// The header that marks cache hits, which don't consume quota.
+    pub fn cache_hit_header(&self) -> Option<&HeaderName> {
+        self.cache_hit_header.as_ref()
+    }
+
+    /// The name and labels of the policy.
+    pub fn labels(&self) -> Option<&PolicyLabels> {
+        self.labels.as_ref()
+    }
+
+    /// The scopes with their settings merged with the ones of their enclosing scopes,
+    /// longest path first.
+    pub fn scopes(&self) -> &[(String, ScopePolicy)] {
+        self.scopes
+            .as_deref()
+            .map(ScopeTable::scopes)
+            .unwrap_or_default()
+    }
+
+    /// The path rules, the first matching rule selects the quota.
+    pub fn rules(&self) -> &[PathRule] {
+        &self.rules
+    }
+
+    /// The quotas of the methods that have their own.
+    pub fn method_quotas(&self) -> &[(Method, QuotaOverride)] {
+        &self.method_quotas
+    }
+
+    /// The quotas every key has to pass in addition to its own.
+    pub fn additional_quotas(&self) -> &[QuotaOverride] {
+        &self.additional_quotas
+    }
+
+    /// The quota of all keys together.
+    pub fn global_quota(&self) -> Option<QuotaOverride> {
+        self.global_quota
+    }
+
+    /// The file extensions whose requests are not rate limited.
+    pub fn exempt_extensions(&self) -> Option<&[String]> {
+        self.exempt_extensions.as_deref()
+    }
+
+    /// Whether the denials are only logged, see [`shadow_mode`](crate::GovernorConfigBuilder::shadow_mode()).
+    pub fn shadow_mode(&self) -> bool {
+        self.shadow
+    }
+
+    /// The period over which the enforcement ramps up to all keys,
+    /// see [`slow_start`](crate::GovernorConfigBuilder::slow_start()).
+    pub fn slow_start(&self) -> Option<Duration> {
+        self.ramp
+    }
+
+    /// The fraction of the keys the quota is enforced for,
+    /// see [`enforce_fraction`](crate::GovernorConfigBuilder::enforce_fraction()).
+    pub fn enforced_fraction(&self) -> Option<f32> {
+        self.enforced_millionths
+            .map(|millionths| millionths as f32 / 1_000_000.0)
+    }
+
+    /// The period after startup in which the denials are only logged,
+    /// see [`warmup`](crate::GovernorConfigBuilder::warmup()).
+    pub fn warmup(&self) -> Option<Duration> {
+        self.warmup
+    }
+
+    /// The policy of the requests with this method and path, with the quota of its scope,
+    /// path rule or method as the quota of the policy.
+    ///
+    /// Use it to document operations that have their own quota:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, QuotaOverride};
+    /// use actix_web::http::Method;
+    /// use std::time::Duration;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(6)
+    ///     .burst_size(10)
+    ///     .method_quota(&[Method::POST], QuotaOverride::new(Duration::from_secs(60), 2).unwrap())
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// let post = config.policy().operation(&Method::POST, "/items").unwrap();
+    /// assert_eq!(post.burst_size(), 2);
+    /// ```
+    ///
+    /// Returns `None` if the requests are not rate limited, because of their method,
+    /// their scope or the extension of the requested file.
+    pub fn operation(&self, method: &Method, path: &str) -> Option<GovernorPolicy> {
+        let quota = match self.route(method, path) {
+            Route::Exempt => return None,
+            Route::Limited(quota) => quota,
+        };
+        let mut policy = self.clone();
+        if let Some(quota) = quota {
+            policy.period = quota.period();
+            policy.burst_size = quota.burst_size();
+        }
+        policy.scopes = None;
+        policy.rules = Vec::new();
+        policy.method_quotas = Vec::new();
+        Some(policy)
+    }
+
+    /// How the requests with this method and path are limited, like the governor does.
+    pub(crate) fn route(&self, method: &Method, path: &str) -> Route {
+        let method_ignored = self
+            .methods
+            .as_ref()
+            .is_some_and(|methods| !methods.contains(method));
+        let scope = self
+            .scopes
+            .as_ref()
+            .and_then(|scopes| scopes.lookup(path))
+            .copied();
+        if method_ignored
+            || self.is_exempt_extension(path)
+            || scope.is_some_and(|scope| scope.is_exempt())
+        {
+            return Route::Exempt;
+        }
+        let quota = self
+            .rules
+            .iter()
+            .find(|rule| rule.matches_path(method, path))
+            .map(|rule| rule.quota())
+            .or_else(|| scope.and_then(|scope| scope.quota_override()))
+            .or_else(|| {
+                self.method_quotas
+                    .iter()
+                    .find(|(quota_method, _)| quota_method == method)
+                    .map(|(_, quota)| *quota)
+            });
+        Route::Limited(quota)
+    }
+
+    /// Whether the requested file has one of the exempt extensions.
+    pub(crate) fn is_exempt_extension(&self, path: &str) -> bool {
+        let extensions = match &self.exempt_extensions {
+            Some(extensions) => extensions,
+            None => return false,
+        };
+        let file_name = path.rsplit('/').next().unwrap_or_default();
+        match file_name.rsplit_once('.') {
+            Some((_, extension)) => extensions
+                .iter()
+                .any(|exempt| exempt.eq_ignore_ascii_case(extension)),
+            None => false,
+        }
+    }
+
+    /// Serialize the policy as JSON.
+    ///
+    /// Durations are given in milliseconds, optional settings that are not configured are `null`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        write!(
+            json,
+            "{{\"period_ms\":{},\"burst_size\":{}",
+            millis(self.period),
+            self.burst_size
+        )
+        .unwrap();
+
+        write!(
+            json,
+            ",\"methods\":{}",
+            methods_json(self.methods.as_deref())
+        )
+        .unwrap();
+
+        write!(json, ",\"headers\":{}", self.use_headers).unwrap();
+
+        json.push_str(",\"cold_start\":");
+        match &self.cold_start {
+            Some(cold_start) => write!(
+                json,
+                "{{\"period_ms\":{},\"burst_size\":{},\"probation_ms\":{}}}",
+                millis(cold_start.period),
+                cold_start.burst_size,
+                millis(cold_start.probation)
+            )
+            .unwrap(),
+            None => json.push_str("null"),
+        }
+
+        write!(
+            json,
+            ",\"max_concurrency\":{},\"max_concurrent_streams\":{}",
+            optional(self.max_concurrency),
+            optional(self.max_concurrent_streams)
+        )
+        .unwrap();
+
+        let codes: Vec<_> = self
+            .free_status_codes
+            .iter()
+            .map(|code| code.as_u16().to_string())
+            .collect();
+        write!(json, ",\"free_status_codes\":[{}]", codes.join(",")).unwrap();
+
+        json.push_str(",\"cache_hit_header\":");
+        match &self.cache_hit_header {
+            Some(header_name) => write!(json, "\"{header_name}\"").unwrap(),
+            None => json.push_str("null"),
+        }
+
+        json.push_str(",\"name\":");
+        match self.labels.as_ref().and_then(|labels| labels.name()) {
+            Some(name) => write!(json, "\"{}\"", escape_json(name)).unwrap(),
+            None => json.push_str("null"),
+        }
+
+        let labels: Vec<_> = self
+            .labels
+            .iter()
+            .flat_map(|labels| labels.labels())
+            .map(|(key, value)| format!("\"{}\":\"{}\"", escape_json(key), escape_json(value)))
+            .collect();
+        write!(json, ",\"labels\":{{{}}}", labels.join(",")).unwrap();
+
+        let scopes: Vec<_> = self
+            .scopes()
+            .iter()
+            .map(|(path, scope)| {
+                format!(
+                    "{{\"path\":\"{}\",\"quota\":{},\"exempt\":{}}}",
+                    escape_json(path),
+                    optional_quota_json(scope.quota_override()),
+                    scope.is_exempt()
+                )
+            })
+            .collect();
+        write!(json, ",\"scopes\":[{}]", scopes.join(",")).unwrap();
+
+        let rules: Vec<_> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                format!(
+                    "{{\"pattern\":\"{}\",\"regex\":{},\"methods\":{},\"quota\":{}}}",
+                    escape_json(&rule.pattern()),
+                    rule.is_regex(),
+                    methods_json(rule.method_filter()),
+                    quota_json(rule.quota())
+                )
+            })
+            .collect();
+        write!(json, ",\"rules\":[{}]", rules.join(",")).unwrap();
+
+        let method_quotas: Vec<_> = self
+            .method_quotas
+            .iter()
+            .map(|(method, quota)| {
+                format!(
+                    "{{\"method\":\"{method}\",\"quota\":{}}}",
+                    quota_json(*quota)
+                )
+            })
+            .collect();
+        write!(json, ",\"method_quotas\":[{}]", method_quotas.join(",")).unwrap();
+
+        let additional_quotas: Vec<_> = self
+            .additional_quotas
+            .iter()
+            .map(|quota| quota_json(*quota))
+            .collect();
+        write!(
+            json,
+            ",\"additional_quotas\":[{}],\"global_quota\":{}",
+            additional_quotas.join(","),
+            optional_quota_json(self.global_quota)
+        )
+        .unwrap();
+
+        json.push_str(",\"exempt_extensions\":");
+        match &self.exempt_extensions {
+            Some(extensions) => {
+                let extensions: Vec<_> = extensions
+                    .iter()
+                    .map(|extension| format!("\"{}\"", escape_json(extension)))
+                    .collect();
+                write!(json, "[{}]", extensions.join(",")).unwrap();
+            }
+            None => json.push_str("null"),
+        }
+
+        write!(
+            json,
+            ",\"shadow\":{},\"slow_start_ms\":{},\"enforced_fraction\":{},\"warmup_ms\":{}",
+            self.shadow,
+            optional(self.ramp.map(millis)),
+            optional(self.enforced_fraction()),
+            optional(self.warmup.map(millis))
+        )
+        .unwrap();
+
+        json.push('}');
+        json
+    }
+
+    /// The value of an `x-rate-limit` OpenAPI extension for operations protected by this policy, as JSON.
+    ///
+    /// Attach it with the OpenAPI generator of your choice, e.g. utoipa or paperclip,
+    /// so the published documentation always matches the enforced limits.
+    ///
+    /// ```rust
+    /// use actix_governor::GovernorConfigBuilder;
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(6)
+    ///     .burst_size(10)
+    ///     .finish()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     config.policy().openapi_extension(),
+    ///     "{\"burst_size\":10,\"period_ms\":6000,\"requests_per_minute\":10,\"headers\":[\"x-ratelimit-after\"]}"
+    /// );
+    /// ```
+    ///
+    /// Additional and global quotas are listed as `additional_quotas` and `global_quota`
+    /// if they are configured. Operations with their own quota are described by their
+    /// [`operation`](Self::operation) policy.
+    pub fn openapi_extension(&self) -> String {
+        let headers: Vec<_> = self
+            .headers()
+            .iter()
+            .map(|header| format!("\"{header}\""))
+            .collect();
+        let mut extension = format!(
+            "{{\"burst_size\":{},\"period_ms\":{},\"requests_per_minute\":{},\"headers\":[{}]",
+            self.burst_size,
+            millis(self.period),
+            self.requests_per_minute(),
+            headers.join(",")
+        );
+        if !self.additional_quotas.is_empty() {
+            let quotas: Vec<_> = self
+                .additional_quotas
+                .iter()
+                .map(|quota| quota_json(*quota))
+                .collect();
+            write!(extension, ",\"additional_quotas\":[{}]", quotas.join(",")).unwrap();
+        }
+        if let Some(quota) = self.global_quota {
+            write!(extension, ",\"global_quota\":{}", quota_json(quota)).unwrap();
+        }
+        extension.push('}');
+        extension
+    }
+
+    /// A description of the policy for OpenAPI operations, in Markdown.
+    pub fn openapi_description(&self) -> String {
+        let mut description = format!(
+            "Rate limited to bursts of {} requests, one request is replenished every {}ms.",
+            self.burst_size,
+            millis(self.period)
+        );
+        for quota in &self.additional_quotas {
+            write!(
+                description,
+                " Additionally limited to bursts of {} requests, one request is replenished every {}ms.",
+                quota.burst_size(),
+                millis(quota.period())
+            )
+            .unwrap();
+        }
+        if let Some(quota) = self.global_quota {
+            write!(
+                description,
+                " All clients together are limited to bursts of {} requests, one request is replenished every {}ms.",
+                quota.burst_size(),
+                millis(quota.period())
+            )
+            .unwrap();
+        }
+        if let Some(max_concurrency) = self.max_concurrency {
+            write!(
+                description,
+                " At most {max_concurrency} requests are processed at the same time."
+            )
+            .unwrap();
+        }
+        description.push_str(
+            " Exceeding the limit returns `429 Too Many Requests` with the `x-ratelimit-after` header.",
+        );
+        description
+    }
+
+    /// Requests replenished per minute, rounded down.
+    fn requests_per_minute(&self) -> u128 {
+        Duration::from_secs(60).as_nanos() / self.period.as_nanos()
+    }
+
+    /// Headers added to the responses.
+    fn headers(&self) -> Vec<&'static str> {
+        let mut headers = vec!["x-ratelimit-after"];
+        if self.use_headers {
+            headers.extend(["x-ratelimit-limit", "x-ratelimit-remaining"]);
+            if self.methods.is_some() {
+                headers.push("x-ratelimit-whitelisted");
+            }
+            if self.max_concurrency.is_some() {
+                headers.extend([
+                    "x-ratelimit-concurrency-limit",
+                    "x-ratelimit-concurrency-remaining",
+                ]);
+            }
+        }
+        headers
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn optional<T: ToString>(value: Option<T>) -> String {
+    match value {
+        Some(value) => value.to_string(),
+        None => "null".to_owned(),
+    }
+}
+
+fn methods_json(methods: Option<&[Method]>) -> String {
+    match methods {
+        Some(methods) => {
+            let methods: Vec<_> = methods.iter().map(|m| format!("\"{m}\"")).collect();
+            format!("[{}]", methods.join(","))
+        }
+        None => "null".to_owned(),
+    }
+}
+
+fn quota_json(quota: QuotaOverride) -> String {
+    format!(
+        "{{\"period_ms\":{},\"burst_size\":{}}}",
+        millis(quota.period()),
+        quota.burst_size()
+    )
+}
+
+fn optional_quota_json(quota: Option<QuotaOverride>) -> String {
+    quota.map_or_else(|| "null".to_owned(), quota_json)
+}
+
+impl Responder for GovernorPolicy {
+    type Body = BoxBody;
+
+    fn respond_to(self, _req: &HttpRequest) -> HttpResponse<Self::Body> {
+        HttpResponse::Ok()
+            .insert_header(("content-type", "application/json"))
+            .body(self.to_json())
+    }
+}
+
+/// Name and labels of a configuration, which tell stacked policies apart.
+///
+/// They are inserted into the extensions of denial responses, next to the [`DenialReason`](crate::DenialReason),
+/// included in the logs and, with [`use_headers`](crate::GovernorConfigBuilder::use_headers()),
+/// the name is sent in the `x-ratelimit-policy` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PolicyLabels(Arc<PolicyLabelsInner>);
+
+#[derive(Debug, PartialEq, Eq)]
+struct PolicyLabelsInner {
+    name: Option<String>,
+    labels: Vec<(String, String)>,
+    header_value: Option<HeaderValue>,
+}
+
+impl PolicyLabels {
+    pub(crate) fn new(name: Option<String>, labels: Vec<(String, String)>) -> Self {
+        let header_value = name
+            .as_deref()
+            .and_then(|name| HeaderValue::from_str(name).ok());
+        PolicyLabels(Arc::new(PolicyLabelsInner {
+            name,
+            labels,
+            header_value,
+        }))
+    }
+
+    /// The name of the policy.
+    pub fn name(&self) -> Option<&str> {
+        self.0.name.as_deref()
+    }
+
+    /// All labels of the policy.
+    pub fn labels(&self) -> &[(String, String)] {
+        &self.0.labels
+    }
+
+    /// The value of a label.
+    pub fn label(&self, key: &str) -> Option<&str> {
+        self.0
+            .labels
+            .iter()
+            .find(|(label, _)| label == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Value of the `x-ratelimit-policy` header, if the name is a valid header value.
+    pub(crate) fn header_value(&self) -> Option<&HeaderValue> {
+        self.0.header_value.as_ref()
+    }
+}
@@ -0,0 +1,107 @@
+use crate::key_extractor::subnet;
+
+use std::{fmt, net::IpAddr, str::FromStr, sync::Arc};
+
+/// A network of IP addresses in CIDR notation, like `10.0.0.0/8` or `fd00::/8`.
+///
+/// ```rust
+/// use actix_governor::IpNet;
+///
+/// let net: IpNet = "10.0.0.0/8".parse().unwrap();
+/// assert!(net.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!net.contains("192.168.0.1".parse().unwrap()));
+/// ```
+///
+/// A single address without a prefix length, like `10.0.0.1`, is a network of only this address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    /// Create the network of `addr` with the given prefix length.
+    ///
+    /// Returns `None` if the prefix is longer than 32 bits for IPv4 or 128 bits for IPv6.
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+        (prefix <= max_prefix).then(|| IpNet {
+            addr: network(addr, prefix),
+            prefix,
+        })
+    }
+
+    /// The first address of the network.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// The prefix length of the network.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether the address is part of the network.
+    /// IPv4-mapped IPv6 addresses like `::ffff:10.0.0.1` are treated as IPv4 addresses.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        ip.is_ipv4() == self.addr.is_ipv4() && network(ip, self.prefix) == self.addr
+    }
+}
+
+/// The first address of the network of `ip` with the given prefix length.
+fn network(ip: IpAddr, prefix: u8) -> IpAddr {
+    subnet(ip, prefix, prefix)
+}
+
+impl fmt::Display for IpNet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Error returned if a network can't be parsed, see [`IpNet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIpNetError(String);
+
+impl fmt::Display for ParseIpNetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid IP network: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseIpNetError {}
+
+impl FromStr for IpNet {
+    type Err = ParseIpNetError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let error = || ParseIpNetError(value.to_owned());
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (value, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| error())?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| error())?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        IpNet::new(addr, prefix).ok_or_else(error)
+    }
+}
+
+/// The networks of the reverse proxies whose forwarded headers are trusted,
+/// see [`SmartIpKeyExtractor::trusted`](crate::SmartIpKeyExtractor::trusted()).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TrustedProxies(Arc<[IpNet]>);
+
+impl TrustedProxies {
+    pub(crate) fn new(proxies: Vec<IpNet>) -> Self {
+        TrustedProxies(proxies.into())
+    }
+
+    pub(crate) fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|net| net.contains(ip))
+    }
+}
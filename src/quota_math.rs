@@ -0,0 +1,119 @@
+use std::{fmt, time::Duration};
+
+/// Error of the quota conversions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum QuotaError {
+    /// The number of requests is zero.
+    ZeroRequests,
+    /// The window or period is zero.
+    ZeroDuration,
+    /// The conversion is not exact, use the rounded variant instead.
+    Inexact,
+    /// The result does not fit into the target type.
+    Overflow,
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaError::ZeroRequests => f.write_str("the number of requests must not be zero"),
+            QuotaError::ZeroDuration => f.write_str("the window and period must not be zero"),
+            QuotaError::Inexact => f.write_str("the quota can't be converted exactly"),
+            QuotaError::Overflow => f.write_str("the quota is too large"),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// Compute the period after which one element of the quota is replenished,
+/// so that `requests` requests are allowed per `window`.
+///
+/// Use it together with a burst size of `requests` to allow the whole window at once:
+///
+/// ```rust
+/// use actix_governor::{period_for_window, GovernorConfigBuilder};
+/// use std::time::Duration;
+///
+/// // 100 requests per minute
+/// let period = period_for_window(100, Duration::from_secs(60)).unwrap();
+/// assert_eq!(period, Duration::from_millis(600));
+///
+/// let config = GovernorConfigBuilder::default()
+///     .period(period)
+///     .burst_size(100)
+///     .finish()
+///     .unwrap();
+/// ```
+///
+/// Returns [`QuotaError::Inexact`] if the window can't be divided into `requests` equal nanoseconds.
+pub fn period_for_window(requests: u32, window: Duration) -> Result<Duration, QuotaError> {
+    let (period, remainder) = divide_window(requests, window)?;
+    if remainder != 0 {
+        return Err(QuotaError::Inexact);
+    }
+    to_duration(period)
+}
+
+/// Like [`period_for_window`], but rounds the period up to the next nanosecond.
+///
+/// The enforced rate is then never higher than `requests` per `window`.
+pub fn period_for_window_rounded(requests: u32, window: Duration) -> Result<Duration, QuotaError> {
+    let (period, remainder) = divide_window(requests, window)?;
+    to_duration(if remainder != 0 { period + 1 } else { period })
+}
+
+/// Compute how many requests are replenished per `window` if one element of the quota
+/// is replenished after `period`. The burst size is allowed on top of that.
+///
+/// ```rust
+/// use actix_governor::requests_per_window;
+/// use std::time::Duration;
+///
+/// let requests = requests_per_window(Duration::from_millis(500), Duration::from_secs(60));
+/// assert_eq!(requests, Ok(120));
+/// ```
+///
+/// Returns [`QuotaError::Inexact`] if the window is not a multiple of the period.
+pub fn requests_per_window(period: Duration, window: Duration) -> Result<u32, QuotaError> {
+    let (requests, remainder) = divide_period(period, window)?;
+    if remainder != 0 {
+        return Err(QuotaError::Inexact);
+    }
+    u32::try_from(requests).map_err(|_| QuotaError::Overflow)
+}
+
+/// Like [`requests_per_window`], but rounds the number of requests down.
+///
+/// The returned number of requests is then never more than the middleware allows.
+pub fn requests_per_window_rounded(period: Duration, window: Duration) -> Result<u32, QuotaError> {
+    let (requests, _) = divide_period(period, window)?;
+    u32::try_from(requests).map_err(|_| QuotaError::Overflow)
+}
+
+fn divide_window(requests: u32, window: Duration) -> Result<(u128, u128), QuotaError> {
+    if requests == 0 {
+        return Err(QuotaError::ZeroRequests);
+    }
+    let window = window.as_nanos();
+    if window == 0 {
+        return Err(QuotaError::ZeroDuration);
+    }
+    let requests = u128::from(requests);
+    Ok((window / requests, window % requests))
+}
+
+fn divide_period(period: Duration, window: Duration) -> Result<(u128, u128), QuotaError> {
+    let (period, window) = (period.as_nanos(), window.as_nanos());
+    if period == 0 || window == 0 {
+        return Err(QuotaError::ZeroDuration);
+    }
+    Ok((window / period, window % period))
+}
+
+fn to_duration(nanos: u128) -> Result<Duration, QuotaError> {
+    u64::try_from(nanos)
+        .map(Duration::from_nanos)
+        .map_err(|_| QuotaError::Overflow)
+}
@@ -0,0 +1,121 @@
+use governor::{clock::QuantaInstant, middleware::RateLimitingMiddleware, Quota, RateLimiter};
+
+use std::{collections::HashMap, fmt, hash::Hash, sync::Arc, sync::Mutex, time::Duration};
+
+use crate::config_error::checked_quota;
+use crate::SharedRateLimiter;
+
+/// Quota that replaces the configured quota for a single request.
+///
+/// Insert it into the request extensions in an earlier middleware or guard,
+/// for example based on the pricing tier of a customer, and the governor
+/// middleware will check the request against this quota instead.
+/// Keys get an independent bucket for every distinct override quota.
+///
+/// ```rust
+/// use actix_governor::QuotaOverride;
+/// use actix_web::{dev::ServiceRequest, HttpMessage};
+/// use std::time::Duration;
+///
+/// fn premium_tier(req: &ServiceRequest) {
+///     let quota = QuotaOverride::new(Duration::from_millis(100), 50).unwrap();
+///     req.extensions_mut().insert(quota);
+/// }
+/// ```
+///
+/// **Every distinct override quota creates a rate limiter that lives as long as the configuration,
+/// so derive them from a small set of values.**
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QuotaOverride {
+    period: Duration,
+    burst_size: u32,
+}
+
+impl QuotaOverride {
+    /// Create a quota that replenishes one element after `period` and allows bursts of `burst_size` requests.
+    ///
+    /// Returns `None` if either burst size or period interval are zero, or if replenishing
+    /// the whole burst takes more than 64 bits of nanoseconds.
+    pub fn new(period: Duration, burst_size: u32) -> Option<Self> {
+        checked_quota(period, burst_size)?;
+        Some(QuotaOverride { period, burst_size })
+    }
+
+    /// The interval after which one element of the quota is replenished.
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+
+    /// The number of requests that can be made in a burst.
+    pub fn burst_size(&self) -> u32 {
+        self.burst_size
+    }
+
+    pub(crate) fn quota(&self) -> Quota {
+        // Checked by `new`, the only way to create a quota override.
+        checked_quota(self.period, self.burst_size).unwrap()
+    }
+}
+
+/// Lazily created rate limiters for the override quotas.
+#[derive(Debug)]
+pub(crate) struct OverrideLimiters<Key: Clone + Hash + Eq, M: RateLimitingMiddleware<QuantaInstant>>
+{
+    limiters: Mutex<HashMap<QuotaOverride, SharedRateLimiter<Key, M>>>,
+}
+
+impl<Key: Clone + Hash + Eq, M: RateLimitingMiddleware<QuantaInstant>> OverrideLimiters<Key, M> {
+    pub(crate) fn new() -> Self {
+        OverrideLimiters {
+            limiters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the rate limiter of the quota override.
+    pub(crate) fn limiter(&self, quota: &QuotaOverride) -> SharedRateLimiter<Key, M> {
+        self.limiters
+            .lock()
+            .unwrap()
+            .entry(*quota)
+            .or_insert_with(|| Arc::new(RateLimiter::keyed(quota.quota()).with_middleware::<M>()))
+            .clone()
+    }
+}
+
+type ResolveFn<Key> = dyn Fn(&Key) -> Option<QuotaOverride> + Send + Sync;
+
+/// Closure that selects the quota of a key, like the quota of its pricing tier.
+pub(crate) struct QuotaResolver<Key>(Arc<ResolveFn<Key>>);
+
+impl<Key> QuotaResolver<Key> {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&Key) -> Option<QuotaOverride> + Send + Sync + 'static,
+    {
+        QuotaResolver(Arc::new(f))
+    }
+
+    pub(crate) fn resolve(&self, key: &Key) -> Option<QuotaOverride> {
+        (self.0)(key)
+    }
+}
+
+impl<Key> Clone for QuotaResolver<Key> {
+    fn clone(&self) -> Self {
+        QuotaResolver(self.0.clone())
+    }
+}
+
+impl<Key> PartialEq for QuotaResolver<Key> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<Key> Eq for QuotaResolver<Key> {}
+
+impl<Key> fmt::Debug for QuotaResolver<Key> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("QuotaResolver")
+    }
+}
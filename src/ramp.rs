@@ -0,0 +1,44 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    time::{Duration, Instant},
+};
+
+/// Gradually enforces a newly deployed quota, or enforces it only for a share of the keys.
+///
+/// Every key gets a stable position between 0 and 1 derived from its hash,
+/// and its denials are enforced once the elapsed fraction of the ramp,
+/// scaled to the enforced share, reached that position.
+#[derive(Debug)]
+pub(crate) struct Ramp {
+    started: Instant,
+    duration: Option<Duration>,
+    share: f64,
+}
+
+impl Ramp {
+    pub(crate) fn new(duration: Option<Duration>, millionths: u32) -> Self {
+        Ramp {
+            started: Instant::now(),
+            duration,
+            share: f64::from(millionths.min(1_000_000)) / 1_000_000.0,
+        }
+    }
+
+    /// Whether the quota is already enforced for the key.
+    pub(crate) fn is_enforced<Key: Hash>(&self, key: &Key) -> bool {
+        let progress = match self.duration {
+            Some(duration) if self.started.elapsed() < duration => {
+                self.started.elapsed().as_secs_f64() / duration.as_secs_f64()
+            }
+            _ => 1.0,
+        };
+        if progress * self.share >= 1.0 {
+            return true;
+        }
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let position = hasher.finish() as f64 / u64::MAX as f64;
+        position < progress * self.share
+    }
+}
@@ -0,0 +1,158 @@
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{header::HeaderName, Method, StatusCode};
+
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::QuotaOverride;
+
+/// Marks a response as served from a cache.
+///
+/// Insert it into the response extensions from your cache middleware,
+/// so [`free_cache_hits`](crate::GovernorConfigBuilder::free_cache_hits()) can detect cache hits
+/// without relying on headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheHit;
+
+/// A key and the quota override that charged it, `None` for the configured quota.
+type Bucket<Key> = (Key, Option<QuotaOverride>);
+
+/// Gives back the quota of requests whose response is "free" and of unused reservations.
+///
+/// The underlying rate limiter can't return cells, so refunded cells are kept
+/// as credits of the key that allow requests which would otherwise be denied.
+/// A credit only stands in for a cell of the bucket it was refunded to, either the configured
+/// quota or a [`QuotaOverride`]. The credits of a bucket never exceed its burst size
+/// and expire after its period, when the limiter would have replenished the cell anyway.
+#[derive(Debug)]
+pub(crate) struct Refunds<Key: Clone + Hash + Eq> {
+    free_status_codes: Vec<StatusCode>,
+    cache_hit_header: Option<HeaderName>,
+    free_head: bool,
+    period: Duration,
+    burst_size: u32,
+    /// The expiry times of the credits of each bucket, oldest first.
+    credits: Mutex<HashMap<Bucket<Key>, VecDeque<Instant>>>,
+}
+
+impl<Key: Clone + Hash + Eq> Refunds<Key> {
+    pub(crate) fn new(
+        free_status_codes: Vec<StatusCode>,
+        cache_hit_header: Option<HeaderName>,
+        free_head: bool,
+        period: Duration,
+        burst_size: u32,
+    ) -> Self {
+        Refunds {
+            free_status_codes,
+            cache_hit_header,
+            free_head,
+            period,
+            burst_size,
+            credits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Use `n` credits of the bucket of the key.
+    /// Returns `false`, without using any, if the bucket has fewer than `n` credits left.
+    pub(crate) fn take_credits(&self, key: &Key, quota: Option<QuotaOverride>, n: u32) -> bool {
+        let bucket = (key.clone(), quota);
+        let mut credits = self.credits.lock().unwrap();
+        let expiries = match credits.get_mut(&bucket) {
+            Some(expiries) => expiries,
+            None => return false,
+        };
+        let now = Instant::now();
+        while expiries.front().is_some_and(|expires| *expires <= now) {
+            expiries.pop_front();
+        }
+        let taken = expiries.len() >= n as usize;
+        if taken {
+            expiries.drain(..n as usize);
+        }
+        if expiries.is_empty() {
+            credits.remove(&bucket);
+        }
+        taken
+    }
+
+    /// Create a ticket that refunds the request of the key, if the request is free
+    /// or its response turns out to be free.
+    /// Returns `None` if no requests are free, the credits are then only used by reservations.
+    pub(crate) fn ticket(
+        self: &Arc<Self>,
+        key: &Key,
+        quota: Option<QuotaOverride>,
+        method: &Method,
+    ) -> Option<RefundTicket<Key>> {
+        if self.free_status_codes.is_empty() && self.cache_hit_header.is_none() && !self.free_head {
+            return None;
+        }
+        Some(RefundTicket {
+            refunds: self.clone(),
+            key: key.clone(),
+            quota,
+            free_request: self.free_head && method == Method::HEAD,
+        })
+    }
+
+    fn is_free<B>(&self, response: &ServiceResponse<B>) -> bool {
+        if self.free_status_codes.contains(&response.status()) {
+            return true;
+        }
+        match &self.cache_hit_header {
+            Some(header_name) => {
+                response.response().extensions().contains::<CacheHit>()
+                    || response
+                        .headers()
+                        .get(header_name)
+                        .is_some_and(|value| value.as_bytes().starts_with(b"HIT"))
+            }
+            None => false,
+        }
+    }
+
+    /// Give back `n` requests to the bucket of the key.
+    pub(crate) fn refund(&self, key: Key, quota: Option<QuotaOverride>, n: u32) {
+        if n == 0 {
+            return;
+        }
+        let (period, burst_size) = match quota {
+            Some(quota) => (quota.period(), quota.burst_size()),
+            None => (self.period, self.burst_size),
+        };
+        let now = Instant::now();
+        let mut credits = self.credits.lock().unwrap();
+        let expiries = credits.entry((key, quota)).or_default();
+        while expiries.front().is_some_and(|expires| *expires <= now) {
+            expiries.pop_front();
+        }
+        for _ in 0..n.min(burst_size) {
+            expiries.push_back(now + period);
+        }
+        while expiries.len() > burst_size as usize {
+            expiries.pop_front();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct RefundTicket<Key: Clone + Hash + Eq> {
+    refunds: Arc<Refunds<Key>>,
+    key: Key,
+    quota: Option<QuotaOverride>,
+    free_request: bool,
+}
+
+impl<Key: Clone + Hash + Eq> RefundTicket<Key> {
+    /// Refund the request if its response is free.
+    pub(crate) fn settle<B>(self, response: &ServiceResponse<B>) {
+        if self.free_request || self.refunds.is_free(response) {
+            self.refunds.refund(self.key, self.quota, 1);
+        }
+    }
+}
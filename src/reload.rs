@@ -0,0 +1,156 @@
+use crate::env::parse_duration;
+use crate::QuotaOverride;
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    thread,
+    time::Duration,
+};
+
+/// Replaces the quota of a [`Governor`](crate::Governor) at runtime, without restarting the server.
+///
+/// While a quota is set, it is used instead of the configured quota of the keys that don't have
+/// an override of their own, see [`QuotaOverride`] and
+/// [`key_quota`](crate::GovernorConfigBuilder::key_quota()). The buckets of the keys are kept
+/// per quota, so switching back to an earlier quota continues with its buckets.
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfig, QuotaOverride, ReloadableQuota};
+/// use actix_web::{web, App, Responder};
+/// use std::time::Duration;
+///
+/// async fn index() -> impl Responder {
+///     "Hello world!"
+/// }
+///
+/// let config = GovernorConfig::default();
+/// let quota = ReloadableQuota::new();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&config).with_reloadable_quota(quota.clone()))
+///     .route("/", web::get().to(index));
+///
+/// // Later, for example during an attack
+/// quota.set(QuotaOverride::new(Duration::from_secs(10), 2));
+/// ```
+///
+/// All clones of a handle share the same quota.
+#[derive(Debug, Clone, Default)]
+pub struct ReloadableQuota(Arc<RwLock<Option<QuotaOverride>>>);
+
+impl ReloadableQuota {
+    /// Create a handle that uses the configured quota until a quota is set.
+    pub fn new() -> Self {
+        ReloadableQuota::default()
+    }
+
+    /// Read the quota from a file and reload it whenever the file changes.
+    ///
+    /// The file is checked every `interval` by a background thread, which stops
+    /// when all clones of the handle are dropped. It contains the period and burst size
+    /// in the format of [`from_env`](crate::GovernorConfigBuilder::from_env()):
+    ///
+    /// ```text
+    /// # Tightened during the incident
+    /// PERIOD=2s
+    /// BURST_SIZE=5
+    /// ```
+    ///
+    /// An empty file selects the configured quota. Returns an error if the file can't be
+    /// read or parsed at first. Later changes that can't be read or parsed are ignored
+    /// and the previous quota stays active.
+    pub fn watch_file(path: impl Into<PathBuf>, interval: Duration) -> io::Result<Self> {
+        let path = path.into();
+        let mut contents = fs::read_to_string(&path)?;
+        let handle = ReloadableQuota::new();
+        handle.set(parse_quota(&contents)?);
+
+        let weak = Arc::downgrade(&handle.0);
+        thread::Builder::new()
+            .name("governor-quota-watcher".to_owned())
+            .spawn(move || loop {
+                thread::sleep(interval);
+                let quota = match weak.upgrade() {
+                    Some(quota) => quota,
+                    None => break,
+                };
+                match reload(&path, &mut contents) {
+                    Ok(Some(reloaded)) => *quota.write().unwrap() = reloaded,
+                    Ok(None) => {}
+                    #[cfg(feature = "log")]
+                    Err(e) => log::warn!("Failed to reload quota from {}: {}", path.display(), e),
+                    #[cfg(not(feature = "log"))]
+                    Err(_) => {}
+                }
+            })?;
+        Ok(handle)
+    }
+
+    /// Use `quota` instead of the configured quota, or the configured quota again if it is `None`.
+    pub fn set(&self, quota: Option<QuotaOverride>) {
+        *self.0.write().unwrap() = quota;
+    }
+
+    /// The quota that is used instead of the configured quota, if any.
+    pub fn get(&self) -> Option<QuotaOverride> {
+        *self.0.read().unwrap()
+    }
+}
+
+/// Read the file again. Returns the new quota if the file changed.
+///
+/// Invalid contents are remembered as well, so they are reported only once.
+fn reload(path: &Path, contents: &mut String) -> io::Result<Option<Option<QuotaOverride>>> {
+    let current = fs::read_to_string(path)?;
+    if current == *contents {
+        return Ok(None);
+    }
+    *contents = current;
+    parse_quota(contents).map(Some)
+}
+
+fn parse_quota(contents: &str) -> io::Result<Option<QuotaOverride>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let (mut period, mut burst_size) = (None, None);
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid(format!("expected NAME=VALUE: {line:?}")))?;
+        let value = value.trim();
+        match name.trim() {
+            "PERIOD" => {
+                period = Some(
+                    parse_duration(value)
+                        .ok_or_else(|| invalid(format!("invalid PERIOD: {value:?}")))?,
+                )
+            }
+            "BURST_SIZE" => {
+                burst_size = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid BURST_SIZE: {value:?}")))?,
+                )
+            }
+            name => return Err(invalid(format!("unknown setting: {name:?}"))),
+        }
+    }
+    match (period, burst_size) {
+        (None, None) => Ok(None),
+        (Some(period), Some(burst_size)) => QuotaOverride::new(period, burst_size)
+            .map(Some)
+            .ok_or_else(|| {
+                invalid(
+                    "PERIOD and BURST_SIZE must not be zero or exceed the limiter's range"
+                        .to_owned(),
+                )
+            }),
+        _ => Err(invalid(
+            "PERIOD and BURST_SIZE must be set together".to_owned(),
+        )),
+    }
+}
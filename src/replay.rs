@@ -0,0 +1,260 @@
+use actix_web::http::Method;
+use governor::{
+    clock::{Clock, FakeRelativeClock},
+    middleware::NoOpMiddleware,
+    state::keyed::DefaultKeyedStateStore,
+    Quota, RateLimiter,
+};
+
+use std::{collections::HashMap, fmt, str::FromStr, time::Duration};
+
+use crate::policy::Route;
+use crate::{GovernorPolicy, QuotaOverride};
+
+/// A keyed rate limiter on the simulated clock.
+type KeyedLimiter<'a> = RateLimiter<
+    &'a str,
+    DefaultKeyedStateStore<&'a str>,
+    FakeRelativeClock,
+    NoOpMiddleware<<FakeRelativeClock as Clock>::Instant>,
+>;
+
+/// A request of a recorded traffic log, see [`GovernorPolicy::replay`].
+///
+/// It can be parsed from a line of the form `<timestamp> <method> <path> <key>`,
+/// where the timestamp is given in seconds, e.g. `1700000000.250 GET /api/items 10.0.0.1`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedRequest {
+    /// Time of the request, relative to any fixed point in time.
+    pub timestamp: Duration,
+    /// The HTTP method of the request.
+    pub method: Method,
+    /// The path of the request.
+    pub path: String,
+    /// The rate limiting key of the request.
+    pub key: String,
+}
+
+/// Error returned if a line of a traffic log can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRecordError(String);
+
+impl fmt::Display for ParseRecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid recorded request: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseRecordError {}
+
+impl FromStr for RecordedRequest {
+    type Err = ParseRecordError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let error = || ParseRecordError(line.to_owned());
+        let mut fields = line.split_whitespace();
+        let timestamp = fields
+            .next()
+            .and_then(|timestamp| timestamp.parse::<f64>().ok())
+            .and_then(|timestamp| Duration::try_from_secs_f64(timestamp).ok())
+            .ok_or_else(error)?;
+        let method = fields
+            .next()
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok())
+            .ok_or_else(error)?;
+        let path = fields.next().ok_or_else(error)?.to_owned();
+        let key = fields.next().ok_or_else(error)?.to_owned();
+        if fields.next().is_some() {
+            return Err(error());
+        }
+        Ok(RecordedRequest {
+            timestamp,
+            method,
+            path,
+            key,
+        })
+    }
+}
+
+/// Outcome of replaying recorded traffic against a policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayReport {
+    /// Number of replayed requests.
+    pub total: u64,
+    /// Requests that were not rate limited because of their method, scope or file extension.
+    pub whitelisted: u64,
+    /// Requests that would have been allowed.
+    pub allowed: u64,
+    /// Requests that would have been denied.
+    pub denied: u64,
+    /// Denied requests per key.
+    pub denied_by_key: HashMap<String, u64>,
+}
+
+/// Decision of a policy for a simulated request, see [`GovernorPolicy::simulate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimulatedOutcome {
+    /// The request would have been allowed.
+    Allowed,
+    /// The request would have been denied.
+    Denied,
+    /// The request would not have been rate limited because of its method, scope or file extension.
+    Whitelisted,
+}
+
+impl GovernorPolicy {
+    /// Replay recorded traffic against the quota of this policy and report
+    /// how many requests would have been denied, to select quotas based on real traffic.
+    ///
+    /// The requests are replayed in the order of their timestamps with a simulated clock,
+    /// so this runs as fast as possible. The methods, scopes, exempt extensions, path rules,
+    /// method quotas, the cold start and the additional and global quotas are simulated
+    /// like the governor applies them, every request costs one. The decisions are the ones
+    /// of an enforced policy, even in shadow mode, during the warmup or the slow start.
+    ///
+    /// Everything that is not part of the [`GovernorPolicy`] is not simulated, and neither are
+    /// the limits that depend on how long requests take or on the responses, like the concurrency
+    /// or the refunds of free responses.
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, RecordedRequest};
+    ///
+    /// let log = "0.0 GET / 10.0.0.1\n0.1 GET / 10.0.0.1\n0.2 GET / 10.0.0.1";
+    /// let requests = log.lines().map(|line| line.parse::<RecordedRequest>().unwrap());
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_second(1)
+    ///     .burst_size(2)
+    ///     .finish()
+    ///     .unwrap();
+    /// let report = config.policy().replay(requests);
+    /// assert_eq!(report.denied, 1);
+    /// ```
+    pub fn replay(&self, requests: impl IntoIterator<Item = RecordedRequest>) -> ReplayReport {
+        let requests: Vec<_> = requests.into_iter().collect();
+        let outcomes = self.simulate(&requests);
+
+        let mut report = ReplayReport::default();
+        for (request, outcome) in requests.into_iter().zip(outcomes) {
+            report.total += 1;
+            match outcome {
+                SimulatedOutcome::Allowed => report.allowed += 1,
+                SimulatedOutcome::Whitelisted => report.whitelisted += 1,
+                SimulatedOutcome::Denied => {
+                    report.denied += 1;
+                    *report.denied_by_key.entry(request.key).or_insert(0) += 1;
+                }
+            }
+        }
+        report
+    }
+
+    /// Run requests against the quota of this policy under virtual time and return
+    /// the decision for every request, in the order of the given requests.
+    ///
+    /// Like [`replay`](Self::replay), but for synthetic traces whose exact allow and deny
+    /// sequence matters, for example to check in CI how a change of the quota
+    /// affects a known traffic pattern:
+    ///
+    /// ```rust
+    /// use actix_governor::{GovernorConfigBuilder, RecordedRequest, SimulatedOutcome};
+    /// use actix_web::http::Method;
+    /// use std::time::Duration;
+    ///
+    /// // A client that sends a request every 200ms
+    /// let trace: Vec<_> = (0..6)
+    ///     .map(|i| RecordedRequest {
+    ///         timestamp: Duration::from_millis(200 * i),
+    ///         method: Method::GET,
+    ///         path: "/".to_owned(),
+    ///         key: "client".to_owned(),
+    ///     })
+    ///     .collect();
+    ///
+    /// let config = GovernorConfigBuilder::default()
+    ///     .per_millisecond(500)
+    ///     .burst_size(2)
+    ///     .finish()
+    ///     .unwrap();
+    /// let outcomes = config.policy().simulate(&trace);
+    /// assert_eq!(outcomes[2], SimulatedOutcome::Denied);
+    /// ```
+    ///
+    /// The requests are simulated in the order of their timestamps, nothing sleeps.
+    pub fn simulate(&self, requests: &[RecordedRequest]) -> Vec<SimulatedOutcome> {
+        let mut order: Vec<_> = (0..requests.len()).collect();
+        order.sort_by_key(|&i| requests[i].timestamp);
+
+        let clock = FakeRelativeClock::default();
+        let keyed = |quota: Quota| -> KeyedLimiter<'_> {
+            RateLimiter::new(quota, DefaultKeyedStateStore::default(), &clock)
+        };
+        let limiter = keyed(
+            QuotaOverride::new(self.period, self.burst_size)
+                .unwrap()
+                .quota(),
+        );
+        let mut overrides: HashMap<QuotaOverride, KeyedLimiter<'_>> = HashMap::new();
+        let windows: Vec<_> = self
+            .additional_quotas
+            .iter()
+            .map(|window| keyed(window.quota()))
+            .collect();
+        let global = self
+            .global_quota
+            .map(|quota| RateLimiter::direct_with_clock(quota.quota(), &clock));
+        let cold_start = self.cold_start.map(|cold_start| {
+            let quota = QuotaOverride::new(cold_start.period, cold_start.burst_size).unwrap();
+            (keyed(quota.quota()), cold_start.probation)
+        });
+        // The start of the probation and the last request of each key.
+        let mut probations: HashMap<&str, (Duration, Duration)> = HashMap::new();
+
+        let mut outcomes = vec![SimulatedOutcome::Allowed; requests.len()];
+        let mut now = order.first().map(|&i| requests[i].timestamp);
+        for i in order {
+            let request = &requests[i];
+            if let Some(now) = &mut now {
+                clock.advance(request.timestamp - *now);
+                *now = request.timestamp;
+            }
+            let quota = match self.route(&request.method, &request.path) {
+                Route::Exempt => {
+                    outcomes[i] = SimulatedOutcome::Whitelisted;
+                    continue;
+                }
+                Route::Limited(quota) => quota,
+            };
+            let key = request.key.as_str();
+
+            if let Some((cold_limiter, probation)) = &cold_start {
+                let now = request.timestamp;
+                let (start, last_seen) = probations.entry(key).or_insert((now, now));
+                // Keys that were idle for another probation period after theirs count as new.
+                if now.saturating_sub((*last_seen).max(*start + *probation)) >= *probation {
+                    *start = now;
+                }
+                *last_seen = now;
+                if now - *start < *probation && cold_limiter.check_key(&key).is_err() {
+                    // Denied keys have to start their probation from scratch.
+                    *start = now;
+                    outcomes[i] = SimulatedOutcome::Denied;
+                    continue;
+                }
+            }
+            let limiter = match quota {
+                Some(quota) => overrides
+                    .entry(quota)
+                    .or_insert_with(|| keyed(quota.quota())),
+                None => &limiter,
+            };
+            let allowed = limiter.check_key(&key).is_ok()
+                && windows.iter().all(|window| window.check_key(&key).is_ok())
+                && global.as_ref().is_none_or(|global| global.check().is_ok());
+            if !allowed {
+                outcomes[i] = SimulatedOutcome::Denied;
+            }
+        }
+        outcomes
+    }
+}
@@ -0,0 +1,168 @@
+use crate::QuotaOverride;
+
+use actix_web::dev::ServiceRequest;
+use actix_web::http::Method;
+
+/// A quota for the requests whose path matches a pattern,
+/// see [`rule`](crate::GovernorConfigBuilder::rule()).
+///
+/// ```rust
+/// use actix_governor::{PathRule, QuotaOverride};
+/// use actix_web::http::Method;
+/// use std::time::Duration;
+///
+/// let strict = QuotaOverride::new(Duration::from_secs(10), 5).unwrap();
+/// let rule = PathRule::glob("/api/*/orders/**", strict).methods(vec![Method::POST]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct PathRule {
+    pattern: PathPattern,
+    methods: Option<Vec<Method>>,
+    quota: QuotaOverride,
+}
+
+#[derive(Debug, Clone)]
+enum PathPattern {
+    /// The segments of a glob pattern.
+    Glob(Vec<String>),
+    #[cfg(feature = "regex")]
+    Regex(regex::Regex),
+}
+
+impl PartialEq for PathPattern {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (PathPattern::Glob(a), PathPattern::Glob(b)) => a == b,
+            #[cfg(feature = "regex")]
+            (PathPattern::Regex(a), PathPattern::Regex(b)) => a.as_str() == b.as_str(),
+            #[cfg(feature = "regex")]
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for PathRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.pattern == other.pattern && self.methods == other.methods && self.quota == other.quota
+    }
+}
+
+impl Eq for PathRule {}
+
+impl PathRule {
+    /// A rule for the paths that match the glob pattern.
+    ///
+    /// The pattern is matched segment by segment: `*` matches any characters within a segment,
+    /// like `/files/*.json`, and a `**` segment matches any number of segments,
+    /// like `/api/**/export`. Trailing slashes are ignored.
+    pub fn glob(pattern: &str, quota: QuotaOverride) -> Self {
+        PathRule {
+            pattern: PathPattern::Glob(segments(pattern).map(str::to_owned).collect()),
+            methods: None,
+            quota,
+        }
+    }
+
+    /// A rule for the paths that match the regular expression.
+    ///
+    /// The expression has to match the whole path, `^` and `$` are implied.
+    /// Returns an error if the expression is invalid.
+    #[cfg(feature = "regex")]
+    pub fn regex(pattern: &str, quota: QuotaOverride) -> Result<Self, regex::Error> {
+        Ok(PathRule {
+            pattern: PathPattern::Regex(regex::Regex::new(&format!("^(?:{pattern})$"))?),
+            methods: None,
+            quota,
+        })
+    }
+
+    /// Only apply the rule to requests with one of the given methods.
+    /// By default it applies to all methods.
+    pub fn methods(mut self, methods: Vec<Method>) -> Self {
+        self.methods = Some(methods);
+        self
+    }
+
+    pub(crate) fn matches(&self, req: &ServiceRequest) -> bool {
+        self.matches_path(req.method(), req.path())
+    }
+
+    pub(crate) fn matches_path(&self, method: &Method, path: &str) -> bool {
+        if let Some(methods) = &self.methods {
+            if !methods.contains(method) {
+                return false;
+            }
+        }
+        match &self.pattern {
+            PathPattern::Glob(pattern) => {
+                matches_segments(pattern, &segments(path).collect::<Vec<_>>())
+            }
+            #[cfg(feature = "regex")]
+            PathPattern::Regex(regex) => regex.is_match(path),
+        }
+    }
+
+    pub(crate) fn quota(&self) -> QuotaOverride {
+        self.quota
+    }
+
+    /// The pattern of the rule, globs without trailing slashes.
+    pub(crate) fn pattern(&self) -> String {
+        match &self.pattern {
+            PathPattern::Glob(segments) => format!("/{}", segments.join("/")),
+            #[cfg(feature = "regex")]
+            PathPattern::Regex(regex) => {
+                let pattern = regex.as_str();
+                pattern["^(?:".len()..pattern.len() - ")$".len()].to_owned()
+            }
+        }
+    }
+
+    pub(crate) fn is_regex(&self) -> bool {
+        !matches!(self.pattern, PathPattern::Glob(_))
+    }
+
+    pub(crate) fn method_filter(&self) -> Option<&[Method]> {
+        self.methods.as_deref()
+    }
+}
+
+fn segments(path: &str) -> impl Iterator<Item = &str> {
+    path.split('/').filter(|segment| !segment.is_empty())
+}
+
+fn matches_segments(pattern: &[String], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((first, rest)) if first == "**" => {
+            (0..=path.len()).any(|skipped| matches_segments(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => match path.split_first() {
+            Some((segment, path)) => {
+                matches_segment(first, segment) && matches_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Whether the segment matches the pattern, in which `*` matches any characters.
+fn matches_segment(pattern: &str, segment: &str) -> bool {
+    let (prefix, rest) = match pattern.split_once('*') {
+        Some(split) => split,
+        None => return pattern == segment,
+    };
+    let mut remaining = match segment.strip_prefix(prefix) {
+        Some(remaining) => remaining,
+        None => return false,
+    };
+    let parts: Vec<&str> = rest.split('*').collect();
+    let (suffix, middle) = parts.split_last().unwrap();
+    for part in middle {
+        match remaining.find(part) {
+            Some(index) => remaining = &remaining[index + part.len()..],
+            None => return false,
+        }
+    }
+    remaining.ends_with(suffix)
+}
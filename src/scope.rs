@@ -0,0 +1,116 @@
+use crate::QuotaOverride;
+
+/// Overrides of the configured policy for the requests below a path,
+/// see [`scope`](crate::GovernorConfigBuilder::scope()).
+///
+/// Settings that are not overridden are inherited from the enclosing scope,
+/// or from the configuration for the outermost scopes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ScopePolicy {
+    quota: Option<QuotaOverride>,
+    exempt: Option<bool>,
+}
+
+impl ScopePolicy {
+    /// A policy that inherits everything.
+    pub fn new() -> Self {
+        ScopePolicy::default()
+    }
+
+    /// Use `quota` for the requests of the scope.
+    ///
+    /// The keys get an independent bucket for every distinct quota, like with [`QuotaOverride`].
+    pub fn quota(mut self, quota: QuotaOverride) -> Self {
+        self.quota = Some(quota);
+        self
+    }
+
+    /// Don't rate limit the requests of the scope.
+    pub fn exempt(mut self) -> Self {
+        self.exempt = Some(true);
+        self
+    }
+
+    /// Rate limit the requests of the scope, even if the enclosing scope is exempt.
+    pub fn limited(mut self) -> Self {
+        self.exempt = Some(false);
+        self
+    }
+
+    /// Fill the settings that are not overridden with the settings of `parent`.
+    fn inherit(self, parent: ScopePolicy) -> Self {
+        ScopePolicy {
+            quota: self.quota.or(parent.quota),
+            exempt: self.exempt.or(parent.exempt),
+        }
+    }
+
+    /// The quota of the requests of the scope, if it has its own.
+    pub fn quota_override(&self) -> Option<QuotaOverride> {
+        self.quota
+    }
+
+    /// Whether the requests of the scope are not rate limited.
+    pub fn is_exempt(&self) -> bool {
+        self.exempt.unwrap_or(false)
+    }
+}
+
+/// The scopes with the settings of their enclosing scopes merged in, longest path first.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ScopeTable(Vec<(String, ScopePolicy)>);
+
+impl ScopeTable {
+    pub(crate) fn new(scopes: &[(String, ScopePolicy)]) -> Self {
+        let mut declared: Vec<(String, ScopePolicy)> = Vec::new();
+        for (path, policy) in scopes {
+            let path = normalize(path);
+            match declared.iter_mut().find(|(declared, _)| *declared == path) {
+                // Later declarations of the same scope override the earlier ones.
+                Some((_, declared)) => *declared = policy.inherit(*declared),
+                None => declared.push((path, *policy)),
+            }
+        }
+        declared.sort_by_key(|(path, _)| path.len());
+
+        let mut resolved: Vec<(String, ScopePolicy)> = Vec::with_capacity(declared.len());
+        for (path, policy) in declared {
+            // The parents are already resolved, the longest one is the closest.
+            let parent = resolved
+                .iter()
+                .rev()
+                .find(|(parent, _)| contains(parent, &path))
+                .map(|(_, parent)| *parent)
+                .unwrap_or_default();
+            resolved.push((path, policy.inherit(parent)));
+        }
+        resolved.reverse();
+        ScopeTable(resolved)
+    }
+
+    /// The scopes and their merged settings, longest path first.
+    pub(crate) fn scopes(&self) -> &[(String, ScopePolicy)] {
+        &self.0
+    }
+
+    /// The policy of the innermost scope of the path.
+    pub(crate) fn lookup(&self, path: &str) -> Option<&ScopePolicy> {
+        self.0
+            .iter()
+            .find(|(scope, _)| contains(scope, path))
+            .map(|(_, policy)| policy)
+    }
+}
+
+/// Remove the trailing slash, so `/api/` and `/api` are the same scope and `/` is the empty path.
+fn normalize(path: &str) -> String {
+    path.trim_end_matches('/').to_owned()
+}
+
+/// Whether the path is the scope or below it, `/api` contains `/api/users` but not `/apis`.
+fn contains(scope: &str, path: &str) -> bool {
+    match path.strip_prefix(scope) {
+        Some(rest) => rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
@@ -1,233 +1,1360 @@
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
-use actix_web::http::header::{HeaderName, HeaderValue};
-use actix_web::{body::MessageBody, error, Error};
-use futures::future;
-use governor::clock::{Clock, DefaultClock};
-use governor::middleware::{NoOpMiddleware, StateInformationMiddleware};
+use actix_web::http::header::{ContentType, HeaderMap, HeaderName, HeaderValue, WARNING};
+use actix_web::http::Version;
+use actix_web::{body::MessageBody, error, Error, HttpMessage};
+use futures::future::{self, FutureExt, LocalBoxFuture};
+use governor::clock::{Clock, DefaultClock, QuantaInstant};
+use governor::middleware::{
+    NoOpMiddleware, RateLimitingMiddleware, StateInformationMiddleware, StateSnapshot,
+};
+use governor::NotUntil;
+use pin_project_lite::pin_project;
 
 use std::future::Future;
-use std::marker::Unpin;
+use std::num::NonZeroU32;
 use std::pin::Pin;
+use std::sync::{Arc, OnceLock};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use crate::{GovernorMiddleware, KeyExtractor};
+use crate::capture::CaptureState;
+use crate::concurrency::{ConcurrencyLimit, InFlightGuard};
+use crate::cost::{check_cost, check_global_cost, CostCharge, RequestCosts};
+use crate::debug_key;
+use crate::decision::DecisionId;
+use crate::dialect::HeaderDialect;
+use crate::key_extractor::KeyDecision;
+use crate::key_headers::{KeyHeaders, KeySnapshot};
+use crate::policy::PolicyLabels;
+use crate::refund::RefundTicket;
+use crate::scope::ScopePolicy;
+use crate::singleflight::ExtractedKey;
+use crate::streaming::StreamCharges;
+use crate::time_budget::{TimeBudget, TimeCharge};
+use crate::{GovernorMiddleware, KeyExtractor, QuotaOverride, SharedRateLimiter};
 
-impl<S, B, K> Service<ServiceRequest> for GovernorMiddleware<S, K, NoOpMiddleware>
+const DECISION_ID: HeaderName = HeaderName::from_static("x-ratelimit-decision-id");
+const POLICY: HeaderName = HeaderName::from_static("x-ratelimit-policy");
+const LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const AFTER: HeaderName = HeaderName::from_static("x-ratelimit-after");
+const WHITELISTED: HeaderName = HeaderName::from_static("x-ratelimit-whitelisted");
+const CONCURRENCY_LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-concurrency-limit");
+const CONCURRENCY_REMAINING: HeaderName =
+    HeaderName::from_static("x-ratelimit-concurrency-remaining");
+
+/// Numbers below this are formatted once and shared by all responses.
+const CACHED_NUMBERS: u64 = 1024;
+
+/// The header value of a number, like the limit or the remaining requests.
+fn number_value(number: u64) -> HeaderValue {
+    static CACHE: OnceLock<Vec<HeaderValue>> = OnceLock::new();
+    if number >= CACHED_NUMBERS {
+        return number.into();
+    }
+    let cache = CACHE.get_or_init(|| (0..CACHED_NUMBERS).map(HeaderValue::from).collect());
+    cache[number as usize].clone()
+}
+
+/// Reason why the governor middleware denied a request.
+///
+/// It is inserted into the extensions of the error response, so that other middleware like
+/// [`ErrorHandlers`](actix_web::middleware::ErrorHandlers) can tell denials apart.
+///
+/// ```rust
+/// use actix_governor::DenialReason;
+/// use actix_web::{dev::ServiceResponse, middleware::ErrorHandlerResponse, Result};
+///
+/// fn log_denial<B>(res: ServiceResponse<B>) -> Result<ErrorHandlerResponse<B>> {
+///     if let Some(reason) = res.response().extensions().get::<DenialReason>() {
+///         println!("request denied: {:?}", reason);
+///     }
+///     Ok(ErrorHandlerResponse::Response(res.map_into_left_body()))
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DenialReason {
+    /// The quota of the key was exceeded.
+    RateLimited,
+    /// The key already has too many requests in flight.
+    ConcurrencyLimited,
+    /// The rate limiting key could not be extracted from the request.
+    ExtractionFailed,
+    /// The key extractor blocked the request, e.g. because of the country of the client.
+    Blocklisted,
+}
+
+/// Turn the response into an error and attach the denial reason and decision id to it.
+fn deny(
+    cause: impl std::fmt::Debug + std::fmt::Display + 'static,
+    mut response: actix_web::HttpResponse,
+    reason: DenialReason,
+    decision: DecisionId,
+    labels: Option<&PolicyLabels>,
+    use_headers: bool,
+    dialect: Option<&HeaderDialect>,
+) -> Error {
+    response.extensions_mut().insert(reason);
+    response.extensions_mut().insert(decision);
+    if let Ok(header_value) = HeaderValue::from_str(&decision.to_string()) {
+        response.headers_mut().insert(DECISION_ID, header_value);
+    }
+    if let Some(labels) = labels {
+        if let (true, Some(header_value)) = (use_headers, labels.header_value()) {
+            response.headers_mut().insert(POLICY, header_value.clone());
+        }
+        response.extensions_mut().insert(labels.clone());
+    }
+    if let Some(dialect) = dialect {
+        dialect.apply(response.headers_mut());
+    }
+    error::InternalError::from_response(cause, response).into()
+}
+
+/// Error returned if the rate limiting key could not be extracted.
+fn extraction_failed(
+    cause: impl std::fmt::Display,
+    decision: DecisionId,
+    labels: Option<&PolicyLabels>,
+    use_headers: bool,
+    dialect: Option<&HeaderDialect>,
+) -> Error {
+    let cause = cause.to_string();
+    let response = actix_web::HttpResponse::Unauthorized()
+        .insert_header(ContentType::plaintext())
+        .body(cause.clone());
+    deny(
+        cause,
+        response,
+        DenialReason::ExtractionFailed,
+        decision,
+        labels,
+        use_headers,
+        dialect,
+    )
+}
+
+/// Error returned if the key extractor or a [`KeyBlocks`](crate::KeyBlocks) handle
+/// blocked the request.
+fn blocked(
+    cause: &str,
+    decision: DecisionId,
+    labels: Option<&PolicyLabels>,
+    use_headers: bool,
+    dialect: Option<&HeaderDialect>,
+) -> Error {
+    let response = actix_web::HttpResponse::Forbidden()
+        .insert_header(ContentType::plaintext())
+        .body(cause.to_owned());
+    deny(
+        cause.to_owned(),
+        response,
+        DenialReason::Blocklisted,
+        decision,
+        labels,
+        use_headers,
+        dialect,
+    )
+}
+
+impl<S, K, M> GovernorMiddleware<S, K, M>
 where
     K: KeyExtractor,
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
-    B: MessageBody,
+    M: RateLimitingMiddleware<QuantaInstant>,
 {
-    type Response = S::Response;
-    type Error = S::Error;
-    type Future =
-        future::Either<future::Ready<Result<ServiceResponse<B>, actix_web::Error>>, S::Future>;
+    /// Deny the request and handle its body according to the configured policy.
+    fn reject<B>(
+        &self,
+        req: &mut ServiceRequest,
+        denial: Error,
+    ) -> future::Either<future::Ready<Result<ServiceResponse<B>, Error>>, DenialNoteFut<B>> {
+        let denial = debug_key::apply_to_error(denial, self.key_header(req));
+        match self.state.denied_payload {
+            Some(policy) => future::Either::Right(
+                policy
+                    .reject(req, future::ready(denial).boxed_local())
+                    .map(Err),
+            ),
+            None => future::Either::Left(future::err(denial)),
+        }
+    }
 
-    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.service.poll_ready(cx)
+    /// Check the key against the cold start quota, if configured.
+    fn check_cold_start(&self, key: &K::Key) -> Result<(), NotUntil<QuantaInstant>> {
+        match &self.state.cold_start {
+            Some(cold_start) => cold_start.check_key(key),
+            None => Ok(()),
+        }
     }
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        if let Some(configured_methods) = &self.methods {
-            if !configured_methods.contains(req.method()) {
-                // The request method is not configured, we're ignoring this one.
-                let fut = self.service.call(req);
-                return future::Either::Right(fut);
+    /// Check the key against its quota, the additional quotas and the global quota.
+    ///
+    /// Keys that an additional quota denied before are denied right away until that denial
+    /// ends, so the wait of the most restrictive quota is reported.
+    fn check_quotas(
+        &self,
+        limiter: &SharedRateLimiter<K::Key, M>,
+        key: &K::Key,
+        quota: Option<QuotaOverride>,
+        cost: NonZeroU32,
+    ) -> Result<Admission<M::PositiveOutcome>, QuotaDenial>
+    where
+        M: RateLimitInfo,
+    {
+        if let Some(windows) = &self.state.windows {
+            if let Some((wait, burst_size)) = windows.denied(key) {
+                return Err(QuotaDenial { wait, burst_size });
             }
         }
+        let admission = self.check_key(limiter, key, quota, cost)?;
+        let admission = self.check_windows(key, cost, admission)?;
+        self.check_global(cost, admission).inspect_err(|_| {
+            // The request wasn't admitted, give its cost back to the quotas of the key.
+            // The refunds are capped at the burst sizes, like the charges of the quotas.
+            self.state.refunds.refund(key.clone(), quota, cost.get());
+            if let Some(windows) = &self.state.windows {
+                windows.refund(key, cost.get());
+            }
+        })
+    }
 
-        // Use the provided key extractor to extract the rate limiting key from the request.
-        match self.key_extractor.extract(&req) {
-            // Extraction worked, let's check if rate limiting is needed.
-            Ok(key) => match self.limiter.check_key(&key) {
-                Ok(_) => {
-                    let fut = self.service.call(req);
-                    future::Either::Right(fut)
+    /// Check the key against its own quota, after the cold start quota if configured.
+    /// If the quota is used up, the refunded or released requests of the bucket of the key
+    /// are used instead, but only if there are enough of them for the whole cost.
+    fn check_key(
+        &self,
+        limiter: &SharedRateLimiter<K::Key, M>,
+        key: &K::Key,
+        quota: Option<QuotaOverride>,
+        cost: NonZeroU32,
+    ) -> Result<Admission<M::PositiveOutcome>, QuotaDenial>
+    where
+        M: RateLimitInfo,
+    {
+        self.check_cold_start(key)
+            .map_err(|negative| QuotaDenial::new(&negative))?;
+        match check_cost(limiter, key, cost) {
+            Ok(outcome) => Ok(Admission::Quota(outcome)),
+            Err(negative) => {
+                let burst_size = negative.quota().burst_size().get();
+                // Costs above the burst size are capped, like the charge of the quota.
+                let credits = cost.get().min(burst_size);
+                if self.is_enforced(key) && self.state.refunds.take_credits(key, quota, credits) {
+                    Ok(Admission::Refunded { burst_size })
+                } else {
+                    Err(QuotaDenial::new(&negative))
                 }
+            }
+        }
+    }
 
+    /// Check the key against the additional quotas, if configured.
+    /// Returns the admission with the fewest remaining requests.
+    fn check_windows(
+        &self,
+        key: &K::Key,
+        cost: NonZeroU32,
+        admission: Admission<M::PositiveOutcome>,
+    ) -> Result<Admission<M::PositiveOutcome>, QuotaDenial>
+    where
+        M: RateLimitInfo,
+    {
+        let windows = match &self.state.windows {
+            Some(windows) => windows,
+            None => return Ok(admission),
+        };
+        let mut most_restrictive = admission;
+        for (index, window) in windows.limiters().iter().enumerate() {
+            let outcome = match check_cost(window, key, cost) {
+                Ok(outcome) => Admission::Quota(outcome),
                 Err(negative) => {
-                    let wait_time = negative
-                        .wait_time_from(DefaultClock::default().now())
-                        .as_secs();
+                    let denial = QuotaDenial::new(&negative);
+                    let credits = cost.get().min(denial.burst_size);
+                    if self.is_enforced(key) && windows.take_credits(index, key, credits) {
+                        most_restrictive = Admission::Refunded {
+                            burst_size: denial.burst_size,
+                        };
+                        continue;
+                    }
+                    if cost == NonZeroU32::MIN {
+                        windows.deny(key, denial.wait, denial.burst_size);
+                    }
+                    return Err(denial);
+                }
+            };
+            if outcome.remaining::<M>() < most_restrictive.remaining::<M>() {
+                most_restrictive = outcome;
+            }
+        }
+        Ok(most_restrictive)
+    }
+
+    /// Check the request against the global quota, if configured.
+    /// Returns the admission with fewer remaining requests.
+    fn check_global(
+        &self,
+        cost: NonZeroU32,
+        admission: Admission<M::PositiveOutcome>,
+    ) -> Result<Admission<M::PositiveOutcome>, QuotaDenial>
+    where
+        M: RateLimitInfo,
+    {
+        let global_limiter = match &self.state.global_limiter {
+            Some(global_limiter) => global_limiter,
+            None => return Ok(admission),
+        };
+        let global_outcome = Admission::Quota(
+            check_global_cost(global_limiter, cost)
+                .map_err(|negative| QuotaDenial::new(&negative))?,
+        );
+        Ok(
+            if global_outcome.remaining::<M>() < admission.remaining::<M>() {
+                global_outcome
+            } else {
+                admission
+            },
+        )
+    }
 
+    /// Whether the requested file has one of the exempt extensions.
+    fn is_exempt_extension(&self, req: &ServiceRequest) -> bool {
+        self.state.policy.is_exempt_extension(req.path())
+    }
+
+    /// Check the peer IP against the pre-limit, if configured.
+    /// Returns the seconds to wait and the id of the denial if it is exceeded.
+    fn check_pre_limit(&self, req: &ServiceRequest) -> Option<(u64, DecisionId)> {
+        let pre_limiter = self.state.pre_limiter.as_ref()?;
+        let ip = req.peer_addr()?.ip();
+        match pre_limiter.check_key(&ip) {
+            Ok(()) => None,
+            // There is no key yet to capture the denial for.
+            #[cfg(feature = "log")]
+            Err(negative) if self.is_shadowed() => {
+                if let Some(level) = self.state.denial_log.level() {
+                    log::log!(
+                        level,
+                        "Shadow mode, would deny peer IP [{}] (pre_limited), retry in {}s",
+                        ip,
+                        negative
+                            .wait_time_from(DefaultClock::default().now())
+                            .as_secs()
+                    );
+                }
+                None
+            }
+            #[cfg(not(feature = "log"))]
+            Err(_) if self.is_shadowed() => None,
+            Err(negative) => {
+                let wait_time = negative
+                    .wait_time_from(DefaultClock::default().now())
+                    .as_secs();
+                let decision = DecisionId::next();
+                #[cfg(feature = "log")]
+                if let Some(level) = self.state.denial_log.level() {
+                    log::log!(
+                        level,
+                        "Pre-limit exceeded for peer IP [{}], quota reset in {}s (decision {})",
+                        ip,
+                        &wait_time,
+                        decision
+                    );
+                }
+                Some((wait_time, decision))
+            }
+        }
+    }
+
+    /// Check the key against a streaming or time budget, `reason` tells them apart.
+    /// Returns the seconds to wait and the id of the denial if the budget is used up.
+    fn check_budget(
+        &self,
+        budget: &TimeBudget<K::Key>,
+        key: &K::Key,
+        reason: &'static str,
+    ) -> Option<(u64, DecisionId)> {
+        let wait_time = budget.check(key).err()?.as_secs();
+        if self.shadow_denial(key, reason, Some(wait_time)) {
+            return None;
+        }
+        let decision = DecisionId::next();
+        #[cfg(feature = "log")]
+        if let Some(level) = self.state.denial_log.level() {
+            let key_name = self.log_name(key);
+            log::log!(
+                level,
+                "{} exceeded for {}, retry in {}s (decision {})",
+                if reason == "streaming_budget" {
+                    "Streaming budget"
+                } else {
+                    "Time budget"
+                },
+                key_name,
+                &wait_time,
+                decision
+            );
+        }
+        self.state.captures.record(
+            key,
+            CaptureState::Denied {
+                reason,
+                wait_time: Some(wait_time),
+                decision,
+            },
+        );
+        Some((wait_time, decision))
+    }
+
+    /// The quota that replaces the configured quota for the request, if any.
+    ///
+    /// Earlier middleware may have replaced the quota for this request,
+    /// otherwise a path rule, the scope, the method, the key extractor or the key
+    /// may have its own quota or the quota was reloaded.
+    fn resolve_quota(
+        &self,
+        req: &ServiceRequest,
+        key: &K::Key,
+        key_decision: Option<KeyDecision>,
+        scope: Option<ScopePolicy>,
+    ) -> Option<QuotaOverride> {
+        req.extensions()
+            .get::<QuotaOverride>()
+            .copied()
+            .or_else(|| {
+                self.state.rules.as_ref().and_then(|rules| {
+                    rules
+                        .iter()
+                        .find(|rule| rule.matches(req))
+                        .map(|rule| rule.quota())
+                })
+            })
+            .or_else(|| scope.and_then(|scope| scope.quota_override()))
+            .or_else(|| {
+                self.state
+                    .method_quotas
+                    .as_ref()
+                    .and_then(|quotas| quotas.get(req.method()).copied())
+            })
+            .or(match key_decision {
+                Some(KeyDecision::Quota(quota)) => Some(quota),
+                _ => None,
+            })
+            .or_else(|| {
+                self.state
+                    .quota_resolver
+                    .as_ref()
+                    .and_then(|resolver| resolver.resolve(key))
+            })
+            .or_else(|| self.reloadable_quota.as_ref().and_then(|quota| quota.get()))
+    }
+
+    /// Whether the governor is in shadow mode or still warming up.
+    fn is_shadowed(&self) -> bool {
+        self.state.shadow
+            || self
+                .state
+                .warmup_until
+                .is_some_and(|warmup_until| Instant::now() < warmup_until)
+    }
+
+    /// Log and capture the denial instead of enforcing it, if the governor is in shadow mode.
+    /// Returns whether the request is let through.
+    fn shadow_denial(&self, key: &K::Key, reason: &'static str, wait_time: Option<u64>) -> bool {
+        if !self.is_shadowed() {
+            return false;
+        }
+        self.record_shadow_denial(key, reason, wait_time);
+        true
+    }
+
+    /// Log and capture a denial that is not enforced.
+    fn record_shadow_denial(&self, key: &K::Key, reason: &'static str, wait_time: Option<u64>) {
+        let decision = DecisionId::next();
+        #[cfg(feature = "log")]
+        if let Some(level) = self.state.denial_log.level() {
+            let key_name = self.log_name(key);
+            log::log!(
+                level,
+                "Not enforcing the denial of {} ({}), retry in {}s (decision {})",
+                key_name,
+                reason,
+                wait_time.unwrap_or_default(),
+                decision
+            );
+        }
+        self.state.captures.record(
+            key,
+            CaptureState::Shadowed {
+                reason,
+                wait_time,
+                decision,
+            },
+        );
+    }
+
+    /// Whether the slow start of the quota reached the key.
+    fn is_enforced(&self, key: &K::Key) -> bool {
+        match &self.state.ramp {
+            Some(ramp) => ramp.is_enforced(key),
+            None => true,
+        }
+    }
+
+    fn refund_ticket(
+        &self,
+        req: &ServiceRequest,
+        key: &K::Key,
+        quota: Option<QuotaOverride>,
+    ) -> Option<RefundTicket<K::Key>> {
+        self.state.refunds.ticket(key, quota, req.method())
+    }
+
+    /// The `x-ratelimit-key` header of the request, if enabled.
+    fn key_header(&self, req: &ServiceRequest) -> Option<HeaderValue> {
+        self.state.debug_key.as_ref()?;
+        debug_key::key_header(req)
+    }
+
+    /// Start measuring the handler time of the request, if it is limited.
+    fn latency_charge(&self, key: &K::Key) -> Option<TimeCharge<K::Key>> {
+        self.state
+            .time_budget
+            .as_ref()
+            .map(|time_budget| time_budget.start(key))
+    }
+
+    /// The `Warning` header of a request that reached the soft limit, if configured.
+    fn soft_limit_warning(&self, burst_state: Option<(u32, u32)>) -> Option<HeaderValue> {
+        let soft_limit = self.state.soft_limit?;
+        match burst_state {
+            Some((_, remaining)) if remaining <= soft_limit => HeaderValue::from_str(&format!(
+                "199 - \"rate limit nearly exceeded, {remaining} requests remaining\""
+            ))
+            .ok(),
+            _ => None,
+        }
+    }
+
+    fn policy_header(&self) -> Option<HeaderValue> {
+        self.state
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.header_value())
+            .cloned()
+    }
+
+    fn key_headers(&self, key: &K::Key) -> Option<(KeyHeaders<K::Key>, K::Key)> {
+        self.state
+            .key_headers
+            .as_ref()
+            .map(|key_headers| (key_headers.clone(), key.clone()))
+    }
+
+    /// Describe the key and the policy in logs.
+    #[cfg(feature = "log")]
+    fn log_name(&self, key: &K::Key) -> String {
+        let mut name = self.state.key_extractor.name().to_owned();
+        if let Some(key_name) = self.state.key_extractor.key_name(key) {
+            name.push_str(&format!(" [{}]", key_name));
+        }
+        if let Some(policy_name) = self.state.labels.as_ref().and_then(|labels| labels.name()) {
+            name.push_str(&format!(" (policy {})", policy_name));
+        }
+        name
+    }
+
+    /// Whether the request presents a valid exemption token.
+    #[cfg(feature = "exemption")]
+    fn is_exempt(&self, req: &ServiceRequest, key: &K::Key) -> bool {
+        let exemptions = match &self.state.exemptions {
+            Some(exemptions) => exemptions,
+            None => return false,
+        };
+        let token = match req.headers().get(exemptions.header_name()) {
+            Some(token) => token.to_str().unwrap_or_default(),
+            None => return false,
+        };
+        let result = exemptions.check(token, key);
+
+        #[cfg(feature = "log")]
+        {
+            let key_name = self.log_name(key);
+            match &result {
+                Ok(()) => log::info!("Exemption token accepted for {}", key_name,),
+                Err(e) => log::warn!("Exemption token rejected for {}: {}", key_name, e,),
+            }
+        }
+
+        result.is_ok()
+    }
+
+    #[cfg(not(feature = "exemption"))]
+    fn is_exempt(&self, _req: &ServiceRequest, _key: &K::Key) -> bool {
+        false
+    }
+
+    /// Register the request as in-flight, if the concurrency is limited.
+    /// Returns the maximum concurrency and the id of the denial if the key already reached it.
+    fn acquire_concurrency(
+        &self,
+        limit: Option<&Arc<ConcurrencyLimit<K::Key>>>,
+        key: &K::Key,
+    ) -> Result<Option<InFlightGuard<K::Key>>, (u32, DecisionId)> {
+        match limit {
+            Some(concurrency) => match concurrency.acquire(key) {
+                Some(guard) => Ok(Some(guard)),
+                None if self.shadow_denial(key, "concurrency_limited", None) => Ok(None),
+                None => {
+                    let decision = DecisionId::next();
                     #[cfg(feature = "log")]
-                    {
-                        let key_name = match self.key_extractor.key_name(&key) {
-                            Some(n) => format!(" [{}]", &n),
-                            None => "".to_owned(),
-                        };
-                        log::info!(
-                            "Rate limit exceeded for {}{}, quota reset in {}s",
-                            self.key_extractor.name(),
+                    if let Some(level) = self.state.denial_log.level() {
+                        let key_name = self.log_name(key);
+                        log::log!(
+                            level,
+                            "Concurrency limit exceeded for {} (decision {})",
                             key_name,
-                            &wait_time
+                            decision
                         );
                     }
-
-                    let body = format!("{{\"ok\":false,\"error_code\":429,\"description\":\"Too Many Requests: retry after {wait_time}s\"}}");
-                    let response = actix_web::HttpResponse::TooManyRequests()
-                        .insert_header(("content-type", "application/json"))
-                        .insert_header(("x-ratelimit-after", wait_time))
-                        .body(body.clone());
-                    future::Either::Left(future::err(
-                        error::InternalError::from_response(body, response).into(),
-                    ))
+                    self.state.captures.record(
+                        key,
+                        CaptureState::Denied {
+                            reason: "concurrency_limited",
+                            wait_time: None,
+                            decision,
+                        },
+                    );
+                    Err((concurrency.max_concurrency(), decision))
                 }
             },
+            None => Ok(None),
+        }
+    }
 
-            // Extraction failed, stop right now with a HTTP 401 error.
-            Err(e) => future::Either::Left(future::err(error::ErrorUnauthorized(e.to_string()))),
+    /// Register the request as open stream, if it is a HTTP/2 request and the streams are limited.
+    fn acquire_stream(
+        &self,
+        req: &ServiceRequest,
+        key: &K::Key,
+    ) -> Result<Option<InFlightGuard<K::Key>>, (u32, DecisionId)> {
+        if req.version() == Version::HTTP_2 {
+            self.acquire_concurrency(self.state.streams.as_ref(), key)
+        } else {
+            Ok(None)
         }
     }
 }
 
-pub struct RateLimitHeaderFut<F>
-where
-    F: Future,
-{
-    future: F,
-    burst_size: u32,
-    remaining_burst_capacity: u32,
+/// Error returned if a key has too many requests in flight.
+fn concurrency_limit_exceeded(
+    max_concurrency: u32,
+    decision: DecisionId,
+    labels: Option<&PolicyLabels>,
+    use_headers: bool,
+    dialect: Option<&HeaderDialect>,
+) -> Error {
+    let body = format!("{{\"ok\":false,\"error_code\":429,\"description\":\"Too Many Requests: concurrency limit exceeded\",\"decision_id\":\"{decision}\"}}");
+    let mut response = actix_web::HttpResponse::TooManyRequests();
+    response.insert_header(("content-type", "application/json"));
+    if use_headers {
+        response
+            .insert_header((CONCURRENCY_LIMIT, number_value(max_concurrency.into())))
+            .insert_header((CONCURRENCY_REMAINING, number_value(0)));
+    }
+    deny(
+        body.clone(),
+        response.body(body),
+        DenialReason::ConcurrencyLimited,
+        decision,
+        labels,
+        use_headers,
+        dialect,
+    )
 }
 
-impl<F, B> Future for RateLimitHeaderFut<F>
-where
-    F: Future<Output = Result<ServiceResponse<B>, actix_web::Error>> + Unpin,
-    B: MessageBody,
-{
-    type Output = F::Output;
+/// Error returned if the quota of a key is used up.
+///
+/// `burst_size` is only given if the `x-ratelimit-limit` header should be added.
+fn rate_limit_exceeded(
+    wait_time: u64,
+    burst_size: Option<u32>,
+    note: Option<&str>,
+    decision: DecisionId,
+    labels: Option<&PolicyLabels>,
+    dialect: Option<&HeaderDialect>,
+) -> Error {
+    let note = match note {
+        Some(note) => format!(",\"note\":\"{}\"", escape_json(note)),
+        None => "".to_owned(),
+    };
+    let body = format!("{{\"ok\":false,\"error_code\":429,\"description\":\"Too Many Requests: retry after {wait_time}s\",\"decision_id\":\"{decision}\"{note}}}");
+    let mut response = actix_web::HttpResponse::TooManyRequests();
+    response
+        .insert_header(("content-type", "application/json"))
+        .insert_header((AFTER, number_value(wait_time)));
+    if let Some(burst_size) = burst_size {
+        response
+            .insert_header((LIMIT, number_value(burst_size.into())))
+            .insert_header((REMAINING, number_value(0)));
+    }
+    deny(
+        body.clone(),
+        response.body(body),
+        DenialReason::RateLimited,
+        decision,
+        labels,
+        burst_size.is_some(),
+        dialect,
+    )
+}
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match Future::poll(Pin::new(&mut self.future), cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(response) => Poll::Ready(match response {
-                Ok(mut response) => {
-                    let headers = response.headers_mut();
-                    headers.insert(
-                        HeaderName::from_static("x-ratelimit-limit"),
-                        self.burst_size.into(),
-                    );
-                    headers.insert(
-                        HeaderName::from_static("x-ratelimit-remaining"),
-                        self.remaining_burst_capacity.into(),
-                    );
-                    Ok(response)
-                }
-                Err(err) => Err(err),
-            }),
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
         }
     }
+    escaped
 }
 
-pub struct WhitelistedHeaderFut<F>
-where
-    F: Future,
+/// Denies the request once the note of the key is resolved or the body of the request is handled.
+pub type DenialNoteFut<B> = future::Map<
+    LocalBoxFuture<'static, Error>,
+    fn(Error) -> Result<ServiceResponse<B>, actix_web::Error>,
+>;
+
+/// Connects a [`RateLimitingMiddleware`] of the governor crate with the
+/// [`Governor`](crate::Governor) middleware.
+///
+/// It is implemented for [`NoOpMiddleware`] and [`StateInformationMiddleware`],
+/// which are used by default and by [`use_headers`](crate::GovernorConfigBuilder::use_headers()).
+/// Implement it for your own middleware and select it with
+/// [`with_middleware`](crate::GovernorConfigBuilder::with_middleware()) to plug it into the
+/// [`Governor`](crate::Governor) middleware.
+pub trait RateLimitInfo:
+    RateLimitingMiddleware<QuantaInstant, NegativeOutcome = NotUntil<QuantaInstant>>
 {
-    future: F,
+    /// Whether the `x-ratelimit-limit`, `x-ratelimit-remaining` and `x-ratelimit-whitelisted`
+    /// headers are added to the responses.
+    const USE_HEADERS: bool;
+
+    /// Returns the burst size and the remaining burst capacity of the key
+    /// after a positive decision, which are used for the headers.
+    fn burst_state(outcome: &Self::PositiveOutcome) -> Option<(u32, u32)>;
 }
 
-impl<F, B> Future for WhitelistedHeaderFut<F>
-where
-    F: Future<Output = Result<ServiceResponse<B>, actix_web::Error>> + Unpin,
-    B: MessageBody,
-{
-    type Output = F::Output;
+impl RateLimitInfo for NoOpMiddleware {
+    const USE_HEADERS: bool = false;
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        match Future::poll(Pin::new(&mut self.future), cx) {
-            Poll::Pending => Poll::Pending,
-            Poll::Ready(response) => Poll::Ready(match response {
-                Ok(mut response) => {
-                    let headers = response.headers_mut();
-                    headers.insert(
-                        HeaderName::from_static("x-ratelimit-whitelisted"),
-                        HeaderValue::from_static("true"),
-                    );
-                    Ok(response)
-                }
-                Err(err) => Err(err),
-            }),
+    fn burst_state(_outcome: &Self::PositiveOutcome) -> Option<(u32, u32)> {
+        None
+    }
+}
+
+impl RateLimitInfo for StateInformationMiddleware {
+    const USE_HEADERS: bool = true;
+
+    fn burst_state(snapshot: &StateSnapshot) -> Option<(u32, u32)> {
+        Some((
+            snapshot.quota().burst_size().get(),
+            snapshot.remaining_burst_capacity(),
+        ))
+    }
+}
+
+/// Why a quota denied a request.
+#[derive(Debug, Clone, Copy)]
+struct QuotaDenial {
+    /// The time until the request can be allowed.
+    wait: Duration,
+    /// The burst size of the quota that denied the request.
+    burst_size: u32,
+}
+
+impl QuotaDenial {
+    fn new(negative: &NotUntil<QuantaInstant>) -> Self {
+        QuotaDenial {
+            wait: negative.wait_time_from(DefaultClock::default().now()),
+            burst_size: negative.quota().burst_size().get(),
+        }
+    }
+}
+
+/// How a request passed the quota of its key.
+enum Admission<P> {
+    /// The quota allowed the request.
+    Quota(P),
+    /// The quota was used up, the request used refunded requests of the key instead.
+    Refunded { burst_size: u32 },
+}
+
+impl<P> Admission<P> {
+    /// The burst size and the remaining burst capacity, which are used for the headers.
+    fn burst_state<M: RateLimitInfo<PositiveOutcome = P>>(&self) -> Option<(u32, u32)> {
+        match self {
+            Admission::Quota(outcome) => M::burst_state(outcome),
+            Admission::Refunded { burst_size } => Some((*burst_size, 0)),
         }
     }
+
+    fn remaining<M: RateLimitInfo<PositiveOutcome = P>>(&self) -> Option<u32> {
+        self.burst_state::<M>().map(|(_, remaining)| remaining)
+    }
 }
 
-/// Implementation using rate limit headers
-impl<S, B, K> Service<ServiceRequest> for GovernorMiddleware<S, K, StateInformationMiddleware>
+impl<S, B, K, M> Service<ServiceRequest> for GovernorMiddleware<S, K, M>
 where
     K: KeyExtractor,
+    K::Key: 'static,
+    M: RateLimitInfo,
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     B: MessageBody,
-    S::Future: Unpin,
 {
     type Response = S::Response;
     type Error = S::Error;
-    type Future = future::Either<
-        future::Ready<Result<ServiceResponse<B>, actix_web::Error>>,
-        future::Either<RateLimitHeaderFut<S::Future>, WhitelistedHeaderFut<S::Future>>,
-    >;
+    type Future = GovernorFuture<S::Future, B, K::Key>;
 
     fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         self.service.poll_ready(cx)
     }
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        if let Some(configured_methods) = &self.methods {
-            if !configured_methods.contains(req.method()) {
-                // The request method is not configured, we're ignoring this one.
-                let fut = self.service.call(req);
-                return future::Either::Right(future::Either::Right(WhitelistedHeaderFut {
-                    future: fut,
-                }));
-            }
+    fn call(&self, mut req: ServiceRequest) -> Self::Future {
+        let switched_off = !self.state.enabled.is_enabled()
+            || self
+                .switch
+                .as_ref()
+                .is_some_and(|switch| !switch.is_enabled());
+        if switched_off {
+            // Rate limiting is switched off, pass the request through.
+            let fut = self.service.call(req);
+            return future::Either::Right(RateLimitHeaderFut::new(fut, M::USE_HEADERS, None));
+        }
+
+        let dialect = self
+            .state
+            .dialects
+            .as_ref()
+            .and_then(|dialects| dialects.select(&req));
+
+        let method_ignored = self
+            .state
+            .methods
+            .as_ref()
+            .is_some_and(|configured_methods| !configured_methods.contains(req.method()));
+        let scope = self
+            .state
+            .scopes
+            .as_ref()
+            .and_then(|scopes| scopes.lookup(req.path()))
+            .copied();
+        if method_ignored
+            || self.is_exempt_extension(&req)
+            || scope.is_some_and(|scope| scope.is_exempt())
+        {
+            // The request method is not configured or the file or scope is exempt,
+            // we're ignoring this one.
+            let fut = self.service.call(req);
+            return future::Either::Right(RateLimitHeaderFut {
+                whitelisted: true,
+                ..RateLimitHeaderFut::new(fut, M::USE_HEADERS, dialect)
+            });
         }
 
-        // Use the provided key extractor to extract the rate limiting key from the request.
-        match self.key_extractor.extract(&req) {
+        // Reject floods cheaply before the key extractor runs.
+        if let Some((wait_time, decision)) = self.check_pre_limit(&req) {
+            return future::Either::Left(self.reject(
+                &mut req,
+                rate_limit_exceeded(
+                    wait_time,
+                    None,
+                    None,
+                    decision,
+                    self.state.labels.as_ref(),
+                    dialect.as_ref(),
+                ),
+            ));
+        }
+
+        // Use the provided key extractor to extract the rate limiting key from the request,
+        // unless `Singleflight` did already.
+        let extracted = match ExtractedKey::take(&req, &self.state) {
+            Some(key) => Ok(key),
+            None => self.state.key_extractor.extract(&req),
+        };
+        // The decisions of the key extractor only apply to this governor.
+        let key_decision = req.extensions_mut().remove::<KeyDecision>();
+        match extracted {
             // Extraction worked, let's check if rate limiting is needed.
-            Ok(key) => match self.limiter.check_key(&key) {
-                Ok(snapshot) => {
-                    let fut = self.service.call(req);
-                    future::Either::Right(future::Either::Left(RateLimitHeaderFut {
-                        future: fut,
-                        burst_size: snapshot.quota().burst_size().get(),
-                        remaining_burst_capacity: snapshot.remaining_burst_capacity(),
-                    }))
-                }
+            Ok(key) => self.limit(req, key, key_decision, scope, dialect),
 
-                Err(negative) => {
-                    let wait_time = negative
-                        .wait_time_from(DefaultClock::default().now())
-                        .as_secs();
+            // Extraction failed, stop right now with a HTTP 401 error.
+            Err(e) => future::Either::Left(self.reject(
+                &mut req,
+                extraction_failed(
+                    e,
+                    DecisionId::next(),
+                    self.state.labels.as_ref(),
+                    M::USE_HEADERS,
+                    dialect.as_ref(),
+                ),
+            )),
+        }
+    }
+}
 
-                    #[cfg(feature = "log")]
-                    {
-                        let key_name = match self.key_extractor.key_name(&key) {
-                            Some(n) => format!(" [{}]", &n),
-                            None => "".to_owned(),
-                        };
-                        log::info!(
-                            "Rate limit exceeded for {}{}, quota reset in {}s",
-                            self.key_extractor.name(),
-                            key_name,
-                            &wait_time
-                        );
-                    }
+/// The future of the [`Governor`](crate::Governor) middleware: either the denial
+/// of the request or the response of the wrapped service with the rate limit headers.
+type GovernorFuture<F, B, Key> = future::Either<
+    future::Either<future::Ready<Result<ServiceResponse<B>, actix_web::Error>>, DenialNoteFut<B>>,
+    RateLimitHeaderFut<F, Key>,
+>;
+
+impl<S, B, K, M> GovernorMiddleware<S, K, M>
+where
+    K: KeyExtractor,
+    K::Key: 'static,
+    M: RateLimitInfo,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    /// Check the request of the key against the blocks, limits and quotas
+    /// and call the wrapped service if it is admitted.
+    fn limit(
+        &self,
+        mut req: ServiceRequest,
+        key: K::Key,
+        key_decision: Option<KeyDecision>,
+        scope: Option<ScopePolicy>,
+        dialect: Option<HeaderDialect>,
+    ) -> GovernorFuture<S::Future, B, K::Key> {
+        if let Some(debug_key) = &self.state.debug_key {
+            debug_key.insert(&req, &key);
+        }
+        let key_header = self.key_header(&req);
+        let block = match key_decision {
+            Some(KeyDecision::Blocked(cause)) => Some(cause.into()),
+            // Scheduled blocks of the key, e.g. during its maintenance window.
+            _ => self
+                .key_blocks
+                .as_ref()
+                .and_then(|blocks| blocks.active(&key)),
+        };
+        if let Some(cause) = block {
+            return future::Either::Left(self.reject(
+                &mut req,
+                blocked(
+                    &cause,
+                    DecisionId::next(),
+                    self.state.labels.as_ref(),
+                    M::USE_HEADERS,
+                    dialect.as_ref(),
+                ),
+            ));
+        }
+        if key_decision == Some(KeyDecision::Unlimited) || self.is_exempt(&req, &key) {
+            self.state.captures.record(&key, CaptureState::Exempt);
+            let fut = self.service.call(req);
+            return future::Either::Right(RateLimitHeaderFut {
+                whitelisted: true,
+                key_header,
+                ..RateLimitHeaderFut::new(fut, M::USE_HEADERS, dialect)
+            });
+        }
+
+        let guard = match self.acquire_concurrency(self.state.concurrency.as_ref(), &key) {
+            Ok(guard) => guard,
+            Err((max_concurrency, decision)) => {
+                return future::Either::Left(self.reject(
+                    &mut req,
+                    concurrency_limit_exceeded(
+                        max_concurrency,
+                        decision,
+                        self.state.labels.as_ref(),
+                        M::USE_HEADERS,
+                        dialect.as_ref(),
+                    ),
+                ))
+            }
+        };
+        let stream_guard = match self.acquire_stream(&req, &key) {
+            Ok(stream_guard) => stream_guard,
+            Err((max_streams, decision)) => {
+                return future::Either::Left(self.reject(
+                    &mut req,
+                    concurrency_limit_exceeded(
+                        max_streams,
+                        decision,
+                        self.state.labels.as_ref(),
+                        false,
+                        dialect.as_ref(),
+                    ),
+                ))
+            }
+        };
 
-                    let body = format!("{{\"ok\":false,\"error_code\":429,\"description\":\"Too Many Requests: retry after {wait_time}s\"}}");
-                    let response = actix_web::HttpResponse::TooManyRequests()
-                        .insert_header(("content-type", "application/json"))
-                        .insert_header(("x-ratelimit-after", wait_time))
-                        .insert_header(("x-ratelimit-limit", negative.quota().burst_size().get()))
-                        .insert_header(("x-ratelimit-remaining", 0))
-                        .body(body.clone());
-                    future::Either::Left(future::err(
-                        error::InternalError::from_response(body, response).into(),
-                    ))
+        // Streaming requests are limited by the time they are open instead of their number.
+        let streaming = self
+            .state
+            .streaming
+            .as_ref()
+            .filter(|(classifier, _)| classifier.is_streaming(&req));
+        let (budget, reason) = match streaming {
+            Some((_, budget)) => (Some(budget), "streaming_budget"),
+            None => (self.state.time_budget.as_ref(), "time_budget"),
+        };
+        if let Some((wait_time, decision)) =
+            budget.and_then(|budget| self.check_budget(budget, &key, reason))
+        {
+            return future::Either::Left(self.reject(
+                &mut req,
+                rate_limit_exceeded(
+                    wait_time,
+                    None,
+                    None,
+                    decision,
+                    self.state.labels.as_ref(),
+                    dialect.as_ref(),
+                ),
+            ));
+        }
+        if let Some((_, budget)) = streaming {
+            StreamCharges::add(&req, Box::new(budget.start(&key)));
+            self.state
+                .captures
+                .record(&key, CaptureState::Allowed(None));
+            let fut = self.service.call(req);
+            return future::Either::Right(RateLimitHeaderFut {
+                guard,
+                stream_guard,
+                policy_header: self.policy_header(),
+                key_header,
+                ..RateLimitHeaderFut::new(fut, M::USE_HEADERS, dialect)
+            });
+        }
+
+        let quota = self.resolve_quota(&req, &key, key_decision, scope);
+        let override_limiter = quota.map(|quota| self.state.overrides.limiter(&quota));
+        let limiter = override_limiter.as_ref().unwrap_or(&self.state.limiter);
+        // The key also pays for the costs its handlers raised after earlier requests.
+        let debt = self.state.cost_debts.debt(&key);
+        let cost = RequestCosts::cost(self.state.request_costs.as_ref(), &req);
+        let total_cost = cost.saturating_add(debt);
+
+        let (burst_state, cost_charge) = match self.check_quotas(limiter, &key, quota, total_cost) {
+            Ok(admission) => {
+                let burst_state = admission.burst_state::<M>();
+                self.state.captures.record(
+                    &key,
+                    CaptureState::Allowed(burst_state.map(|(_, remaining)| remaining)),
+                );
+                if debt > 0 {
+                    // Costs above the burst size only use up the whole burst,
+                    // the debt that didn't fit stays for the next request.
+                    let burst_size =
+                        quota.map_or(self.state.policy.burst_size, |quota| quota.burst_size());
+                    let charged = total_cost.get().min(burst_size);
+                    self.state
+                        .cost_debts
+                        .settle(&key, charged.saturating_sub(cost.get()));
+                }
+                let cost_charge = self.state.cost_debts.charge(&key, cost);
+                if let Some(costs) = &self.state.request_costs {
+                    costs.meter_payload(&mut req, &self.state.cost_debts, &key, cost);
                 }
+                (burst_state, Some(cost_charge))
+            }
+
+            // The quota is used up, but it is not enforced for the key (yet)
+            // or the governor only logs the denials.
+            Err(denial) if !self.is_enforced(&key) || self.is_shadowed() => {
+                self.record_shadow_denial(&key, "rate_limited", Some(denial.wait.as_secs()));
+                (Some((denial.burst_size, 0)), None)
+            }
+
+            Err(denial) => return self.deny_rate_limited(req, &key, denial, key_header, dialect),
+        };
+        let refund = self.refund_ticket(&req, &key, quota);
+        let fut = self.service.call(req);
+        future::Either::Right(RateLimitHeaderFut {
+            burst_state,
+            guard,
+            stream_guard,
+            refund,
+            key_headers: self.key_headers(&key),
+            policy_header: self.policy_header(),
+            warning: self.soft_limit_warning(burst_state),
+            latency_charge: self.latency_charge(&key),
+            cost_charge,
+            key_header,
+            ..RateLimitHeaderFut::new(fut, M::USE_HEADERS, dialect)
+        })
+    }
+
+    /// Deny the request because the quota of the key is used up,
+    /// with the note of the key if configured.
+    fn deny_rate_limited(
+        &self,
+        mut req: ServiceRequest,
+        key: &K::Key,
+        denial: QuotaDenial,
+        key_header: Option<HeaderValue>,
+        dialect: Option<HeaderDialect>,
+    ) -> GovernorFuture<S::Future, B, K::Key> {
+        let wait_time = denial.wait.as_secs();
+        let decision = DecisionId::next();
+
+        #[cfg(feature = "log")]
+        if let Some(level) = self.state.denial_log.level() {
+            let key_name = self.log_name(key);
+            log::log!(
+                level,
+                "Rate limit exceeded for {}, quota reset in {}s (decision {})",
+                key_name,
+                &wait_time,
+                decision
+            );
+        }
+        self.state.captures.record(
+            key,
+            CaptureState::Denied {
+                reason: "rate_limited",
+                wait_time: Some(wait_time),
+                decision,
             },
+        );
 
-            // Extraction failed, stop right now with a HTTP 401 error.
-            Err(e) => future::Either::Left(future::err(error::ErrorUnauthorized(e.to_string()))),
+        let burst_size = M::USE_HEADERS.then_some(denial.burst_size);
+        let notes = match &self.state.denial_notes {
+            Some(notes) => notes,
+            None => {
+                return future::Either::Left(self.reject(
+                    &mut req,
+                    rate_limit_exceeded(
+                        wait_time,
+                        burst_size,
+                        None,
+                        decision,
+                        self.state.labels.as_ref(),
+                        dialect.as_ref(),
+                    ),
+                ))
+            }
+        };
+        if let Some(note) = notes.cached(key) {
+            return future::Either::Left(self.reject(
+                &mut req,
+                rate_limit_exceeded(
+                    wait_time,
+                    burst_size,
+                    note.as_deref(),
+                    decision,
+                    self.state.labels.as_ref(),
+                    dialect.as_ref(),
+                ),
+            ));
+        }
+        let labels = self.state.labels.clone();
+        let denial = notes
+            .resolve(key)
+            .map(move |note| {
+                debug_key::apply_to_error(
+                    rate_limit_exceeded(
+                        wait_time,
+                        burst_size,
+                        note.as_deref(),
+                        decision,
+                        labels.as_ref(),
+                        dialect.as_ref(),
+                    ),
+                    key_header,
+                )
+            })
+            .boxed_local();
+        let denial = match self.state.denied_payload {
+            Some(policy) => policy.reject(&mut req, denial),
+            None => denial,
+        };
+        future::Either::Left(future::Either::Right(denial.map(Err)))
+    }
+}
+
+pin_project! {
+    /// Adds the rate limit headers to the response and keeps the request
+    /// registered as in-flight until the response is ready, or until its body ended
+    /// with [`StreamMeter`](crate::StreamMeter).
+    pub struct RateLimitHeaderFut<F, Key>
+    where
+        Key: Clone,
+        Key: std::hash::Hash,
+        Key: Eq,
+    {
+        #[pin]
+        future: F,
+        use_headers: bool,
+        burst_state: Option<(u32, u32)>,
+        whitelisted: bool,
+        guard: Option<InFlightGuard<Key>>,
+        stream_guard: Option<InFlightGuard<Key>>,
+        refund: Option<RefundTicket<Key>>,
+        key_headers: Option<(KeyHeaders<Key>, Key)>,
+        policy_header: Option<HeaderValue>,
+        warning: Option<HeaderValue>,
+        dialect: Option<HeaderDialect>,
+        latency_charge: Option<TimeCharge<Key>>,
+        cost_charge: Option<CostCharge<Key>>,
+        key_header: Option<HeaderValue>,
+    }
+}
+
+impl<F, Key: Clone + std::hash::Hash + Eq> RateLimitHeaderFut<F, Key> {
+    /// Pass the response of `future` through, without any state of the governor.
+    fn new(future: F, use_headers: bool, dialect: Option<HeaderDialect>) -> Self {
+        RateLimitHeaderFut {
+            future,
+            use_headers,
+            burst_state: None,
+            whitelisted: false,
+            guard: None,
+            stream_guard: None,
+            refund: None,
+            key_headers: None,
+            policy_header: None,
+            warning: None,
+            dialect,
+            latency_charge: None,
+            cost_charge: None,
+            key_header: None,
+        }
+    }
+}
+
+impl<F, B, Key> Future for RateLimitHeaderFut<F, Key>
+where
+    F: Future<Output = Result<ServiceResponse<B>, actix_web::Error>>,
+    B: MessageBody,
+    Key: Clone + std::hash::Hash + Eq + 'static,
+{
+    type Output = Result<ServiceResponse<B>, actix_web::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        match this.future.poll(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(response) => {
+                let guard = this.guard.take();
+                // The handler is done, charge its time and the cost it set.
+                this.latency_charge.take();
+                if let (Some(cost_charge), Ok(response)) = (this.cost_charge.take(), &response) {
+                    cost_charge.settle(response);
+                }
+                if let (Some(refund), Ok(response)) = (this.refund.take(), &response) {
+                    refund.settle(response);
+                }
+                Poll::Ready(match response {
+                    Ok(mut response) => {
+                        let headers = response.headers_mut();
+                        if *this.use_headers {
+                            add_rate_limit_headers(
+                                headers,
+                                *this.burst_state,
+                                guard.as_ref(),
+                                this.policy_header.take(),
+                                *this.whitelisted,
+                            );
+                            if let Some(warning) = this.warning.take() {
+                                headers.append(WARNING, warning);
+                            }
+                        }
+                        if let (Some((key_headers, key)), Some((burst_size, remaining))) =
+                            (this.key_headers.take(), this.burst_state)
+                        {
+                            key_headers.apply(
+                                &key,
+                                &KeySnapshot::new(*burst_size, *remaining),
+                                headers,
+                            );
+                        }
+                        debug_key::apply(headers, this.key_header.take());
+                        if let Some(dialect) = this.dialect.take() {
+                            dialect.apply(headers);
+                        }
+                        // Keep the request in-flight until `StreamMeter` sent the body.
+                        if StreamCharges::is_metered(response.request()) {
+                            for guard in [guard, this.stream_guard.take()].into_iter().flatten() {
+                                StreamCharges::add(response.request(), Box::new(guard));
+                            }
+                        }
+                        Ok(response)
+                    }
+                    Err(e) => Err(e),
+                })
+            }
+        }
+    }
+}
+
+/// Add the rate limiting headers to a response.
+///
+/// If several governors wrap the same route, the most restrictive state wins:
+/// the limit and remaining pairs with the fewest remaining requests are kept,
+/// the policies are listed one after another and the request only counts as
+/// whitelisted if no governor limited it.
+fn add_rate_limit_headers<Key: Clone + std::hash::Hash + Eq>(
+    headers: &mut HeaderMap,
+    burst_state: Option<(u32, u32)>,
+    guard: Option<&InFlightGuard<Key>>,
+    policy_header: Option<HeaderValue>,
+    whitelisted: bool,
+) {
+    if let Some((burst_size, remaining_burst_capacity)) = burst_state {
+        insert_most_restrictive(
+            headers,
+            LIMIT,
+            REMAINING,
+            burst_size,
+            remaining_burst_capacity,
+        );
+        headers.remove(WHITELISTED);
+    }
+    if let Some(guard) = guard {
+        insert_most_restrictive(
+            headers,
+            CONCURRENCY_LIMIT,
+            CONCURRENCY_REMAINING,
+            guard.max_concurrency(),
+            guard.remaining(),
+        );
+    }
+    if let Some(policy_header) = policy_header {
+        headers.append(POLICY, policy_header);
+    }
+    if whitelisted && !headers.contains_key(LIMIT) {
+        headers.insert(WHITELISTED, HeaderValue::from_static("true"));
+    }
+}
+
+/// Insert a limit and remaining pair, unless a more restrictive one was already set.
+fn insert_most_restrictive(
+    headers: &mut HeaderMap,
+    limit_name: HeaderName,
+    remaining_name: HeaderName,
+    limit: u32,
+    remaining: u32,
+) {
+    let get = |name: &HeaderName| -> Option<u32> { headers.get(name)?.to_str().ok()?.parse().ok() };
+    let more_restrictive = match (get(&limit_name), get(&remaining_name)) {
+        (Some(existing_limit), Some(existing_remaining)) => {
+            (remaining, limit) < (existing_remaining, existing_limit)
         }
+        _ => true,
+    };
+    if more_restrictive {
+        headers.insert(limit_name, number_value(limit.into()));
+        headers.insert(remaining_name, number_value(remaining.into()));
     }
 }
@@ -0,0 +1,64 @@
+use crate::{GovernorConfigBuilder, Method, PeerIpKeyExtractor};
+
+use governor::middleware::{NoOpMiddleware, StateInformationMiddleware};
+use serde::{de, Deserialize, Deserializer};
+
+/// The settings of the configuration builder that can be read from a configuration file.
+/// The names match the fields of [`GovernorPolicy::to_json`](crate::GovernorPolicy::to_json).
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Settings {
+    period_ms: Option<u64>,
+    burst_size: Option<u32>,
+    methods: Option<Vec<String>>,
+    headers: Option<bool>,
+}
+
+impl Settings {
+    fn into_builder<E: de::Error>(
+        self,
+        use_headers: bool,
+    ) -> Result<GovernorConfigBuilder<PeerIpKeyExtractor, NoOpMiddleware>, E> {
+        if self.headers.is_some_and(|headers| headers != use_headers) {
+            return Err(E::custom(if use_headers {
+                "`headers` can't be disabled for a builder with StateInformationMiddleware"
+            } else {
+                "`headers` needs a builder with StateInformationMiddleware"
+            }));
+        }
+        let mut builder = GovernorConfigBuilder::default();
+        if let Some(period_ms) = self.period_ms {
+            builder.per_millisecond(period_ms);
+        }
+        if let Some(burst_size) = self.burst_size {
+            builder.burst_size(burst_size);
+        }
+        if let Some(methods) = self.methods {
+            let methods = methods
+                .iter()
+                .map(|method| {
+                    Method::from_bytes(method.to_uppercase().as_bytes())
+                        .map_err(|_| E::custom(format!("invalid HTTP method: {method}")))
+                })
+                .collect::<Result<_, _>>()?;
+            builder.methods(methods);
+        }
+        Ok(builder)
+    }
+}
+
+impl<'de> Deserialize<'de> for GovernorConfigBuilder<PeerIpKeyExtractor, NoOpMiddleware> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Settings::deserialize(deserializer)?.into_builder(false)
+    }
+}
+
+impl<'de> Deserialize<'de>
+    for GovernorConfigBuilder<PeerIpKeyExtractor, StateInformationMiddleware>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Settings::deserialize(deserializer)?
+            .into_builder(true)?
+            .use_headers())
+    }
+}
@@ -0,0 +1,495 @@
+use actix_web::body::{BodySize, BoxBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, COOKIE, VARY};
+use actix_web::http::{Method, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{error, Error, HttpMessage, HttpRequest, HttpResponse};
+use futures::channel::oneshot;
+use futures::future::{self, FutureExt, LocalBoxFuture, Shared};
+use governor::clock::QuantaInstant;
+use governor::middleware::RateLimitingMiddleware;
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::{DenialReason, GovernorConfig, KeyExtractor};
+
+/// Fingerprint of a request: rate limiting key, method, normalized path with query
+/// and the credentials of the request.
+///
+/// Several users can share a key, e.g. behind a NAT, so only requests with the same
+/// `Authorization` and `Cookie` headers get the same response.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Fingerprint<Key> {
+    key: Key,
+    method: Method,
+    path: String,
+    authorization: Option<HeaderValue>,
+    cookies: Vec<HeaderValue>,
+}
+
+impl<Key> Fingerprint<Key> {
+    fn new(key: Key, req: &ServiceRequest) -> Self {
+        Fingerprint {
+            key,
+            method: req.method().clone(),
+            path: normalize(req.path(), req.query_string()),
+            authorization: req.headers().get(AUTHORIZATION).cloned(),
+            cookies: req.headers().get_all(COOKIE).cloned().collect(),
+        }
+    }
+}
+
+/// The key [`Singleflight`] extracted for the governor of its configuration,
+/// so the key extractor runs only once per request.
+pub(crate) struct ExtractedKey<Key> {
+    config: usize,
+    key: Key,
+}
+
+/// Identifies the configuration by the address of its state, which the governors share.
+fn config_id<T>(state: &Arc<T>) -> usize {
+    Arc::as_ptr(state) as usize
+}
+
+impl<Key: 'static> ExtractedKey<Key> {
+    /// Take the key out of the request extensions, if it was extracted for the governors
+    /// with this state.
+    pub(crate) fn take<T>(req: &ServiceRequest, state: &Arc<T>) -> Option<Key> {
+        let mut extensions = req.extensions_mut();
+        if extensions.get::<ExtractedKey<Key>>()?.config != config_id(state) {
+            return None;
+        }
+        extensions
+            .remove::<ExtractedKey<Key>>()
+            .map(|extracted| extracted.key)
+    }
+}
+
+/// Response parts that can be handed out to every coalesced request.
+#[derive(Clone)]
+struct SharedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    denial_reason: Option<DenialReason>,
+    /// The request headers named by the `Vary` header of the response and their values.
+    vary: Vec<(HeaderName, Option<HeaderValue>)>,
+}
+
+impl SharedResponse {
+    /// Buffer the response body, so it can be shared.
+    ///
+    /// Streamed bodies, like server-sent events, and responses with `Vary: *`
+    /// are given back, they can't be shared.
+    async fn buffer(
+        response: HttpResponse,
+        request_headers: &HeaderMap,
+    ) -> Result<Self, HttpResponse> {
+        let vary = match vary(response.headers(), request_headers) {
+            Some(vary) if !matches!(response.body().size(), BodySize::Stream) => vary,
+            _ => return Err(response),
+        };
+        let status = response.status();
+        let headers = response.headers().clone();
+        let denial_reason = response.extensions().get::<DenialReason>().copied();
+        Ok(
+            match actix_web::body::to_bytes(response.into_body()).await {
+                Ok(body) => SharedResponse {
+                    status,
+                    headers,
+                    body,
+                    denial_reason,
+                    vary,
+                },
+                Err(_) => SharedResponse {
+                    status: StatusCode::INTERNAL_SERVER_ERROR,
+                    headers: HeaderMap::new(),
+                    body: Bytes::new(),
+                    denial_reason,
+                    vary,
+                },
+            },
+        )
+    }
+
+    /// Whether the response can be shared with a request with these headers.
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| headers.get(name) == value.as_ref())
+    }
+
+    fn into_response(self) -> HttpResponse {
+        let mut response = HttpResponse::with_body(self.status, self.body);
+        *response.headers_mut() = self.headers;
+        if let Some(denial_reason) = self.denial_reason {
+            response.extensions_mut().insert(denial_reason);
+        }
+        response.map_into_boxed_body()
+    }
+
+    fn into_service_response(self, req: HttpRequest) -> ServiceResponse<BoxBody> {
+        ServiceResponse::new(req, self.into_response())
+    }
+}
+
+/// The response of a request in flight and the number of identical requests waiting for it.
+#[derive(Clone)]
+struct InFlight {
+    response: Shared<oneshot::Receiver<SharedResponse>>,
+    waiters: Rc<Cell<usize>>,
+}
+
+/// Request coalescing middleware factory.
+///
+/// While a `GET` or `HEAD` request is in flight, identical requests
+/// (same rate limiting key, method, normalized path and query) are not forwarded
+/// but wait for the response of the first request, which is then shared with all of them.
+/// This reduces duplicate load from clients that aggressively retry requests
+/// and keeps the retries from consuming quota.
+///
+/// The middleware has to wrap the [`Governor`](crate::Governor) middleware,
+/// i.e. it must be registered **after** it:
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfigBuilder, Singleflight};
+/// use actix_web::App;
+///
+/// let config = GovernorConfigBuilder::default()
+///     .use_headers()
+///     .finish()
+///     .unwrap();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&config))
+///     .wrap(Singleflight::new(&config).near_limit(2));
+/// ```
+///
+/// Requests with different `Authorization` or `Cookie` headers are never coalesced,
+/// and a response is only shared with requests that match the headers named by its
+/// `Vary` header. A response is only buffered in memory if identical requests are waiting
+/// for it, streamed responses like server-sent events are never shared.
+/// Requests are only coalesced per worker thread.
+///
+/// The key is extracted once per request, the governor of the same configuration
+/// reuses it, so stateful key extractors see every request only once.
+pub struct Singleflight<K: KeyExtractor> {
+    key_extractor: K,
+    config: usize,
+    near_limit: Option<u32>,
+}
+
+impl<K: KeyExtractor> Singleflight<K> {
+    /// Create new request coalescing middleware factory that uses
+    /// the key extractor of the configuration.
+    pub fn new<M: RateLimitingMiddleware<QuantaInstant>>(config: &GovernorConfig<K, M>) -> Self {
+        Singleflight {
+            key_extractor: config.state.key_extractor.clone(),
+            config: config_id(&config.state),
+            near_limit: None,
+        }
+    }
+
+    /// Only coalesce requests of keys that have at most `remaining` requests left.
+    ///
+    /// The remaining requests are taken from the `x-ratelimit-remaining` header,
+    /// so the wrapped [`Governor`](crate::Governor) has to be configured with
+    /// [`use_headers`](crate::GovernorConfigBuilder::use_headers()).
+    /// By default identical requests are always coalesced.
+    pub fn near_limit(mut self, remaining: u32) -> Self {
+        self.near_limit = Some(remaining);
+        self
+    }
+}
+
+impl<S, B, K> Transform<S, ServiceRequest> for Singleflight<K>
+where
+    K: KeyExtractor + 'static,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Transform = SingleflightMiddleware<S, K>;
+    type InitError = ();
+    type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(SingleflightMiddleware {
+            service: Rc::new(service),
+            key_extractor: self.key_extractor.clone(),
+            config: self.config,
+            near_limit: self.near_limit,
+            in_flight: Rc::new(RefCell::new(HashMap::new())),
+            remaining: Rc::new(RefCell::new(HashMap::new())),
+        })
+    }
+}
+
+pub struct SingleflightMiddleware<S, K: KeyExtractor> {
+    service: Rc<S>,
+    key_extractor: K,
+    config: usize,
+    near_limit: Option<u32>,
+    in_flight: Rc<RefCell<HashMap<Fingerprint<K::Key>, InFlight>>>,
+    /// Last known remaining requests of keys that are near their limit.
+    remaining: Rc<RefCell<HashMap<K::Key, u32>>>,
+}
+
+impl<S, K: KeyExtractor> SingleflightMiddleware<S, K> {
+    fn is_near_limit(&self, key: &K::Key) -> bool {
+        match self.near_limit {
+            Some(threshold) => self
+                .remaining
+                .borrow()
+                .get(key)
+                .is_some_and(|remaining| *remaining <= threshold),
+            None => true,
+        }
+    }
+}
+
+impl<S, B, K> Service<ServiceRequest> for SingleflightMiddleware<S, K>
+where
+    K: KeyExtractor + 'static,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<BoxBody>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        // The governor reuses the key instead of running the extractor again.
+        let key = self.key_extractor.extract(&req).ok().inspect(|key| {
+            req.extensions_mut().insert(ExtractedKey {
+                config: self.config,
+                key: key.clone(),
+            });
+        });
+        let key = match key {
+            Some(key) if req.method() == Method::GET || req.method() == Method::HEAD => key,
+            // Only requests without side effects can be coalesced.
+            _ => {
+                return self
+                    .service
+                    .call(req)
+                    .map(|res| res.map(ServiceResponse::map_into_boxed_body))
+                    .boxed_local()
+            }
+        };
+
+        let fingerprint = Fingerprint::new(key.clone(), &req);
+
+        if self.is_near_limit(&key) {
+            if let Some(in_flight) = self.in_flight.borrow().get(&fingerprint).cloned() {
+                // An identical request is already in flight, share its response.
+                in_flight.waiters.set(in_flight.waiters.get() + 1);
+                let service = self.service.clone();
+                return async move {
+                    match in_flight.response.await {
+                        Ok(shared) if shared.matches(req.headers()) => {
+                            Ok(shared.into_service_response(req.into_parts().0))
+                        }
+                        // The first request was dropped or its response can't be shared,
+                        // so this one has to be executed after all.
+                        _ => service
+                            .call(req)
+                            .await
+                            .map(ServiceResponse::map_into_boxed_body),
+                    }
+                }
+                .boxed_local();
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            let waiters = Rc::new(Cell::new(0));
+            self.in_flight.borrow_mut().insert(
+                fingerprint.clone(),
+                InFlight {
+                    response: receiver.shared(),
+                    waiters: waiters.clone(),
+                },
+            );
+            let guard = InFlightGuard {
+                in_flight: self.in_flight.clone(),
+                fingerprint,
+            };
+
+            // Errors don't carry the request, keep its headers to match the `Vary` header.
+            let request_headers = req.headers().clone();
+            let fut = self.service.call(req);
+            let remaining = self.remaining.clone();
+            let near_limit = self.near_limit;
+            async move {
+                let result = fut.await;
+                // No more requests can join once the entry is removed.
+                drop(guard);
+                let waiting = waiters.get() > 0;
+                match result {
+                    Ok(res) => {
+                        record_remaining(
+                            &remaining,
+                            near_limit,
+                            key,
+                            remaining_from_headers(res.headers()),
+                        );
+                        if !waiting {
+                            return Ok(res.map_into_boxed_body());
+                        }
+                        let (req, response) = res.into_parts();
+                        match SharedResponse::buffer(response.map_into_boxed_body(), req.headers())
+                            .await
+                        {
+                            Ok(shared) => {
+                                let _ = sender.send(shared.clone());
+                                Ok(shared.into_service_response(req))
+                            }
+                            // The waiting requests are executed on their own.
+                            Err(response) => Ok(ServiceResponse::new(req, response)),
+                        }
+                    }
+                    Err(err) => {
+                        let response = err.error_response();
+                        record_remaining(
+                            &remaining,
+                            near_limit,
+                            key,
+                            remaining_from_headers(response.headers()),
+                        );
+                        if !waiting {
+                            return Err(err);
+                        }
+                        // The response of an error can only be taken once, so it has to be replaced.
+                        match SharedResponse::buffer(response, &request_headers).await {
+                            Ok(shared) => {
+                                let _ = sender.send(shared.clone());
+                                Err(error::InternalError::from_response(
+                                    err,
+                                    shared.into_response(),
+                                )
+                                .into())
+                            }
+                            Err(_) => Err(err),
+                        }
+                    }
+                }
+            }
+            .boxed_local()
+        } else {
+            let fut = self.service.call(req);
+            let remaining = self.remaining.clone();
+            let near_limit = self.near_limit;
+            async move {
+                match fut.await {
+                    Ok(res) => {
+                        record_remaining(
+                            &remaining,
+                            near_limit,
+                            key,
+                            remaining_from_headers(res.headers()),
+                        );
+                        Ok(res.map_into_boxed_body())
+                    }
+                    Err(err) => {
+                        // Denied requests have no quota left.
+                        if err.as_response_error().status_code() == StatusCode::TOO_MANY_REQUESTS {
+                            record_remaining(&remaining, near_limit, key, Some(0));
+                        }
+                        Err(err)
+                    }
+                }
+            }
+            .boxed_local()
+        }
+    }
+}
+
+/// Removes the in-flight entry once the first request finished or was dropped.
+struct InFlightGuard<Key: Hash + Eq> {
+    in_flight: Rc<RefCell<HashMap<Fingerprint<Key>, InFlight>>>,
+    fingerprint: Fingerprint<Key>,
+}
+
+impl<Key: Hash + Eq> Drop for InFlightGuard<Key> {
+    fn drop(&mut self) {
+        self.in_flight.borrow_mut().remove(&self.fingerprint);
+    }
+}
+
+fn remaining_from_headers(headers: &HeaderMap) -> Option<u32> {
+    headers
+        .get(HeaderName::from_static("x-ratelimit-remaining"))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u32>().ok())
+}
+
+/// Remember the remaining requests of keys that are near their limit.
+fn record_remaining<Key: Hash + Eq>(
+    remaining: &RefCell<HashMap<Key, u32>>,
+    near_limit: Option<u32>,
+    key: Key,
+    value: Option<u32>,
+) {
+    let threshold = match near_limit {
+        Some(threshold) => threshold,
+        None => return,
+    };
+
+    match value {
+        Some(value) if value <= threshold => {
+            remaining.borrow_mut().insert(key, value);
+        }
+        Some(_) => {
+            remaining.borrow_mut().remove(&key);
+        }
+        None => {}
+    }
+}
+
+/// The request headers named by the `Vary` header of the response and their values,
+/// or `None` for `Vary: *`.
+fn vary(
+    response_headers: &HeaderMap,
+    request_headers: &HeaderMap,
+) -> Option<Vec<(HeaderName, Option<HeaderValue>)>> {
+    let mut vary = Vec::new();
+    for value in response_headers.get_all(VARY) {
+        for name in value.to_str().unwrap_or("*").split(',').map(str::trim) {
+            if name == "*" {
+                return None;
+            }
+            if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+                let value = request_headers.get(&name).cloned();
+                vary.push((name, value));
+            }
+        }
+    }
+    Some(vary)
+}
+
+/// Collapse repeated slashes and strip the trailing slash of the path.
+fn normalize(path: &str, query: &str) -> String {
+    let mut normalized = String::with_capacity(path.len() + query.len() + 1);
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if normalized.is_empty() {
+        normalized.push('/');
+    }
+    if !query.is_empty() {
+        normalized.push('?');
+        normalized.push_str(query);
+    }
+    normalized
+}
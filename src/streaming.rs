@@ -0,0 +1,241 @@
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::web::Bytes;
+use actix_web::{Error, HttpMessage};
+use futures::future;
+use pin_project_lite::pin_project;
+
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+type ClassifyFn = dyn Fn(&ServiceRequest) -> bool + Send + Sync;
+
+/// Closure that decides whether a request is streaming.
+#[derive(Clone)]
+pub(crate) struct StreamClassifier(Arc<ClassifyFn>);
+
+impl StreamClassifier {
+    pub(crate) fn new<F>(f: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> bool + Send + Sync + 'static,
+    {
+        StreamClassifier(Arc::new(f))
+    }
+
+    pub(crate) fn is_streaming(&self, req: &ServiceRequest) -> bool {
+        (self.0)(req)
+    }
+}
+
+impl PartialEq for StreamClassifier {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for StreamClassifier {}
+
+impl fmt::Debug for StreamClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("StreamClassifier")
+    }
+}
+
+/// Streaming settings as stored by the configuration builder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct StreamingQuota {
+    pub(crate) classifier: StreamClassifier,
+    pub(crate) budget: Duration,
+    pub(crate) period: Duration,
+}
+
+/// Charges of the streaming budgets of the governors that admitted a request,
+/// and the guards that keep it in-flight.
+///
+/// They are kept in the request extensions until [`StreamMeter`] moves them into the
+/// response body, so every governor of a stack charges its own budget.
+pub(crate) struct StreamCharges(Vec<Box<dyn Any>>);
+
+impl StreamCharges {
+    pub(crate) fn add(req: &impl HttpMessage, charge: Box<dyn Any>) {
+        let mut extensions = req.extensions_mut();
+        match extensions.get_mut::<StreamCharges>() {
+            Some(charges) => charges.0.push(charge),
+            None => {
+                extensions.insert(StreamCharges(vec![charge]));
+            }
+        }
+    }
+    /// Whether [`StreamMeter`] wraps the request and keeps its charges until the body ended.
+    pub(crate) fn is_metered(req: &impl HttpMessage) -> bool {
+        req.extensions().contains::<StreamCharges>()
+    }
+}
+
+/// Middleware that charges streaming requests for as long as their response body is open.
+///
+/// The governor admits the requests marked by
+/// [`streaming_budget`](crate::GovernorConfigBuilder::streaming_budget()), but it can't see
+/// when their stream ends. Wrap the app or scope with `StreamMeter` as well, so the time is
+/// charged when the body ends or the client disconnects:
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfigBuilder, StreamMeter};
+/// use actix_web::App;
+/// use std::time::Duration;
+///
+/// let config = GovernorConfigBuilder::default()
+///     .streaming_budget(
+///         |req| req.path().starts_with("/events"),
+///         Duration::from_secs(10 * 60),
+///         Duration::from_secs(60 * 60),
+///     )
+///     .finish()
+///     .unwrap();
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&config))
+///     .wrap(StreamMeter);
+/// ```
+///
+/// Its responses have a [`MeteredBody`]. Without it, streams are only charged until
+/// the handler returned the response. It also keeps requests counted by
+/// [`max_concurrency`](crate::GovernorConfigBuilder::max_concurrency()) and
+/// [`max_concurrent_streams`](crate::GovernorConfigBuilder::max_concurrent_streams())
+/// in-flight until their body ends.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StreamMeter;
+
+impl<S, B> Transform<S, ServiceRequest> for StreamMeter
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<MeteredBody<B>>;
+    type Error = Error;
+    type Transform = StreamMeterMiddleware<S>;
+    type InitError = ();
+    type Future = future::Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        future::ok(StreamMeterMiddleware { service })
+    }
+}
+
+pub struct StreamMeterMiddleware<S> {
+    service: S,
+}
+
+impl<S, B> Service<ServiceRequest> for StreamMeterMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    B: MessageBody,
+{
+    type Response = ServiceResponse<MeteredBody<B>>;
+    type Error = Error;
+    type Future = MeteredResponseFut<S::Future>;
+
+    actix_web::dev::forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        req.extensions_mut().insert(StreamCharges(Vec::new()));
+        MeteredResponseFut {
+            future: self.service.call(req),
+        }
+    }
+}
+
+pin_project! {
+    pub struct MeteredResponseFut<F> {
+        #[pin]
+        future: F,
+    }
+}
+
+impl<F, B> Future for MeteredResponseFut<F>
+where
+    F: Future<Output = Result<ServiceResponse<B>, Error>>,
+{
+    type Output = Result<ServiceResponse<MeteredBody<B>>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let response = match self.project().future.poll(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(response) => response?,
+        };
+        let charges = response
+            .request()
+            .extensions_mut()
+            .remove::<StreamCharges>()
+            .filter(|charges| !charges.0.is_empty());
+        Poll::Ready(Ok(
+            response.map_body(|_, body| MeteredBody::new(body, charges))
+        ))
+    }
+}
+
+pin_project! {
+    /// Body of the responses of the [`StreamMeter`] middleware.
+    ///
+    /// It passes the body of the wrapped service through. For streaming requests it also
+    /// measures how long the stream is open, until it ends or the client disconnects.
+    pub struct MeteredBody<B> {
+        #[pin]
+        body: B,
+        // Charges the time to the streaming budgets of the key when dropped.
+        charge: Option<StreamCharges>,
+    }
+}
+
+impl<B> MeteredBody<B> {
+    fn new(body: B, charge: Option<StreamCharges>) -> Self {
+        MeteredBody { body, charge }
+    }
+}
+
+impl<B: fmt::Debug> fmt::Debug for MeteredBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeteredBody")
+            .field("body", &self.body)
+            .field("metered", &self.charge.is_some())
+            .finish()
+    }
+}
+
+impl<B: MessageBody> MessageBody for MeteredBody<B> {
+    type Error = B::Error;
+
+    fn size(&self) -> BodySize {
+        self.body.size()
+    }
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Bytes, Self::Error>>> {
+        let this = self.project();
+        let poll = this.body.poll_next(cx);
+        if matches!(poll, Poll::Ready(None) | Poll::Ready(Some(Err(_)))) {
+            // The stream ended, charge it right away.
+            this.charge.take();
+        }
+        poll
+    }
+
+    fn try_into_bytes(self) -> Result<Bytes, Self> {
+        match self.charge {
+            Some(_) => Err(self),
+            None => self
+                .body
+                .try_into_bytes()
+                .map_err(|body| MeteredBody::new(body, None)),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Turns rate limiting of a [`Governor`](crate::Governor) on and off at runtime.
+///
+/// actix-web's [`Condition`](actix_web::middleware::Condition) decides once, when the app is
+/// built, whether a middleware is used. A switch instead is checked on every request,
+/// so rate limiting can be toggled without rebuilding the app and without changing
+/// the type of the middleware.
+///
+/// All clones of a switch share the same state.
+/// While the switch is off, requests are passed through without any rate limiting headers.
+///
+/// ```rust
+/// use actix_governor::{Governor, GovernorConfig, GovernorSwitch};
+/// use actix_web::{web, App, Responder};
+///
+/// async fn index() -> impl Responder {
+///     "Hello world!"
+/// }
+///
+/// let config = GovernorConfig::default();
+/// let switch = GovernorSwitch::new(true);
+///
+/// let app = App::new()
+///     .wrap(Governor::new(&config).with_switch(switch.clone()))
+///     .route("/", web::get().to(index));
+///
+/// // Later, for example from an admin endpoint
+/// switch.disable();
+/// ```
+#[derive(Debug, Clone)]
+pub struct GovernorSwitch(Arc<AtomicBool>);
+
+impl GovernorSwitch {
+    /// Create a new switch that starts enabled or disabled.
+    pub fn new(enabled: bool) -> Self {
+        GovernorSwitch(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    /// Turn rate limiting on.
+    pub fn enable(&self) {
+        self.set(true);
+    }
+
+    /// Turn rate limiting off.
+    pub fn disable(&self) {
+        self.set(false);
+    }
+
+    /// Turn rate limiting on or off.
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Whether rate limiting is on.
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for GovernorSwitch {
+    /// An enabled switch.
+    fn default() -> Self {
+        GovernorSwitch::new(true)
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Budget of time per key, like 30 seconds of handler time per minute
+/// or ten minutes of open streams per hour.
+///
+/// Every key has a debt of time it used. The debt is paid off continuously at the rate of
+/// `budget` per `period`, and a key is admitted as long as its debt is below the budget.
+/// Time is charged after it was used, so a single admitted request can exceed the budget,
+/// the following requests of the key then wait until the debt is paid off.
+#[derive(Debug)]
+pub(crate) struct TimeBudget<Key: Clone + Hash + Eq> {
+    budget: Duration,
+    /// Seconds of budget that are paid off per second.
+    rate: f64,
+    debts: Mutex<HashMap<Key, (Duration, Instant)>>,
+}
+
+impl<Key: Clone + Hash + Eq> TimeBudget<Key> {
+    /// Returns `None` if the budget or the period are zero.
+    pub(crate) fn new(budget: Duration, period: Duration) -> Option<Self> {
+        if budget.is_zero() || period.is_zero() {
+            return None;
+        }
+        Some(TimeBudget {
+            budget,
+            rate: budget.as_secs_f64() / period.as_secs_f64(),
+            debts: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Check whether the key has budget left.
+    /// Returns the time until the key is admitted again if it hasn't.
+    pub(crate) fn check(&self, key: &Key) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut debts = self.debts.lock().unwrap();
+        let debt = match debts.get(key) {
+            Some(&(debt, updated)) => self.paid_off(debt, updated, now),
+            None => return Ok(()),
+        };
+        if debt.is_zero() {
+            debts.remove(key);
+            return Ok(());
+        }
+        debts.insert(key.clone(), (debt, now));
+        if debt < self.budget {
+            Ok(())
+        } else {
+            let excess = debt - self.budget + Duration::from_millis(1);
+            Err(excess.div_f64(self.rate))
+        }
+    }
+
+    /// Start measuring the time of the key. It is charged when the returned charge is dropped.
+    pub(crate) fn start(self: &Arc<Self>, key: &Key) -> TimeCharge<Key> {
+        TimeCharge {
+            budget: self.clone(),
+            key: key.clone(),
+            start: Instant::now(),
+        }
+    }
+
+    fn charge(&self, key: &Key, used: Duration) {
+        let now = Instant::now();
+        let mut debts = self.debts.lock().unwrap();
+        let debt = match debts.get(key) {
+            Some(&(debt, updated)) => self.paid_off(debt, updated, now),
+            None => Duration::ZERO,
+        };
+        debts.insert(key.clone(), (debt + used, now));
+    }
+
+    /// The debt after paying off since it was updated.
+    fn paid_off(&self, debt: Duration, updated: Instant, now: Instant) -> Duration {
+        debt.saturating_sub(now.duration_since(updated).mul_f64(self.rate))
+    }
+}
+
+/// Charges the time since it was created to a [`TimeBudget`] when it is dropped.
+#[derive(Debug)]
+pub(crate) struct TimeCharge<Key: Clone + Hash + Eq> {
+    budget: Arc<TimeBudget<Key>>,
+    key: Key,
+    start: Instant,
+}
+
+impl<Key: Clone + Hash + Eq> Drop for TimeCharge<Key> {
+    fn drop(&mut self) {
+        self.budget.charge(&self.key, self.start.elapsed());
+    }
+}